@@ -0,0 +1,1039 @@
+//! Actions sent by the client, and the undo/redo history of a project.
+//!
+//! Every action is recorded as a pack of `UndoAction`s. Undoing a pack produces the pack
+//! that reverses it, and that goes into the redo stack, and vice versa.
+
+use std::ops::ControlFlow;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+
+use cgmath::{Angle, Rad, Zero};
+use fxhash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
+
+use crate::api_error::{ApiError, ApiResult, ErrorCode};
+use crate::paper::{
+    CutLayout, EdgeIndex, EdgeStatus, EdgeToggleFlapAction, FaceIndex, FlapSide, IslandKey,
+    JoinResult, PaperOptions, Papercraft,
+};
+use crate::pdf_font::TrueTypeFont;
+use crate::util_3d::Vector2;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Action {
+    ToggleFlap { edge: EdgeIndex, action: EdgeToggleFlapAction },
+    Cut { edge: EdgeIndex, offset: Option<f32> },
+    Join { edge: EdgeIndex, priority_face: Option<FaceIndex> },
+    MoveIsland { island: IslandKey, delta: [f32; 2] },
+    RotateIsland { island: IslandKey, angle: f32, center: [f32; 2] },
+    LockIsland { island: IslandKey, locked: bool },
+    SetOptions { options: Box<PaperOptions>, relocate_pieces: bool },
+    PackIslands,
+    AutoUnfold,
+    /// Joins the edge and goes on along the strip of quads it is part of
+    JoinStrip { edge: EdgeIndex },
+    /// Rotates the island to the angle that makes it shortest, as packing does
+    AutoRotateIsland { island: IslandKey },
+    /// Rotates the island back to the natural orientation of its root face
+    ResetIslandRotation { island: IslandKey },
+    /// Makes `face` the root of the island, without moving it
+    SetIslandRoot { island: IslandKey, face: FaceIndex },
+}
+
+/// An island that disappeared in a join, and the island it is part of now
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
+pub struct IslandRename {
+    pub from: IslandKey,
+    pub to: IslandKey,
+}
+
+pub enum UndoAction {
+    IslandMove {
+        i_root: FaceIndex,
+        prev_rot: Rad<f32>,
+        prev_loc: Vector2,
+    },
+    IslandLock {
+        i_root: FaceIndex,
+        locked: bool,
+    },
+    FlapToggle {
+        i_edge: EdgeIndex,
+        flap_side: FlapSide,
+    },
+    EdgeCut {
+        i_edge: EdgeIndex,
+    },
+    EdgeJoin {
+        join_result: JoinResult,
+        // The key of the island removed by the join
+        i_removed: IslandKey,
+    },
+    DocConfig {
+        options: Box<PaperOptions>,
+        island_pos: FxHashMap<FaceIndex, (Rad<f32>, Vector2)>,
+    },
+    Layout {
+        layout: Box<CutLayout>,
+    },
+}
+
+impl Action {
+    /// Checks the indices and values of the action against the current model.
+    ///
+    /// The client may send a stale or wrong index, that must be rejected before `apply`, which
+    /// would panic with it.
+    pub fn check(&self, papercraft: &Papercraft) -> ApiResult<()> {
+        match self {
+            Action::ToggleFlap { edge, .. } => check_edge(papercraft, *edge),
+            Action::Cut { edge, offset } => {
+                check_edge(papercraft, *edge)?;
+                check_finite("offset", offset.iter().copied())
+            }
+            Action::Join {
+                edge,
+                priority_face,
+            } => {
+                check_edge(papercraft, *edge)?;
+                match priority_face {
+                    Some(face) => check_face(papercraft, *face),
+                    None => Ok(()),
+                }
+            }
+            Action::MoveIsland { island, delta } => {
+                check_island(papercraft, *island)?;
+                check_finite("delta", *delta)
+            }
+            Action::RotateIsland {
+                island,
+                angle,
+                center,
+            } => {
+                check_island(papercraft, *island)?;
+                check_finite("angle", [*angle])?;
+                check_finite("center", *center)
+            }
+            Action::LockIsland { island, .. } => check_island(papercraft, *island),
+            Action::SetOptions { options, .. } => check_options(options),
+            Action::PackIslands | Action::AutoUnfold => Ok(()),
+            Action::JoinStrip { edge } => check_edge(papercraft, *edge),
+            Action::AutoRotateIsland { island } | Action::ResetIslandRotation { island } => {
+                check_island(papercraft, *island)
+            }
+            Action::SetIslandRoot { island, face } => {
+                check_island(papercraft, *island)?;
+                check_face(papercraft, *face)?;
+                let i = papercraft.island_by_key(*island).unwrap();
+                if !papercraft.contains_face(i, *face) {
+                    return Err(ApiError::new(
+                        ErrorCode::InvalidFace,
+                        format!("Face {} is not in the island", usize::from(*face)),
+                    ));
+                }
+                Ok(())
+            }
+        }
+    }
+    /// If the action changes many islands or edges at once, so that a panic may leave the
+    /// papercraft half changed. See `catch_panic()`.
+    pub fn is_bulk(&self) -> bool {
+        matches!(
+            self,
+            Action::SetOptions { .. }
+                | Action::PackIslands
+                | Action::AutoUnfold
+                | Action::JoinStrip { .. }
+        )
+    }
+    /// If the action may take seconds with a big model.
+    pub fn is_slow(&self) -> bool {
+        matches!(self, Action::PackIslands | Action::AutoUnfold)
+    }
+    /// Applies the action to the papercraft, returning what is needed to undo it.
+    ///
+    /// An empty pack means that the action did nothing.
+    pub fn apply(self, papercraft: &mut Papercraft) -> Vec<UndoAction> {
+        match self {
+            Action::ToggleFlap { edge, action } => papercraft
+                .edge_toggle_flap(edge, action)
+                .map(|flap_side| UndoAction::FlapToggle {
+                    i_edge: edge,
+                    flap_side,
+                })
+                .into_iter()
+                .collect(),
+            Action::Cut { edge, offset } => {
+                if papercraft.edge_status(edge) != EdgeStatus::Joined {
+                    return Vec::new();
+                }
+                // Cutting with an offset may move the original island, too
+                let (i_face, _) = papercraft.model()[edge].faces();
+                let i_island = papercraft.island_by_face(i_face);
+                let undo_move = island_move(papercraft, i_island);
+                papercraft.edge_cut(edge, offset);
+                undo_move
+                    .into_iter()
+                    .chain([UndoAction::EdgeCut { i_edge: edge }])
+                    .collect()
+            }
+            Action::Join {
+                edge,
+                priority_face,
+            } => join_undo(papercraft.edge_join(edge, priority_face)),
+            Action::MoveIsland { island, delta } => {
+                let undo = island_move(papercraft, island);
+                if let Some(island) = papercraft.island_by_key_mut(island) {
+                    island.translate(Vector2::new(delta[0], delta[1]));
+                }
+                undo.into_iter().collect()
+            }
+            Action::RotateIsland {
+                island,
+                angle,
+                center,
+            } => {
+                let undo = island_move(papercraft, island);
+                if let Some(island) = papercraft.island_by_key_mut(island) {
+                    island.rotate(Rad(angle), Vector2::new(center[0], center[1]));
+                }
+                undo.into_iter().collect()
+            }
+            Action::LockIsland { island, locked } => {
+                let Some(island) = papercraft.island_by_key_mut(island) else {
+                    return Vec::new();
+                };
+                if island.is_locked() == locked {
+                    return Vec::new();
+                }
+                island.set_locked(locked);
+                vec![UndoAction::IslandLock {
+                    i_root: island.root_face(),
+                    locked: !locked,
+                }]
+            }
+            Action::SetOptions {
+                options,
+                relocate_pieces,
+            } => {
+                let island_pos = island_positions(papercraft);
+                let old_options = papercraft.set_options(*options, relocate_pieces);
+                vec![UndoAction::DocConfig {
+                    options: Box::new(old_options),
+                    island_pos,
+                }]
+            }
+            Action::PackIslands => {
+                // Packing may change the number of pages, too
+                let island_pos = island_positions(papercraft);
+                let old_options = papercraft.options().clone();
+                let mut options = old_options.clone();
+                options.pages = papercraft.pack_islands();
+                papercraft.set_options(options, false);
+                vec![UndoAction::DocConfig {
+                    options: Box::new(old_options),
+                    island_pos,
+                }]
+            }
+            Action::AutoUnfold => {
+                let layout = Box::new(papercraft.cut_layout());
+                papercraft.auto_unfold();
+                vec![UndoAction::Layout { layout }]
+            }
+            Action::JoinStrip { edge } => join_undo(papercraft.try_join_strip(edge)),
+            Action::AutoRotateIsland { island } => {
+                let Some(i) = papercraft.island_by_key(island) else {
+                    return Vec::new();
+                };
+                let (angle, _) = papercraft.island_best_bounding_box(i);
+                rotate_island_to(papercraft, island, angle)
+            }
+            Action::ResetIslandRotation { island } => {
+                rotate_island_to(papercraft, island, Rad::zero())
+            }
+            Action::SetIslandRoot { island, face } => {
+                let Some(i) = papercraft.island_by_key(island) else {
+                    return Vec::new();
+                };
+                // Where the face is now, it will be the origin of the island
+                let mut pos = None;
+                let _ = papercraft.traverse_faces(i, |i_face, _, m| {
+                    if i_face != face {
+                        return ControlFlow::Continue(());
+                    }
+                    pos = Some((Rad::atan2(m.x.y, m.x.x), m.z.truncate()));
+                    ControlFlow::Break(())
+                });
+                let Some((rot, loc)) = pos else {
+                    return Vec::new();
+                };
+                let undo = island_move(papercraft, island);
+                let i = papercraft.island_by_key_mut(island).unwrap();
+                i.reset_transformation(face, rot, loc);
+                undo.into_iter().collect()
+            }
+        }
+    }
+}
+
+/// The islands renamed by the joins in a pack, with the island they finally are part of.
+pub fn island_renames(pack: &[UndoAction]) -> Vec<IslandRename> {
+    let direct: FxHashMap<IslandKey, IslandKey> = pack
+        .iter()
+        .filter_map(|undo| match undo {
+            UndoAction::EdgeJoin {
+                join_result,
+                i_removed,
+            } => Some((*i_removed, join_result.i_island)),
+            _ => None,
+        })
+        .collect();
+    let mut renames: Vec<_> = direct
+        .keys()
+        .map(|&from| {
+            // An island may be joined again later, in a strip or a batch
+            let mut to = direct[&from];
+            while let Some(&next) = direct.get(&to) {
+                to = next;
+            }
+            IslandRename { from, to }
+        })
+        .collect();
+    renames.sort_by_key(|r| r.from);
+    renames
+}
+
+/// If applying `pack`, from `History`, changes many islands or edges at once, as
+/// `Action::is_bulk()`.
+pub fn is_bulk_pack(pack: &[UndoAction]) -> bool {
+    pack.len() > 1
+        || matches!(
+            pack,
+            [UndoAction::DocConfig { .. } | UndoAction::Layout { .. }]
+        )
+}
+
+/// The islands that may look different after applying `pack`, or `None` if any of them may.
+///
+/// It works for the packs of `History` too, because each one names the same faces and edges as
+/// the pack it reverts. Cuts and joins change the names in the edge labels of the islands at
+/// the other side of their cut edges, so those are included.
+pub fn changed_islands(
+    papercraft: &Papercraft,
+    pack: &[UndoAction],
+) -> Option<FxHashSet<IslandKey>> {
+    let edge_islands = |i_edge: EdgeIndex| {
+        let (i_face_a, i_face_b) = papercraft.model()[i_edge].faces();
+        std::iter::once(i_face_a)
+            .chain(i_face_b)
+            .map(|i_face| papercraft.island_by_face(i_face))
+    };
+    let mut changed = FxHashSet::default();
+    let mut cut_or_joined = FxHashSet::default();
+    for undo in pack {
+        match undo {
+            UndoAction::IslandMove { i_root, .. } | UndoAction::IslandLock { i_root, .. } => {
+                changed.insert(papercraft.island_by_face(*i_root));
+            }
+            UndoAction::FlapToggle { i_edge, .. } => changed.extend(edge_islands(*i_edge)),
+            UndoAction::EdgeCut { i_edge } => cut_or_joined.extend(edge_islands(*i_edge)),
+            UndoAction::EdgeJoin { join_result, .. } => {
+                cut_or_joined.extend(edge_islands(join_result.i_edge));
+            }
+            UndoAction::DocConfig { .. } | UndoAction::Layout { .. } => return None,
+        }
+    }
+    for i_island in cut_or_joined {
+        if let Some(island) = papercraft.island_by_key(i_island) {
+            let labels = papercraft.island_edge_labels(island);
+            changed.extend(labels.iter().map(|label| label.partner_island));
+        }
+        changed.insert(i_island);
+    }
+    Some(changed)
+}
+
+fn join_undo(renames: FxHashMap<IslandKey, JoinResult>) -> Vec<UndoAction> {
+    renames
+        .into_iter()
+        .map(|(i_removed, join_result)| UndoAction::EdgeJoin {
+            join_result,
+            i_removed,
+        })
+        .collect()
+}
+
+// Rotates the island around its center, to leave it at `angle`
+fn rotate_island_to(
+    papercraft: &mut Papercraft,
+    i_island: IslandKey,
+    angle: Rad<f32>,
+) -> Vec<UndoAction> {
+    let Some(island) = papercraft.island_by_key(i_island) else {
+        return Vec::new();
+    };
+    let (a, b) = papercraft.island_bounding_box_angle(island, island.rotation());
+    let center = island.location() + (a + b) / 2.0;
+    let delta = angle - island.rotation();
+    let undo = island_move(papercraft, i_island);
+    let island = papercraft.island_by_key_mut(i_island).unwrap();
+    island.rotate(delta, center);
+    undo.into_iter().collect()
+}
+
+/// Applies a batch of actions, all of them or none, as a single undoable pack.
+///
+/// Every action is checked against the state left by the previous ones, because island keys
+/// change after cuts and joins. If one of them is invalid the ones already applied are reverted.
+pub fn apply_batch(
+    papercraft: &mut Papercraft,
+    actions: Vec<Action>,
+) -> ApiResult<Vec<UndoAction>> {
+    let mut pack = Vec::new();
+    for (i, action) in actions.into_iter().enumerate() {
+        if let Err(err) = action.check(papercraft) {
+            revert(papercraft, pack);
+            let mut causes = vec![err.message];
+            causes.extend(err.causes);
+            return Err(ApiError {
+                code: err.code,
+                message: format!("Invalid action {i} in the batch"),
+                causes,
+            });
+        }
+        pack.extend(action.apply(papercraft));
+    }
+    Ok(pack)
+}
+
+fn check_edge(papercraft: &Papercraft, i_edge: EdgeIndex) -> ApiResult<()> {
+    if usize::from(i_edge) >= papercraft.model().num_edges() {
+        return Err(ApiError::new(
+            ErrorCode::InvalidEdge,
+            format!("Edge {} does not exist", usize::from(i_edge)),
+        ));
+    }
+    Ok(())
+}
+
+fn check_face(papercraft: &Papercraft, i_face: FaceIndex) -> ApiResult<()> {
+    if usize::from(i_face) >= papercraft.model().num_faces() {
+        return Err(ApiError::new(
+            ErrorCode::InvalidFace,
+            format!("Face {} does not exist", usize::from(i_face)),
+        ));
+    }
+    Ok(())
+}
+
+fn check_island(papercraft: &Papercraft, i_island: IslandKey) -> ApiResult<()> {
+    if papercraft.island_by_key(i_island).is_none() {
+        return Err(ApiError::new(
+            ErrorCode::InvalidIsland,
+            format!("Island {:?} does not exist", i_island),
+        ));
+    }
+    Ok(())
+}
+
+fn check_finite(name: &str, values: impl IntoIterator<Item = f32>) -> ApiResult<()> {
+    if !values.into_iter().all(f32::is_finite) {
+        return Err(ApiError::new(
+            ErrorCode::BadRequest,
+            format!("The {name} is not a finite number"),
+        ));
+    }
+    Ok(())
+}
+
+// Limit of the cut offset and the bleed, in mm
+const MAX_CUT_OFFSET: f32 = 10.0;
+
+fn check_options(options: &PaperOptions) -> ApiResult<()> {
+    let invalid = |what: &str| {
+        Err(ApiError::new(
+            ErrorCode::BadRequest,
+            format!("Invalid {what} in the options"),
+        ))
+    };
+    let positive = |x: f32| x.is_finite() && x > 0.0;
+    let (width, height) = options.page_size;
+    let (top, left, right, bottom) = options.margin;
+    if !positive(options.scale) {
+        return invalid("scale");
+    }
+    if !positive(width) || !positive(height) {
+        return invalid("page size");
+    }
+    if ![top, left, right, bottom]
+        .iter()
+        .all(|m| m.is_finite() && *m >= 0.0)
+        || left + right >= width
+        || top + bottom >= height
+    {
+        return invalid("margins");
+    }
+    if options.page_cols == 0 {
+        return invalid("page columns");
+    }
+    if options.resolution == 0 {
+        return invalid("resolution");
+    }
+    if ![
+        options.flap_width,
+        options.flap_angle,
+        options.fold_line_len,
+        options.fold_line_width,
+        options.cut_line_width,
+        options.tab_line_width,
+        options.hidden_line_angle,
+    ]
+    .iter()
+    .all(|x| x.is_finite())
+    {
+        return invalid("line or flap size");
+    }
+    // The packer draws a shape per rotation and pads the pages with the gap, huge values
+    // would run out of memory with the project locked
+    if !(1..=360).contains(&options.pack_rotations) {
+        return invalid("pack rotations");
+    }
+    if !(options.pack_gap.is_finite()
+        && options.pack_gap >= 0.0
+        && options.pack_gap < width.min(height))
+    {
+        return invalid("pack gap");
+    }
+    // Much more than a kerf or a bleed would only make the offsets go wild
+    if !(-MAX_CUT_OFFSET..=MAX_CUT_OFFSET).contains(&options.cut_offset) {
+        return invalid("cut offset");
+    }
+    if !(0.0..=MAX_CUT_OFFSET).contains(&options.bleed) {
+        return invalid("bleed");
+    }
+    if let Some(name) = &options.pdf_font {
+        // Only the name of the file, not a path
+        let is_stem = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.'));
+        if !is_stem {
+            return invalid("PDF font");
+        }
+        // Better now than when exporting
+        if let Err(e) = TrueTypeFont::find(name) {
+            return Err(ApiError::with_chain(ErrorCode::BadRequest, &e));
+        }
+    }
+    Ok(())
+}
+
+/// Runs `f`, an action or its undo, catching any panic.
+///
+/// Like the importers, the actions have a lot of indices and unwraps, and a bug must not take
+/// down the server with the lock of the project. If `f` panics and `restore` is set the
+/// papercraft gets back the layout and options it had before. Copying them takes as long as
+/// the model is big, so `restore` is for the bulk actions only, the others change a single
+/// island or edge.
+pub fn catch_panic<T>(
+    papercraft: &mut Papercraft,
+    restore: bool,
+    f: impl FnOnce(&mut Papercraft) -> T,
+) -> ApiResult<T> {
+    let before = restore.then(|| (papercraft.cut_layout(), papercraft.options().clone()));
+    match catch_unwind(AssertUnwindSafe(|| f(papercraft))) {
+        Ok(res) => Ok(res),
+        Err(err) => {
+            if let Some((layout, options)) = before {
+                papercraft.set_options(options, false);
+                papercraft.set_cut_layout(layout);
+            }
+            let msg = err
+                .downcast_ref::<&str>()
+                .map(|s| String::from(*s))
+                .or_else(|| err.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| String::from("Unknown panic"));
+            Err(ApiError {
+                code: ErrorCode::Internal,
+                message: String::from("Panic applying the action"),
+                causes: vec![msg],
+            })
+        }
+    }
+}
+
+fn island_move(papercraft: &Papercraft, i_island: IslandKey) -> Option<UndoAction> {
+    let island = papercraft.island_by_key(i_island)?;
+    Some(UndoAction::IslandMove {
+        i_root: island.root_face(),
+        prev_rot: island.rotation(),
+        prev_loc: island.location(),
+    })
+}
+
+fn island_positions(papercraft: &Papercraft) -> FxHashMap<FaceIndex, (Rad<f32>, Vector2)> {
+    papercraft
+        .islands()
+        .map(|(_, island)| (island.root_face(), (island.rotation(), island.location())))
+        .collect()
+}
+
+// Undoes a pack of actions, in reverse order, and returns the pack that would redo them.
+fn revert(papercraft: &mut Papercraft, pack: Vec<UndoAction>) -> Vec<UndoAction> {
+    let mut redo = Vec::with_capacity(pack.len());
+    for action in pack.into_iter().rev() {
+        match action {
+            UndoAction::IslandMove {
+                i_root,
+                prev_rot,
+                prev_loc,
+            } => {
+                let i_island = papercraft.island_by_face(i_root);
+                redo.extend(island_move(papercraft, i_island));
+                let island = papercraft.island_by_key_mut(i_island).unwrap();
+                island.reset_transformation(i_root, prev_rot, prev_loc);
+            }
+            UndoAction::IslandLock { i_root, locked } => {
+                let i_island = papercraft.island_by_face(i_root);
+                let island = papercraft.island_by_key_mut(i_island).unwrap();
+                redo.push(UndoAction::IslandLock {
+                    i_root,
+                    locked: island.is_locked(),
+                });
+                island.set_locked(locked);
+            }
+            UndoAction::FlapToggle { i_edge, flap_side } => {
+                if let Some(flap_side) =
+                    papercraft.edge_toggle_flap(i_edge, EdgeToggleFlapAction::Set(flap_side))
+                {
+                    redo.push(UndoAction::FlapToggle { i_edge, flap_side });
+                }
+            }
+            UndoAction::EdgeCut { i_edge } => {
+                redo.extend(join_undo(papercraft.edge_join(i_edge, None)));
+            }
+            UndoAction::EdgeJoin { join_result, .. } => {
+                // The joined island will be split, remember where it was
+                let i_island = papercraft.island_by_face(join_result.prev_root);
+                redo.extend(island_move(papercraft, i_island));
+                papercraft.edge_cut(join_result.i_edge, None);
+                redo.push(UndoAction::EdgeCut {
+                    i_edge: join_result.i_edge,
+                });
+
+                let i_prev_island = papercraft.island_by_face(join_result.prev_root);
+                let island = papercraft.island_by_key_mut(i_prev_island).unwrap();
+                island.reset_transformation(
+                    join_result.prev_root,
+                    join_result.prev_rot,
+                    join_result.prev_loc,
+                );
+            }
+            UndoAction::DocConfig {
+                options,
+                island_pos,
+            } => {
+                let current_pos = island_positions(papercraft);
+                let current_options = papercraft.set_options(*options, false);
+                for (i_root_face, (rot, loc)) in island_pos {
+                    let i_island = papercraft.island_by_face(i_root_face);
+                    let island = papercraft.island_by_key_mut(i_island).unwrap();
+                    island.reset_transformation(i_root_face, rot, loc);
+                }
+                redo.push(UndoAction::DocConfig {
+                    options: Box::new(current_options),
+                    island_pos: current_pos,
+                });
+            }
+            UndoAction::Layout { layout } => {
+                let current = papercraft.set_cut_layout(*layout);
+                redo.push(UndoAction::Layout {
+                    layout: Box::new(current),
+                });
+            }
+        }
+    }
+    redo
+}
+
+#[derive(Default)]
+pub struct History {
+    undo_stack: Vec<Vec<UndoAction>>,
+    redo_stack: Vec<Vec<UndoAction>>,
+}
+
+impl History {
+    pub fn push(&mut self, pack: Vec<UndoAction>) {
+        if pack.is_empty() {
+            return;
+        }
+        self.undo_stack.push(pack);
+        self.redo_stack.clear();
+    }
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+    /// The pack that the next undo reverts, the last action or redo.
+    pub fn last_undo(&self) -> Option<&[UndoAction]> {
+        self.undo_stack.last().map(Vec::as_slice)
+    }
+    /// The pack that the next redo reverts, the last undo.
+    pub fn last_redo(&self) -> Option<&[UndoAction]> {
+        self.redo_stack.last().map(Vec::as_slice)
+    }
+    /// Returns `false` if there is nothing to undo.
+    pub fn undo(&mut self, papercraft: &mut Papercraft) -> bool {
+        let Some(pack) = self.undo_stack.pop() else {
+            return false;
+        };
+        let redo = revert(papercraft, pack);
+        self.redo_stack.push(redo);
+        true
+    }
+    /// Returns `false` if there is nothing to redo.
+    pub fn redo(&mut self, papercraft: &mut Papercraft) -> bool {
+        let Some(pack) = self.redo_stack.pop() else {
+            return false;
+        };
+        let undo = revert(papercraft, pack);
+        self.undo_stack.push(undo);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paper::import::load_example;
+
+    type Snapshot = (Vec<EdgeStatus>, Vec<(FaceIndex, [i32; 4])>, String);
+    type ActionBuilder<'a> = Box<dyn Fn(&Papercraft) -> Action + 'a>;
+
+    // Everything an action can change: edges, options and the placement of every face, with
+    // the lock of its island.
+    // Island keys and root faces may change after an undo, so they are not compared.
+    fn snapshot(papercraft: &Papercraft) -> Snapshot {
+        let q = |x: f32| (x * 100.0).round() as i32;
+        let mut faces = Vec::new();
+        for (_, island) in papercraft.islands() {
+            let _ = papercraft.traverse_faces(island, |i_face, _, fmx| {
+                let locked = island.is_locked() as i32;
+                faces.push((
+                    i_face,
+                    [q(fmx[2][0]), q(fmx[2][1]), q(fmx[0][1].atan2(fmx[0][0])), locked],
+                ));
+                ControlFlow::Continue(())
+            });
+        }
+        faces.sort_by_key(|(i_face, _)| *i_face);
+        let options = serde_json::to_string(papercraft.options()).unwrap();
+        (papercraft.edges().copied().collect(), faces, options)
+    }
+
+    fn find_edge(papercraft: &Papercraft, f: impl Fn(EdgeStatus) -> bool) -> EdgeIndex {
+        (0..papercraft.model().num_edges())
+            .map(EdgeIndex::from)
+            .find(|&i_edge| f(papercraft.edge_status(i_edge)))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_undo_redo_all_actions() {
+        let mut papercraft = load_example("dice.pdo");
+        let mut history = History::default();
+
+        let i_joined = find_edge(&papercraft, |s| s == EdgeStatus::Joined);
+        let i_cut = find_edge(&papercraft, |s| matches!(s, EdgeStatus::Cut(_)));
+        let mut options = papercraft.options().clone();
+        options.scale *= 2.0;
+
+        // Island keys change after cuts and joins, so the actions are built as they are applied
+        let first_island = |p: &Papercraft| p.islands().next().unwrap().0;
+        let actions: Vec<ActionBuilder> = vec![
+            Box::new(|_| Action::Cut { edge: i_joined, offset: Some(10.0) }),
+            Box::new(|_| Action::ToggleFlap { edge: i_cut, action: EdgeToggleFlapAction::Toggle }),
+            Box::new(|_| Action::Join { edge: i_cut, priority_face: None }),
+            Box::new(|p| Action::MoveIsland { island: first_island(p), delta: [5.0, -3.0] }),
+            Box::new(|p| Action::RotateIsland {
+                island: first_island(p),
+                angle: 0.5,
+                center: [1.0, 2.0],
+            }),
+            Box::new(|p| Action::AutoRotateIsland { island: first_island(p) }),
+            Box::new(|p| Action::RotateIsland {
+                island: first_island(p),
+                angle: 0.5,
+                center: [1.0, 2.0],
+            }),
+            Box::new(|p| Action::ResetIslandRotation { island: first_island(p) }),
+            Box::new(|p| Action::LockIsland { island: first_island(p), locked: true }),
+            Box::new(|_| Action::SetOptions {
+                options: Box::new(options.clone()),
+                relocate_pieces: true,
+            }),
+            Box::new(|_| Action::PackIslands),
+            Box::new(|_| Action::AutoUnfold),
+        ];
+
+        let mut states = vec![snapshot(&papercraft)];
+        for action in actions {
+            let undo = action(&papercraft).apply(&mut papercraft);
+            assert!(!undo.is_empty());
+            history.push(undo);
+            let state = snapshot(&papercraft);
+            assert_ne!(states.last(), Some(&state));
+            states.push(state);
+        }
+
+        for expected in states.iter().rev().skip(1) {
+            assert!(history.undo(&mut papercraft));
+            assert_eq!(&snapshot(&papercraft), expected);
+        }
+        assert!(!history.undo(&mut papercraft));
+
+        for expected in states.iter().skip(1) {
+            assert!(history.redo(&mut papercraft));
+            assert_eq!(&snapshot(&papercraft), expected);
+        }
+        assert!(!history.redo(&mut papercraft));
+    }
+
+    #[test]
+    fn test_join_strip() {
+        let mut papercraft = load_example("dice.pdo");
+        // Every quad of the dice in its own island
+        for i_edge in 0..papercraft.model().num_edges() {
+            let i_edge = EdgeIndex::from(i_edge);
+            if papercraft.edge_status(i_edge) == EdgeStatus::Joined {
+                papercraft.edge_cut(i_edge, None);
+            }
+        }
+        let before = snapshot(&papercraft);
+        let num_islands = papercraft.num_islands();
+
+        let i_cut = find_edge(&papercraft, |s| matches!(s, EdgeStatus::Cut(_)));
+        let undo = Action::JoinStrip { edge: i_cut }.apply(&mut papercraft);
+        // A strip is more than one join
+        assert!(undo.len() > 1);
+        assert_eq!(papercraft.num_islands(), num_islands - undo.len());
+        let renames = island_renames(&undo);
+        assert_eq!(renames.len(), undo.len());
+        for rename in &renames {
+            assert!(papercraft.island_by_key(rename.from).is_none());
+            assert!(papercraft.island_by_key(rename.to).is_some());
+        }
+
+        let mut history = History::default();
+        history.push(undo);
+        assert!(history.undo(&mut papercraft));
+        assert_eq!(snapshot(&papercraft), before);
+    }
+
+    #[test]
+    fn test_changed_islands() {
+        let mut papercraft = load_example("dice.pdo");
+        let mut history = History::default();
+
+        let island = papercraft.islands().next().unwrap().0;
+        let undo = Action::MoveIsland { island, delta: [1.0, 0.0] }.apply(&mut papercraft);
+        assert_eq!(
+            changed_islands(&papercraft, &undo),
+            Some(FxHashSet::from_iter([island]))
+        );
+
+        // Both sides of the cut, and the islands that show their names in the edge labels
+        let i_edge = find_edge(&papercraft, |s| s == EdgeStatus::Joined);
+        let undo = Action::Cut { edge: i_edge, offset: None }.apply(&mut papercraft);
+        let changed = changed_islands(&papercraft, &undo).unwrap();
+        let (i_face_a, i_face_b) = papercraft.model()[i_edge].faces();
+        let island_a = papercraft.island_by_face(i_face_a);
+        let island_b = papercraft.island_by_face(i_face_b.unwrap());
+        assert_ne!(island_a, island_b);
+        assert!(changed.contains(&island_a) && changed.contains(&island_b));
+        for (i_island, island) in papercraft.islands() {
+            for label in papercraft.island_edge_labels(island) {
+                if label.partner_island == island_a || label.partner_island == island_b {
+                    assert!(changed.contains(&i_island));
+                }
+            }
+        }
+
+        // Undoing the cut joins them again, the redo pack names the same edge
+        history.push(undo);
+        assert!(history.undo(&mut papercraft));
+        let changed = changed_islands(&papercraft, history.last_redo().unwrap()).unwrap();
+        assert!(changed.contains(&papercraft.island_by_face(i_face_a)));
+
+        let options = Box::new(papercraft.options().clone());
+        let undo = Action::SetOptions { options, relocate_pieces: false }.apply(&mut papercraft);
+        assert_eq!(changed_islands(&papercraft, &undo), None);
+    }
+
+    #[test]
+    fn test_set_island_root() {
+        let mut papercraft = load_example("dice.pdo");
+        let (i_island, island) = papercraft.islands().next().unwrap();
+        let prev_root = island.root_face();
+        let mut faces = Vec::new();
+        let _ = papercraft.traverse_faces_no_matrix(island, |i_face| {
+            faces.push(i_face);
+            ControlFlow::Continue(())
+        });
+        let face = *faces.iter().find(|&&f| f != prev_root).unwrap();
+        let before = snapshot(&papercraft);
+
+        let action = Action::SetIslandRoot { island: i_island, face };
+        assert!(action.check(&papercraft).is_ok());
+        let undo = action.apply(&mut papercraft);
+        // The faces do not move
+        assert_eq!(snapshot(&papercraft), before);
+        assert_eq!(papercraft.island_by_key(i_island).unwrap().root_face(), face);
+
+        let mut history = History::default();
+        history.push(undo);
+        assert!(history.undo(&mut papercraft));
+        let i_island = papercraft.island_by_face(prev_root);
+        assert_eq!(papercraft.island_by_key(i_island).unwrap().root_face(), prev_root);
+
+        // A face of another island is rejected
+        let (other, _) = papercraft.islands().find(|(i, _)| *i != i_island).unwrap();
+        let action = Action::SetIslandRoot { island: other, face: prev_root };
+        assert_eq!(action.check(&papercraft).err().unwrap().code, ErrorCode::InvalidFace);
+    }
+
+    #[test]
+    fn test_check_actions() {
+        let papercraft = load_example("dice.pdo");
+        let code = |action: Action| action.check(&papercraft).map_err(|e| e.code).err();
+        let num_edges = papercraft.model().num_edges();
+        let num_faces = papercraft.model().num_faces();
+        let island = papercraft.islands().next().unwrap().0;
+
+        let last_edge = EdgeIndex::from(num_edges - 1);
+        assert_eq!(code(Action::Cut { edge: last_edge, offset: None }), None);
+        let bad_edge = EdgeIndex::from(num_edges);
+        assert_eq!(
+            code(Action::ToggleFlap { edge: bad_edge, action: EdgeToggleFlapAction::Toggle }),
+            Some(ErrorCode::InvalidEdge)
+        );
+        assert_eq!(
+            code(Action::Cut { edge: last_edge, offset: Some(f32::NAN) }),
+            Some(ErrorCode::BadRequest)
+        );
+        assert_eq!(
+            code(Action::Join { edge: last_edge, priority_face: Some(FaceIndex::from(num_faces)) }),
+            Some(ErrorCode::InvalidFace)
+        );
+        assert_eq!(code(Action::MoveIsland { island, delta: [1.0, 2.0] }), None);
+        assert_eq!(
+            code(Action::MoveIsland { island, delta: [f32::INFINITY, 0.0] }),
+            Some(ErrorCode::BadRequest)
+        );
+        assert_eq!(
+            code(Action::LockIsland { island: IslandKey::default(), locked: true }),
+            Some(ErrorCode::InvalidIsland)
+        );
+
+        let mut options = papercraft.options().clone();
+        assert_eq!(
+            code(Action::SetOptions { options: Box::new(options.clone()), relocate_pieces: false }),
+            None
+        );
+        options.page_cols = 0;
+        assert_eq!(
+            code(Action::SetOptions { options: Box::new(options), relocate_pieces: false }),
+            Some(ErrorCode::BadRequest)
+        );
+    }
+
+    #[test]
+    fn test_check_options() {
+        let papercraft = load_example("dice.pdo");
+        const BAD: Option<ErrorCode> = Some(ErrorCode::BadRequest);
+        let check = |f: &dyn Fn(&mut PaperOptions)| {
+            let mut options = papercraft.options().clone();
+            f(&mut options);
+            check_options(&options).map_err(|e| e.code).err()
+        };
+        assert_eq!(check(&|_| ()), None);
+        assert_eq!(check(&|o| o.pack_rotations = 360), None);
+        assert_eq!(check(&|o| o.pack_rotations = 0), BAD);
+        assert_eq!(check(&|o| o.pack_rotations = 361), BAD);
+        assert_eq!(check(&|o| o.pack_gap = 0.0), None);
+        assert_eq!(check(&|o| o.pack_gap = -1.0), BAD);
+        assert_eq!(check(&|o| o.pack_gap = f32::NAN), BAD);
+        assert_eq!(check(&|o| o.pack_gap = 1e30), BAD);
+        assert_eq!(check(&|o| o.cut_offset = -0.2), None);
+        assert_eq!(check(&|o| o.cut_offset = 11.0), BAD);
+        assert_eq!(check(&|o| o.cut_offset = f32::NAN), BAD);
+        assert_eq!(check(&|o| o.bleed = 3.0), None);
+        assert_eq!(check(&|o| o.bleed = -1.0), BAD);
+        assert_eq!(check(&|o| o.bleed = f32::INFINITY), BAD);
+        assert_eq!(check(&|o| o.pdf_font = Some("../fonts/Arial".into())), BAD);
+        assert_eq!(check(&|o| o.pdf_font = Some("No-Such-Font".into())), BAD);
+    }
+
+    #[test]
+    fn test_batch() {
+        let mut papercraft = load_example("dice.pdo");
+        let before = snapshot(&papercraft);
+        let cut = |edge: EdgeIndex| Action::Cut { edge, offset: None };
+        let join = |edge: EdgeIndex| Action::Join {
+            edge,
+            priority_face: None,
+        };
+        let e0 = find_edge(&papercraft, |s| s == EdgeStatus::Joined);
+        let e1 = (0..papercraft.model().num_edges())
+            .map(EdgeIndex::from)
+            .filter(|&e| papercraft.edge_status(e) == EdgeStatus::Joined)
+            .nth(1)
+            .unwrap();
+
+        // The last one is invalid, so nothing is done
+        let bad = EdgeIndex::from(papercraft.model().num_edges());
+        let err = apply_batch(&mut papercraft, vec![cut(e0), cut(e1), join(bad)])
+            .err()
+            .unwrap();
+        assert_eq!(err.code, ErrorCode::InvalidEdge);
+        assert_eq!(err.message, "Invalid action 2 in the batch");
+        assert_eq!(snapshot(&papercraft), before);
+
+        // A valid batch is a single undo step
+        let mut history = History::default();
+        let pack = apply_batch(&mut papercraft, vec![cut(e0), cut(e1), join(e0)]).unwrap();
+        history.push(pack);
+        let after = snapshot(&papercraft);
+        assert_ne!(after, before);
+        assert!(history.undo(&mut papercraft));
+        assert_eq!(snapshot(&papercraft), before);
+        assert!(!history.can_undo());
+        assert!(history.redo(&mut papercraft));
+        assert_eq!(snapshot(&papercraft), after);
+    }
+
+    #[test]
+    fn test_catch_panic() {
+        let mut papercraft = load_example("dice.pdo");
+        let before = snapshot(&papercraft);
+        let res: ApiResult<()> = catch_panic(&mut papercraft, true, |p| {
+            p.auto_unfold();
+            panic!("Bug in the action");
+        });
+        assert_eq!(res.unwrap_err().causes, ["Bug in the action"]);
+        assert_eq!(snapshot(&papercraft), before);
+
+        // Without `restore` the panic is caught, but the changes stay
+        let res: ApiResult<()> = catch_panic(&mut papercraft, false, |p| {
+            p.auto_unfold();
+            panic!("Bug in the action");
+        });
+        assert!(res.is_err());
+        assert_ne!(snapshot(&papercraft), before);
+    }
+}
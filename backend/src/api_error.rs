@@ -0,0 +1,158 @@
+//! Errors returned by the HTTP API.
+//!
+//! Every failure is answered with a JSON body, with a machine-readable `code`, a `message` to
+//! show to the user and the `causes` of the error, the rest of the anyhow context chain.
+
+use axum::{Json, http::StatusCode, response::IntoResponse};
+use serde::Serialize;
+
+use crate::paper::import::{ImporterPanic, UnsupportedFormat};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request itself is wrong: missing fields, bad parameters...
+    BadRequest,
+    ProjectNotFound,
+    /// The project exists, but no model has been uploaded into it
+    NoModel,
+    /// Something inside the project, such as a texture, does not exist
+    NotFound,
+    NothingToUndo,
+    NothingToRedo,
+    /// The uploaded file is not a model in any of the supported formats
+    UnsupportedFormat,
+    /// The file looks like a supported model, but it cannot be read
+    CorruptFile,
+    /// The importer crashed, usually because of a corrupt file
+    ImporterPanic,
+    /// An action refers to an edge, face or island that is not in the model
+    InvalidEdge,
+    InvalidFace,
+    InvalidIsland,
+    ExportFailed,
+    JobNotFound,
+    /// The job has not finished yet
+    JobRunning,
+    /// The edit was made on an old revision of the project
+    StaleRevision,
+    /// A bug or a problem of the server
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::BadRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::ProjectNotFound
+            | ErrorCode::NoModel
+            | ErrorCode::NotFound
+            | ErrorCode::JobNotFound => StatusCode::NOT_FOUND,
+            ErrorCode::NothingToUndo
+            | ErrorCode::NothingToRedo
+            | ErrorCode::JobRunning
+            | ErrorCode::StaleRevision => StatusCode::CONFLICT,
+            ErrorCode::UnsupportedFormat => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ErrorCode::CorruptFile
+            | ErrorCode::ImporterPanic
+            | ErrorCode::InvalidEdge
+            | ErrorCode::InvalidFace
+            | ErrorCode::InvalidIsland => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::ExportFailed | ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub message: String,
+    /// From the outermost to the root cause, without the message
+    pub causes: Vec<String>,
+}
+
+pub type ApiResult<T> = std::result::Result<T, ApiError>;
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> ApiError {
+        ApiError {
+            code,
+            message: message.into(),
+            causes: Vec::new(),
+        }
+    }
+    pub fn with_chain(code: ErrorCode, err: &anyhow::Error) -> ApiError {
+        ApiError {
+            code,
+            message: err.to_string(),
+            causes: err.chain().skip(1).map(|e| e.to_string()).collect(),
+        }
+    }
+    /// Picks the code of a failed import from the errors in the chain
+    pub fn import(err: &anyhow::Error) -> ApiError {
+        let code = if err.chain().any(|e| e.is::<UnsupportedFormat>()) {
+            ErrorCode::UnsupportedFormat
+        } else if err.chain().any(|e| e.is::<ImporterPanic>()) {
+            ErrorCode::ImporterPanic
+        } else {
+            ErrorCode::CorruptFile
+        };
+        ApiError::with_chain(code, err)
+    }
+    pub fn export(err: &anyhow::Error) -> ApiError {
+        ApiError::with_chain(ErrorCode::ExportFailed, err)
+    }
+    pub fn internal(err: &anyhow::Error) -> ApiError {
+        ApiError::with_chain(ErrorCode::Internal, err)
+    }
+    pub fn project_not_found() -> ApiError {
+        ApiError::new(ErrorCode::ProjectNotFound, "Project not found")
+    }
+    pub fn no_model() -> ApiError {
+        ApiError::new(ErrorCode::NoModel, "No model loaded in the project")
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> axum::response::Response {
+        let status = self.code.status();
+        if status.is_server_error() {
+            eprintln!("API error: {:?}", self);
+        }
+        (status, Json(self)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{Context, anyhow};
+
+    #[test]
+    fn test_error_chain() {
+        let err = Err::<(), _>(anyhow!("unexpected end of file"))
+            .context("Error reading Wavefront file box.obj")
+            .unwrap_err();
+        let api = ApiError::import(&err);
+        assert_eq!(api.code, ErrorCode::CorruptFile);
+        assert_eq!(api.message, "Error reading Wavefront file box.obj");
+        assert_eq!(api.causes, ["unexpected end of file"]);
+
+        let err = anyhow!(UnsupportedFormat).context("Error reading file readme.txt");
+        assert_eq!(ApiError::import(&err).code, ErrorCode::UnsupportedFormat);
+    }
+
+    #[test]
+    fn test_error_json() {
+        let api = ApiError::new(ErrorCode::NothingToUndo, "Nothing to undo");
+        assert_eq!(api.code.status(), StatusCode::CONFLICT);
+        assert_eq!(
+            serde_json::to_value(&api).unwrap(),
+            serde_json::json!({
+                "code": "nothing_to_undo",
+                "message": "Nothing to undo",
+                "causes": [],
+            })
+        );
+    }
+}
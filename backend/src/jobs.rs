@@ -0,0 +1,319 @@
+//! Imports and exports that run in the background.
+//!
+//! Big models and PDFs with textures take a while, so they run in a blocking thread, without
+//! holding the lock of the project. The client polls the status of the job, or subscribes to
+//! it with server-sent events, and can cancel it at any time.
+//!
+//! A finished job is forgotten when its result is downloaded, or after `JOB_TTL` if nobody
+//! does.
+
+use std::collections::BTreeMap;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow, bail};
+use axum::body::Bytes;
+use cancel_rw::CancellationToken;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::api_error::ApiError;
+use crate::session::ProjectId;
+
+/// How long a finished job is kept, if its result is not downloaded
+pub const JOB_TTL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct JobId(u64);
+
+impl std::fmt::Display for JobId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum JobKind {
+    Import,
+    Export,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "camelCase")]
+pub enum JobState {
+    Running,
+    Done,
+    Failed { error: ApiError },
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus {
+    pub id: JobId,
+    pub project: ProjectId,
+    pub kind: JobKind,
+    #[serde(flatten)]
+    pub state: JobState,
+    /// What the job is doing now, to show to the user
+    pub stage: String,
+    /// From 0 to 1
+    pub progress: f32,
+}
+
+impl JobStatus {
+    pub fn is_finished(&self) -> bool {
+        self.state != JobState::Running
+    }
+}
+
+/// The file written by an export job
+pub struct JobOutput {
+    pub content_type: &'static str,
+    pub file_name: String,
+    /// Cheap to clone, for every download of the result
+    pub data: Bytes,
+}
+
+pub struct Job {
+    token: CancellationToken,
+    status: watch::Sender<JobStatus>,
+    output: Mutex<Option<Arc<JobOutput>>>,
+    finished: OnceLock<Instant>,
+}
+
+pub type JobRef = Arc<Job>;
+
+impl Job {
+    pub fn status(&self) -> JobStatus {
+        self.status.borrow().clone()
+    }
+    /// The receiver sees every change of the status, the last one is always the finished state
+    pub fn subscribe(&self) -> watch::Receiver<JobStatus> {
+        self.status.subscribe()
+    }
+    pub fn output(&self) -> Option<Arc<JobOutput>> {
+        self.output.lock().unwrap().clone()
+    }
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+    /// The job will stop at the next check of its token, and its result will be discarded
+    pub fn cancel(&self) {
+        self.token.cancel();
+    }
+    /// Fails if the job has been cancelled, so that `?` stops it
+    pub fn check(&self) -> Result<()> {
+        if self.token.check().is_err() {
+            bail!("The job was cancelled");
+        }
+        Ok(())
+    }
+    /// Updates the progress of the job, and checks if it has been cancelled.
+    ///
+    /// Small changes are not sent, to avoid flooding the subscribers.
+    pub fn progress(&self, stage: &str, progress: f32) -> Result<()> {
+        self.check()?;
+        let progress = progress.clamp(0.0, 1.0);
+        self.status.send_if_modified(|status| {
+            if status.stage == stage && (status.progress - progress).abs() < 0.01 {
+                return false;
+            }
+            status.stage = String::from(stage);
+            status.progress = progress;
+            true
+        });
+        Ok(())
+    }
+    fn finish(&self, result: Result<Option<JobOutput>>) {
+        let state = match result {
+            Ok(output) => {
+                *self.output.lock().unwrap() = output.map(Arc::new);
+                JobState::Done
+            }
+            // Whatever the error is, it is probably caused by the cancellation
+            Err(_) if self.token.check().is_err() => JobState::Cancelled,
+            Err(e) => {
+                let error = match self.status.borrow().kind {
+                    JobKind::Import => ApiError::import(&e),
+                    JobKind::Export => ApiError::export(&e),
+                };
+                JobState::Failed { error }
+            }
+        };
+        let _ = self.finished.set(Instant::now());
+        self.status.send_modify(|status| {
+            if state == JobState::Done {
+                status.progress = 1.0;
+            }
+            status.state = state;
+        });
+    }
+    fn expired(&self, ttl: Duration) -> bool {
+        self.finished.get().is_some_and(|t| t.elapsed() >= ttl)
+    }
+}
+
+#[derive(Default)]
+pub struct JobStore {
+    next_id: AtomicU64,
+    jobs: RwLock<BTreeMap<JobId, JobRef>>,
+}
+
+impl JobStore {
+    pub fn new() -> JobStore {
+        JobStore::default()
+    }
+
+    /// Runs `f` as a new job in a blocking thread.
+    ///
+    /// Only the last finished job of each kind is kept for every project, older ones are
+    /// forgotten when a new one starts.
+    pub fn spawn<F>(&self, project: ProjectId, kind: JobKind, f: F) -> JobRef
+    where
+        F: FnOnce(&Job) -> Result<Option<JobOutput>> + Send + 'static,
+    {
+        let id = JobId(self.next_id.fetch_add(1, Ordering::Relaxed) + 1);
+        let (status, _) = watch::channel(JobStatus {
+            id,
+            project,
+            kind,
+            state: JobState::Running,
+            stage: String::from("Starting"),
+            progress: 0.0,
+        });
+        let job = Arc::new(Job {
+            token: CancellationToken::new(),
+            status,
+            output: Mutex::new(None),
+            finished: OnceLock::new(),
+        });
+        {
+            let mut jobs = self.jobs.write().unwrap();
+            jobs.retain(|_, job| {
+                let status = job.status.borrow();
+                !(status.project == project && status.kind == kind && status.is_finished())
+            });
+            jobs.insert(id, job.clone());
+        }
+        tokio::task::spawn_blocking({
+            let job = job.clone();
+            move || {
+                let result = catch_unwind(AssertUnwindSafe(|| f(&job)))
+                    .unwrap_or_else(|_| Err(anyhow!("Panic running the job")));
+                job.finish(result);
+            }
+        });
+        job
+    }
+    pub fn get(&self, id: JobId) -> Option<JobRef> {
+        self.jobs.read().unwrap().get(&id).cloned()
+    }
+    /// Cancels the job, if it is still running, and forgets it
+    pub fn remove(&self, id: JobId) -> bool {
+        let Some(job) = self.jobs.write().unwrap().remove(&id) else {
+            return false;
+        };
+        job.cancel();
+        true
+    }
+    /// Forgets the jobs that finished more than `ttl` ago
+    pub fn expire(&self, ttl: Duration) {
+        self.jobs
+            .write()
+            .unwrap()
+            .retain(|_, job| !job.expired(ttl));
+    }
+    /// Cancels and forgets all the jobs of a project
+    pub fn remove_project(&self, project: ProjectId) {
+        self.jobs.write().unwrap().retain(|_, job| {
+            if job.status.borrow().project != project {
+                return true;
+            }
+            job.cancel();
+            false
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_error::ErrorCode;
+    use crate::session::{Project, ProjectStore};
+
+    fn project_id() -> ProjectId {
+        ProjectStore::new().create(Project::new(String::from("test"), None))
+    }
+
+    async fn wait(job: &Job) -> JobStatus {
+        let mut rx = job.subscribe();
+        rx.wait_for(|status| status.is_finished())
+            .await
+            .unwrap()
+            .clone()
+    }
+
+    #[tokio::test]
+    async fn test_job_done() {
+        let store = JobStore::new();
+        let job = store.spawn(project_id(), JobKind::Export, |job| {
+            job.progress("Working", 0.5)?;
+            Ok(Some(JobOutput {
+                content_type: "text/plain",
+                file_name: String::from("out.txt"),
+                data: Bytes::from_static(b"done"),
+            }))
+        });
+        let status = wait(&job).await;
+        assert_eq!(status.state, JobState::Done);
+        assert_eq!(status.progress, 1.0);
+        assert_eq!(&job.output().unwrap().data[..], b"done");
+        assert!(store.get(status.id).is_some());
+
+        store.expire(JOB_TTL);
+        assert!(store.get(status.id).is_some());
+        store.expire(Duration::ZERO);
+        assert!(store.get(status.id).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_job_cancel() {
+        let store = JobStore::new();
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+        let job = store.spawn(project_id(), JobKind::Import, move |job| {
+            started_tx.send(()).unwrap();
+            loop {
+                job.progress("Waiting", 0.0)?;
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+        });
+        started_rx.recv().unwrap();
+        // A running job never expires
+        store.expire(Duration::ZERO);
+        assert!(store.get(job.status().id).is_some());
+        job.cancel();
+        assert_eq!(wait(&job).await.state, JobState::Cancelled);
+        assert!(job.output().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_job_failed() {
+        let store = JobStore::new();
+        let project = project_id();
+        let job = store.spawn(project, JobKind::Import, |_| bail!("Bad file"));
+        let JobState::Failed { error } = wait(&job).await.state else {
+            panic!("The job did not fail");
+        };
+        assert_eq!(error.code, ErrorCode::CorruptFile);
+        assert_eq!(error.message, "Bad file");
+        // Only the last finished job of a kind is kept
+        let id = job.status().id;
+        let job = store.spawn(project, JobKind::Import, |_| Ok(None));
+        wait(&job).await;
+        assert!(store.get(id).is_none());
+    }
+}
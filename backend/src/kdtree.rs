@@ -0,0 +1,150 @@
+//! A k-d tree of 3D points, for the queries that would compare every point of a model
+//! against every point of another one.
+
+use cgmath::MetricSpace;
+
+use crate::util_3d::Vector3;
+
+/// A static k-d tree, each point with a value attached.
+///
+/// It is stored in a single vector: every slice has its splitting point in the middle, the
+/// points with a lower coordinate on the left and the others on the right.
+pub struct KdTree<T> {
+    nodes: Vec<(Vector3, T)>,
+}
+
+impl<T> KdTree<T> {
+    pub fn new(points: impl IntoIterator<Item = (Vector3, T)>) -> KdTree<T> {
+        let mut nodes: Vec<_> = points.into_iter().collect();
+        build(&mut nodes, 0);
+        KdTree { nodes }
+    }
+    /// Calls `f` for every point at `radius` or less from `p`, in no particular order.
+    pub fn for_each_within(&self, p: Vector3, radius: f32, mut f: impl FnMut(Vector3, &T)) {
+        within(&self.nodes, 0, p, radius, &mut f);
+    }
+    /// The point nearest to `p`, if the tree is not empty.
+    pub fn nearest(&self, p: Vector3) -> Option<(Vector3, &T)> {
+        let mut best = None;
+        nearest(&self.nodes, 0, p, &mut best);
+        best.map(|(_, (q, t))| (*q, t))
+    }
+}
+
+fn build<T>(nodes: &mut [(Vector3, T)], axis: usize) {
+    if nodes.len() <= 1 {
+        return;
+    }
+    let mid = nodes.len() / 2;
+    nodes.select_nth_unstable_by(mid, |a, b| a.0[axis].total_cmp(&b.0[axis]));
+    let (left, right) = nodes.split_at_mut(mid);
+    let next = (axis + 1) % 3;
+    build(left, next);
+    build(&mut right[1..], next);
+}
+
+fn within<T>(
+    nodes: &[(Vector3, T)],
+    axis: usize,
+    p: Vector3,
+    radius: f32,
+    f: &mut impl FnMut(Vector3, &T),
+) {
+    if nodes.is_empty() {
+        return;
+    }
+    let mid = nodes.len() / 2;
+    let (q, t) = &nodes[mid];
+    if q.distance2(p) <= radius * radius {
+        f(*q, t);
+    }
+    let d = p[axis] - q[axis];
+    let next = (axis + 1) % 3;
+    if d <= radius {
+        within(&nodes[..mid], next, p, radius, f);
+    }
+    if d >= -radius {
+        within(&nodes[mid + 1..], next, p, radius, f);
+    }
+}
+
+// `best` is the squared distance and the node
+fn nearest<'a, T>(
+    nodes: &'a [(Vector3, T)],
+    axis: usize,
+    p: Vector3,
+    best: &mut Option<(f32, &'a (Vector3, T))>,
+) {
+    if nodes.is_empty() {
+        return;
+    }
+    let mid = nodes.len() / 2;
+    let node = &nodes[mid];
+    let d2 = node.0.distance2(p);
+    if best.is_none_or(|(b, _)| d2 < b) {
+        *best = Some((d2, node));
+    }
+    let d = p[axis] - node.0[axis];
+    let next = (axis + 1) % 3;
+    // The side of `p` first, the other one only if it can be nearer
+    let (near, far) = if d <= 0.0 {
+        (&nodes[..mid], &nodes[mid + 1..])
+    } else {
+        (&nodes[mid + 1..], &nodes[..mid])
+    };
+    nearest(near, next, p, best);
+    if best.is_none_or(|(b, _)| d * d < b) {
+        nearest(far, next, p, best);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A deterministic cloud of points, without a random generator
+    fn points(n: usize) -> Vec<Vector3> {
+        (0..n)
+            .map(|i| {
+                let i = i as f32;
+                Vector3::new(
+                    (i * 0.618).fract(),
+                    (i * 0.414).fract(),
+                    (i * 0.732).fract(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_kdtree_queries() {
+        let points = points(500);
+        let tree = KdTree::new(points.iter().copied().enumerate().map(|(i, p)| (p, i)));
+
+        for q in [
+            Vector3::new(0.5, 0.5, 0.5),
+            Vector3::new(0.1, 0.9, 0.3),
+            Vector3::new(2.0, -1.0, 0.0),
+        ] {
+            let brute = points
+                .iter()
+                .map(|p| p.distance2(q))
+                .min_by(f32::total_cmp)
+                .unwrap();
+            let (p, i) = tree.nearest(q).unwrap();
+            assert_eq!(p, points[*i]);
+            assert_eq!(p.distance2(q), brute);
+
+            let mut found = Vec::new();
+            tree.for_each_within(q, 0.2, |_, &i| found.push(i));
+            found.sort();
+            let expected: Vec<_> = (0..points.len())
+                .filter(|&i| points[i].distance2(q) <= 0.2 * 0.2)
+                .collect();
+            assert_eq!(found, expected);
+        }
+
+        let empty = KdTree::<()>::new([]);
+        assert!(empty.nearest(Vector3::new(0.0, 0.0, 0.0)).is_none());
+    }
+}
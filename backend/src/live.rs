@@ -0,0 +1,392 @@
+//! Live editing of a project by several clients.
+//!
+//! Every change of a project gets a new revision number, and it is broadcast with its delta to
+//! the clients connected to the WebSocket of the project, so that every browser shows the same
+//! state. An edit may say the revision it was made on: if the project has changed since then
+//! it is rejected, and the client can rebase it on the updates it got and send it again.
+
+use std::sync::Arc;
+
+use axum::extract::ws::{Message, WebSocket};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::action::{self, Action, History, IslandRename, UndoAction, catch_panic};
+use crate::api_error::{ApiError, ApiResult, ErrorCode};
+use crate::paper::{Papercraft, RenderableDelta, RenderablePapercraft};
+use crate::session::{Project, ProjectRef};
+
+/// A change requested by a client
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Edit {
+    Action { action: Action },
+    Batch { actions: Vec<Action> },
+    Undo,
+    Redo,
+}
+
+impl Edit {
+    /// If applying it may take seconds with a big model, too long for the async executor.
+    ///
+    /// Undoing those actions only restores the layout they replaced.
+    pub fn is_slow(&self) -> bool {
+        match self {
+            Edit::Action { action } => action.is_slow(),
+            Edit::Batch { actions } => actions.iter().any(Action::is_slow),
+            Edit::Undo | Edit::Redo => false,
+        }
+    }
+}
+
+/// A change of the project, as broadcast to its clients
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Update {
+    Edit {
+        revision: u64,
+        edit: Edit,
+        delta: RenderableDelta,
+        renames: Vec<IslandRename>,
+    },
+    /// The model was replaced, by an upload or an import
+    Reload { revision: u64 },
+}
+
+pub struct Applied {
+    pub revision: u64,
+    /// Only if it was asked for
+    pub delta: Option<RenderableDelta>,
+    /// The islands that were joined into others, by an action or by undoing a cut
+    pub renames: Vec<IslandRename>,
+}
+
+/// Applies an edit to the project and broadcasts it.
+///
+/// If `revision` is given and it is not the current one the edit is rejected.
+pub fn apply_edit(
+    project: &mut Project,
+    edit: Edit,
+    revision: Option<u64>,
+    want_delta: bool,
+) -> ApiResult<Applied> {
+    let Project {
+        papercraft,
+        history,
+        revision: current,
+        updates,
+        ..
+    } = project;
+    let papercraft = papercraft.as_mut().ok_or_else(ApiError::no_model)?;
+    if revision.is_some_and(|r| r != *current) {
+        return Err(ApiError::new(
+            ErrorCode::StaleRevision,
+            format!("The project is at revision {current}"),
+        ));
+    }
+    // Computing the delta is not free, do it only if someone wants it
+    let listening = updates.receiver_count() > 0;
+    let before = (want_delta || listening).then(|| papercraft.delta_base());
+    let broadcast = listening.then(|| edit.clone());
+    // The islands that changed are taken from the pack that reverts the edit
+    let delta_of = |papercraft: &Papercraft, pack: &[UndoAction]| {
+        let before = before.as_ref()?;
+        let changed = action::changed_islands(papercraft, pack);
+        Some(papercraft.renderable_delta(before, changed.as_ref()))
+    };
+
+    let mut renames = Vec::new();
+    let delta = match edit {
+        Edit::Action { action } => {
+            action.check(papercraft)?;
+            let undo = catch_panic(papercraft, action.is_bulk(), |p| action.apply(p))?;
+            renames = action::island_renames(&undo);
+            let delta = delta_of(papercraft, &undo);
+            history.push(undo);
+            delta
+        }
+        Edit::Batch { actions } => {
+            // A single snapshot for the whole batch, if it needs one
+            let bulk = actions.len() > 1 || actions.iter().any(Action::is_bulk);
+            let undo = catch_panic(papercraft, bulk, |p| action::apply_batch(p, actions))??;
+            renames = action::island_renames(&undo);
+            let delta = delta_of(papercraft, &undo);
+            history.push(undo);
+            delta
+        }
+        Edit::Undo => {
+            let bulk = history.last_undo().is_some_and(action::is_bulk_pack);
+            history_step(
+                papercraft,
+                history,
+                History::undo,
+                bulk,
+                ApiError::new(ErrorCode::NothingToUndo, "Nothing to undo"),
+            )?;
+            // Undoing a cut joins islands, that is in the pack that would redo it
+            let redo = history.last_redo().unwrap_or_default();
+            renames = action::island_renames(redo);
+            delta_of(papercraft, redo)
+        }
+        Edit::Redo => {
+            let bulk = history.last_redo().is_some_and(action::is_bulk_pack);
+            history_step(
+                papercraft,
+                history,
+                History::redo,
+                bulk,
+                ApiError::new(ErrorCode::NothingToRedo, "Nothing to redo"),
+            )?;
+            let undo = history.last_undo().unwrap_or_default();
+            renames = action::island_renames(undo);
+            delta_of(papercraft, undo)
+        }
+    };
+    *current += 1;
+
+    if let (Some(edit), Some(delta)) = (broadcast, &delta) {
+        let _ = updates.send(Arc::new(Update::Edit {
+            revision: *current,
+            edit,
+            delta: delta.clone(),
+            renames: renames.clone(),
+        }));
+    }
+    Ok(Applied {
+        revision: *current,
+        delta: delta.filter(|_| want_delta),
+        renames,
+    })
+}
+
+// `step` is `History::undo` or `History::redo`, `bulk` if the pack it applies is a bulk one,
+// and `nothing` the error if there is nothing to do
+fn history_step(
+    papercraft: &mut Papercraft,
+    history: &mut History,
+    step: fn(&mut History, &mut Papercraft) -> bool,
+    bulk: bool,
+    nothing: ApiError,
+) -> ApiResult<()> {
+    match catch_panic(papercraft, bulk, |p| step(history, p)) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(nothing),
+        Err(e) => {
+            // The rest of the history may not match the restored model
+            history.clear();
+            Err(e)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Request {
+    /// Chosen by the client, to match the reply
+    id: u64,
+    /// The revision the edit was made on, if it must not be applied on a newer one
+    revision: Option<u64>,
+    #[serde(flatten)]
+    edit: Edit,
+}
+
+/// The messages sent only to one client, the updates go to all of them
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Reply {
+    /// The whole project, when connecting or after missing some updates
+    State {
+        revision: u64,
+        project: Option<RenderablePapercraft>,
+    },
+    Applied {
+        id: u64,
+        revision: u64,
+    },
+    Rejected {
+        id: Option<u64>,
+        revision: u64,
+        error: ApiError,
+    },
+}
+
+/// Serves the WebSocket of a client of the project, until it closes.
+pub async fn serve(mut socket: WebSocket, project_ref: ProjectRef) {
+    // Subscribe with the lock taken, so that no update is missed or comes before the state
+    let (mut updates, state) = {
+        let project = project_ref.lock().unwrap();
+        (project.updates.subscribe(), full_state(&project))
+    };
+    if send(&mut socket, &state).await.is_err() {
+        return;
+    }
+    loop {
+        let res = tokio::select! {
+            msg = socket.recv() => {
+                let text = match msg {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                let reply = handle_request(&project_ref, &text).await;
+                send(&mut socket, &reply).await
+            }
+            update = updates.recv() => match update {
+                Ok(update) => send(&mut socket, &*update).await,
+                // Too slow, send the whole state instead of the missing updates
+                Err(RecvError::Lagged(_)) => {
+                    let state = {
+                        let project = project_ref.lock().unwrap();
+                        updates = project.updates.subscribe();
+                        full_state(&project)
+                    };
+                    send(&mut socket, &state).await
+                }
+                Err(RecvError::Closed) => break,
+            },
+        };
+        if res.is_err() {
+            break;
+        }
+    }
+}
+
+fn full_state(project: &Project) -> Reply {
+    Reply::State {
+        revision: project.revision,
+        project: project.papercraft.as_ref().map(Papercraft::renderable),
+    }
+}
+
+async fn handle_request(project_ref: &ProjectRef, text: &str) -> Reply {
+    let request: Request = match serde_json::from_str(text) {
+        Ok(request) => request,
+        Err(e) => {
+            return Reply::Rejected {
+                id: None,
+                revision: project_ref.lock().unwrap().revision,
+                error: ApiError::new(ErrorCode::BadRequest, e.to_string()),
+            };
+        }
+    };
+    if !request.edit.is_slow() {
+        return apply_request(project_ref, request);
+    }
+    // As in the HTTP API, slow edits are applied out of the async executor
+    let id = request.id;
+    let project = project_ref.clone();
+    tokio::task::spawn_blocking(move || apply_request(&project, request))
+        .await
+        .unwrap_or_else(|e| Reply::Rejected {
+            id: Some(id),
+            revision: project_ref.lock().unwrap().revision,
+            error: ApiError::new(ErrorCode::Internal, e.to_string()),
+        })
+}
+
+fn apply_request(project_ref: &ProjectRef, request: Request) -> Reply {
+    let mut project = project_ref.lock().unwrap();
+    // The delta goes to everybody with the update
+    match apply_edit(&mut project, request.edit, request.revision, false) {
+        Ok(applied) => Reply::Applied {
+            id: request.id,
+            revision: applied.revision,
+        },
+        Err(error) => Reply::Rejected {
+            id: Some(request.id),
+            revision: project.revision,
+            error,
+        },
+    }
+}
+
+async fn send(socket: &mut WebSocket, msg: &impl Serialize) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(msg).unwrap();
+    socket.send(Message::Text(text)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paper::import::load_example;
+    use crate::paper::{EdgeIndex, EdgeStatus};
+
+    fn project() -> Project {
+        Project::new(String::from("dice.pdo"), Some(load_example("dice.pdo")))
+    }
+
+    fn cut(papercraft: &Papercraft) -> Edit {
+        let edge = (0..papercraft.model().num_edges())
+            .map(EdgeIndex::from)
+            .find(|&e| papercraft.edge_status(e) == EdgeStatus::Joined)
+            .unwrap();
+        Edit::Action {
+            action: Action::Cut { edge, offset: None },
+        }
+    }
+
+    #[test]
+    fn test_edits_are_broadcast() {
+        let mut project = project();
+        let mut updates = project.updates.subscribe();
+
+        let edit = cut(project.papercraft.as_ref().unwrap());
+        let applied = apply_edit(&mut project, edit, Some(0), false).unwrap();
+        assert_eq!(applied.revision, 1);
+        assert!(applied.delta.is_none());
+        match &*updates.try_recv().unwrap() {
+            Update::Edit {
+                revision, delta, ..
+            } => {
+                assert_eq!(*revision, 1);
+                assert!(!delta.edges.is_empty());
+            }
+            Update::Reload { .. } => panic!("Expected an edit"),
+        }
+
+        apply_edit(&mut project, Edit::Undo, None, true).unwrap();
+        assert_eq!(project.revision, 2);
+        assert!(updates.try_recv().is_ok());
+    }
+
+    #[test]
+    fn test_undo_renames() {
+        let mut project = project();
+        let edit = cut(project.papercraft.as_ref().unwrap());
+        let applied = apply_edit(&mut project, edit, None, true).unwrap();
+        assert!(applied.renames.is_empty());
+
+        // Undoing the cut joins the new island back
+        let applied = apply_edit(&mut project, Edit::Undo, None, true).unwrap();
+        assert_eq!(applied.renames.len(), 1);
+        let rename = applied.renames[0];
+        let papercraft = project.papercraft.as_ref().unwrap();
+        assert!(papercraft.island_by_key(rename.from).is_none());
+        assert!(papercraft.island_by_key(rename.to).is_some());
+        let delta = applied.delta.unwrap();
+        assert_eq!(delta.removed, [rename.from]);
+        assert!(delta.changed.iter().any(|island| island.id == rename.to));
+
+        let applied = apply_edit(&mut project, Edit::Redo, None, true).unwrap();
+        assert!(applied.renames.is_empty());
+    }
+
+    #[test]
+    fn test_stale_revision() {
+        let mut project = project();
+        let edit = cut(project.papercraft.as_ref().unwrap());
+        apply_edit(&mut project, edit.clone(), Some(0), false).unwrap();
+        // Made on revision 0 by another client
+        let err = apply_edit(&mut project, Edit::Undo, Some(0), false)
+            .err()
+            .unwrap();
+        assert_eq!(err.code, ErrorCode::StaleRevision);
+        assert_eq!(project.revision, 1);
+
+        // A failed edit is not a new revision
+        let err = apply_edit(&mut project, Edit::Redo, Some(1), false)
+            .err()
+            .unwrap();
+        assert_eq!(err.code, ErrorCode::NothingToRedo);
+        assert_eq!(project.revision, 1);
+    }
+}
@@ -2,21 +2,34 @@ use axum::{
     routing::{get, post},
     Router,
     Json,
-    extract::{Query, State, Multipart, DefaultBodyLimit, Path},
+    extract::{Query, State, Multipart, DefaultBodyLimit, Path, rejection::JsonRejection, ws::WebSocketUpgrade},
     http::StatusCode,
-    response::IntoResponse,
+    response::{
+        IntoResponse,
+        sse::{Event, KeepAlive, Sse},
+    },
 };
-use std::sync::{Arc, Mutex};
+use cancel_rw::CancellationToken;
+use futures_util::stream::{self, Stream};
+use std::convert::Infallible;
+use std::sync::Arc;
 use tower_http::cors::CorsLayer;
 use serde::{Serialize, Deserialize};
-use std::io::Write;
-use cgmath::Rad;
 
+mod action;
+mod api_error;
 mod config;
 mod context;
 pub use context::GlobalContext;
+mod jobs;
+mod kdtree;
+mod live;
 mod paper;
+mod pdf_font;
 mod pdf_metrics;
+mod session;
+mod thumbnail;
+mod upload;
 mod vector_export;
 mod util_3d;
 // mod util_gl;
@@ -24,9 +37,15 @@ mod util_3d;
 #[cfg(test)]
 mod svg_tests;
 
-use paper::{Papercraft, RenderablePapercraft, EdgeIndex, EdgeToggleFlapAction, FaceIndex, IslandKey, PaperOptions};
-use util_3d::Vector2;
-use clap::{Parser, Subcommand};
+use action::{Action, IslandRename};
+use api_error::{ApiError, ApiResult, ErrorCode};
+use jobs::{JOB_TTL, Job, JobId, JobKind, JobOutput, JobRef, JobStatus, JobStore};
+use live::Edit;
+use paper::{Papercraft, RenderableDelta, RenderablePapercraft, UpdateReport, ValidationReport};
+use session::{Project, ProjectId, ProjectInfo, ProjectRef, ProjectStore};
+use upload::UploadDir;
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -47,160 +66,551 @@ enum Commands {
         /// Path to the model file (PDO, OBJ, STL, glTF)
         path: std::path::PathBuf,
     },
+    /// Export a model to PDF or SVG without starting the server
+    Export(ExportArgs),
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum ExportFormat {
+    Pdf,
+    Svg,
+    SvgMultipage,
+    /// All the pages tiled in a single drawing, or only `--page`
+    Dxf,
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    /// Path to the model file (craft, PDO, OBJ, STL, glTF)
+    path: std::path::PathBuf,
+    #[arg(short, long, value_enum, default_value_t = ExportFormat::Pdf)]
+    format: ExportFormat,
+    /// Output file
+    #[arg(short, long)]
+    out: std::path::PathBuf,
+    /// Include the textures
+    #[arg(long)]
+    textures: bool,
+    /// Page to export: the first one by default for the single page SVG, all of them tiled for
+    /// DXF
+    #[arg(long)]
+    page: Option<u32>,
+    /// Scale of the model
+    #[arg(long)]
+    scale: Option<f32>,
+    /// Page size in mm, as WIDTHxHEIGHT
+    #[arg(long, value_parser = parse_page_size)]
+    page_size: Option<(f32, f32)>,
+    /// Margins in mm, a single value or TOP,LEFT,RIGHT,BOTTOM
+    #[arg(long, value_parser = parse_margins)]
+    margins: Option<(f32, f32, f32, f32)>,
+    /// TrueType font of the PDF texts, by file name, instead of Helvetica
+    #[arg(long)]
+    pdf_font: Option<String>,
+    /// Compute new cuts from scratch before exporting, implies --pack
+    #[arg(long)]
+    auto_unfold: bool,
+    /// Pack the islands into the pages before exporting
+    #[arg(long)]
+    pack: bool,
+}
+
+fn parse_page_size(s: &str) -> Result<(f32, f32)> {
+    let (w, h) = s
+        .split_once(['x', 'X'])
+        .context("expected WIDTHxHEIGHT")?;
+    Ok((w.trim().parse()?, h.trim().parse()?))
+}
+
+fn parse_margins(s: &str) -> Result<(f32, f32, f32, f32)> {
+    let values = s
+        .split(',')
+        .map(|v| v.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()?;
+    match values[..] {
+        [m] => Ok((m, m, m, m)),
+        [top, left, right, bottom] => Ok((top, left, right, bottom)),
+        _ => anyhow::bail!("expected 1 or 4 values"),
+    }
 }
 
 struct AppState {
-    project: Option<Papercraft>,
+    projects: ProjectStore,
+    jobs: JobStore,
+}
+
+impl AppState {
+    fn project(&self, id: ProjectId) -> ApiResult<ProjectRef> {
+        self.projects.get(id).ok_or_else(ApiError::project_not_found)
+    }
+    fn job(&self, id: JobId) -> ApiResult<JobRef> {
+        self.jobs
+            .get(id)
+            .ok_or_else(|| ApiError::new(ErrorCode::JobNotFound, "Job not found"))
+    }
 }
 
 #[derive(Serialize)]
 struct Status {
     status: String,
     has_model: bool,
+    projects: usize,
 }
 
-#[derive(Deserialize)]
-#[serde(tag = "type", rename_all = "camelCase")]
-enum Action {
-    ToggleFlap { edge: EdgeIndex, action: EdgeToggleFlapAction },
-    Cut { edge: EdgeIndex, offset: Option<f32> },
-    Join { edge: EdgeIndex, priority_face: Option<FaceIndex> },
-    MoveIsland { island: IslandKey, delta: [f32; 2] },
-    RotateIsland { island: IslandKey, angle: f32, center: [f32; 2] },
-    SetOptions { options: PaperOptions, relocate_pieces: bool },
-    PackIslands,
-}
-
-async fn get_status(State(state): State<Arc<Mutex<AppState>>>) -> Json<Status> {
-    let state = state.lock().unwrap();
+#[derive(Deserialize, Default)]
+struct NewProject {
+    name: Option<String>,
+}
+
+async fn get_status(State(state): State<Arc<AppState>>) -> Json<Status> {
+    let projects = state.projects.list();
     Json(Status {
         status: "ok".to_string(),
-        has_model: state.project.is_some(),
+        has_model: projects.iter().any(|p| p.has_model),
+        projects: projects.len(),
     })
 }
 
-async fn upload_model(
-    State(state): State<Arc<Mutex<AppState>>>,
-    mut multipart: Multipart,
-) -> Result<impl IntoResponse, StatusCode> {
+async fn list_projects(State(state): State<Arc<AppState>>) -> Json<Vec<ProjectInfo>> {
+    Json(state.projects.list())
+}
+
+async fn create_project(
+    State(state): State<Arc<AppState>>,
+    new_project: Option<Json<NewProject>>,
+) -> Json<ProjectInfo> {
+    let new_project = new_project.map(|Json(p)| p).unwrap_or_default();
+    let name = new_project.name.unwrap_or_else(|| String::from("Untitled"));
+    let id = state.projects.create(Project::new(name.clone(), None));
+    println!("Created project {}", id);
+    Json(ProjectInfo { id, name, has_model: false, can_undo: false, can_redo: false })
+}
+
+async fn delete_project(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<ProjectId>,
+) -> ApiResult<StatusCode> {
+    if !state.projects.remove(id) {
+        return Err(ApiError::project_not_found());
+    }
+    state.jobs.remove_project(id);
+    println!("Deleted project {}", id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// The files of a model uploaded by the client
+struct ReceivedModel {
+    upload: UploadDir,
+    model: std::path::PathBuf,
+    // The name of the model file, without the directories
+    file_name: String,
+}
+
+// Saves all the files of a multipart upload in a new upload directory.
+// Returns `None` if there is no file.
+async fn receive_model(multipart: &mut Multipart) -> ApiResult<Option<ReceivedModel>> {
+    let mut upload = UploadDir::new().map_err(|e| ApiError::internal(&e))?;
+    let mut has_files = false;
     loop {
         match multipart.next_field().await {
             Ok(Some(field)) => {
                 let name = field.name().unwrap_or("").to_string();
-                let file_name = field.file_name().unwrap_or("model.obj").to_string();
-                let data = field.bytes().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-                if name == "file" {
-                    let temp_dir = std::env::temp_dir();
-                    let temp_path = temp_dir.join(&file_name);
-                    {
-                        let mut file = std::fs::File::create(&temp_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-                        file.write_all(&data).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-                    }
-
-                    eprintln!("Attempting to import file: {} ({} bytes)", file_name, data.len());
-                    eprintln!("Temp path: {:?}", temp_path);
-                    
-                    let (project, _) = paper::import::import_model_file(&temp_path)
-                        .map_err(|e| {
-                            eprintln!("=== Import Error ===");
-                            eprintln!("File: {}", file_name);
-                            eprintln!("Size: {} bytes", data.len());
-                            eprintln!("Temp path: {:?}", temp_path);
-                            eprintln!("Error: {:?}", e);
-                            eprintln!("Error chain:");
-                            for (i, cause) in e.chain().enumerate() {
-                                eprintln!("  {}: {}", i, cause);
-                            }
-                            eprintln!("====================");
-                            StatusCode::INTERNAL_SERVER_ERROR
-                        })?;
-                    
-                    let mut state = state.lock().unwrap();
-                    state.project = Some(project.clone());
-
-                    eprintln!("=== Import Success ===");
-                    eprintln!("File: {}", file_name);
-                    eprintln!("Islands: {}", project.islands().count());
-                    eprintln!("======================");
-                    
-                    return Ok(Json(project.renderable()).into_response());
+                let file_name = field.file_name().unwrap_or("model").to_string();
+                let data = field
+                    .bytes()
+                    .await
+                    .map_err(|e| ApiError::new(ErrorCode::BadRequest, e.body_text()))?;
+
+                if name == "file" || name == "files" {
+                    eprintln!("Received file: {} ({} bytes)", file_name, data.len());
+                    upload.add_file(&file_name, &data).map_err(|e| {
+                        eprintln!("Upload error: {:?}", e);
+                        ApiError::import(&e)
+                    })?;
+                    has_files = true;
                 }
             }
             Ok(None) => break,
-            Err(_) => return Err(StatusCode::BAD_REQUEST),
+            Err(e) => return Err(ApiError::new(ErrorCode::BadRequest, e.body_text())),
         }
     }
-    Ok(StatusCode::OK.into_response())
+    if !has_files {
+        return Ok(None);
+    }
+    let model = upload.model().map_err(|e| {
+        eprintln!("Upload error: {:?}", e);
+        // There is no model, or there are too many of them
+        match ApiError::import(&e) {
+            err if err.code == ErrorCode::UnsupportedFormat => err,
+            _ => ApiError::with_chain(ErrorCode::BadRequest, &e),
+        }
+    })?;
+    let file_name = model
+        .file_name()
+        .map(|f| f.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    eprintln!("Attempting to import file: {}", upload.display(&model));
+    eprintln!("Temp path: {:?}", model);
+    Ok(Some(ReceivedModel {
+        upload,
+        model,
+        file_name,
+    }))
 }
 
-async fn get_project(State(state): State<Arc<Mutex<AppState>>>) -> Result<Json<RenderablePapercraft>, StatusCode> {
-    let state = state.lock().unwrap();
-    if let Some(ref project) = state.project {
-        Ok(Json(project.renderable()))
-    } else {
-        Err(StatusCode::NOT_FOUND)
-    }
+/// The files that an uploaded model uses but were not uploaded with it
+#[derive(Serialize)]
+struct MissingFiles {
+    /// Textures used by the model
+    missing_textures: Vec<String>,
+    /// Other files, such as the MTL of an OBJ
+    missing_files: Vec<String>,
 }
 
-async fn perform_action(
-    State(state): State<Arc<Mutex<AppState>>>,
-    Json(action): Json<Action>,
-) -> Result<Json<RenderablePapercraft>, StatusCode> {
-    let mut state = state.lock().unwrap();
-    if let Some(ref mut project) = state.project {
-        println!("Received action");
-        match action {
-            Action::ToggleFlap { edge, action } => {
-                println!("Action: ToggleFlap");
-                project.edge_toggle_flap(edge, action);
-            }
-            Action::Cut { edge, offset } => {
-                project.edge_cut(edge, offset);
-            }
-            Action::Join { edge, priority_face } => {
-                project.edge_join(edge, priority_face);
-            }
-            Action::MoveIsland { island, delta } => {
-                println!("Action: MoveIsland delta={:?}", delta);
-                if let Some(island) = project.island_by_key_mut(island) {
-                    println!("Found island, translating...");
-                    island.translate(Vector2::new(delta[0], delta[1]));
-                } else {
-                    println!("Island not found!");
-                }
-            }
-            Action::RotateIsland { island, angle, center } => {
-                if let Some(island) = project.island_by_key_mut(island) {
-                    island.rotate(Rad(angle), Vector2::new(center[0], center[1]));
-                }
+// Imports the model, reporting the progress to the job, if any.
+// It is slow, so it runs without the lock of the project.
+fn import_model(job: Option<&Job>, received: &ReceivedModel) -> Result<(Papercraft, MissingFiles)> {
+    let result = match job {
+        Some(job) => {
+            job.progress("Reading the model", 0.0)?;
+            paper::import::import_model_file_with_progress(&received.model, job.token(), &|p| {
+                // A cancellation is detected by the reader, too
+                let _ = job.progress("Reading the model", p);
+            })
+        }
+        None => paper::import::import_model_file_with_progress(
+            &received.model,
+            &CancellationToken::new(),
+            &|_| {},
+        ),
+    };
+    match result {
+        Ok(imported) => {
+            let papercraft = imported.papercraft;
+            let missing = MissingFiles {
+                missing_textures: paper::import::missing_textures(&papercraft),
+                missing_files: imported.missing_files,
+            };
+            eprintln!("=== Import Success ===");
+            eprintln!("File: {}", received.file_name);
+            eprintln!("Islands: {}", papercraft.islands().count());
+            if !missing.missing_textures.is_empty() {
+                eprintln!("Missing textures: {}", missing.missing_textures.join(", "));
             }
-            Action::SetOptions { options, relocate_pieces } => {
-                project.set_options(options, relocate_pieces);
+            if !missing.missing_files.is_empty() {
+                eprintln!("Missing files: {}", missing.missing_files.join(", "));
             }
-            Action::PackIslands => {
-                project.pack_islands();
+            eprintln!("======================");
+            Ok((papercraft, missing))
+        }
+        Err(e) => {
+            eprintln!("=== Import Error ===");
+            eprintln!("File: {}", received.file_name);
+            eprintln!("Temp path: {:?}", received.model);
+            eprintln!("Error: {:?}", e);
+            eprintln!("Error chain:");
+            for (i, cause) in e.chain().enumerate() {
+                eprintln!("  {}: {}", i, cause);
             }
+            eprintln!("====================");
+            Err(e)
         }
-        Ok(Json(project.renderable()))
+    }
+}
+
+#[derive(Serialize)]
+struct UploadResponse {
+    #[serde(flatten)]
+    project: RenderablePapercraft,
+    #[serde(flatten)]
+    missing: MissingFiles,
+}
+
+async fn upload_model(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<ProjectId>,
+    mut multipart: Multipart,
+) -> ApiResult<impl IntoResponse> {
+    let project_ref = state.project(id)?;
+    let Some(received) = receive_model(&mut multipart).await? else {
+        return Ok(StatusCode::OK.into_response());
+    };
+    let (papercraft, missing, file_name) = tokio::task::spawn_blocking(move || {
+        // The upload directory is removed once the import is done
+        import_model(None, &received)
+            .map(|(papercraft, missing)| (papercraft, missing, received.file_name))
+    })
+    .await
+    .map_err(|e| ApiError::new(ErrorCode::Internal, e.to_string()))?
+    .map_err(|e| ApiError::import(&e))?;
+
+    let response = UploadResponse {
+        project: papercraft.renderable(),
+        missing,
+    };
+    let mut project_ref = project_ref.lock().unwrap();
+    project_ref.name = file_name;
+    project_ref.set_papercraft(papercraft);
+    Ok(Json(response).into_response())
+}
+
+#[derive(Serialize)]
+struct UpdateModelResponse {
+    #[serde(flatten)]
+    upload: UploadResponse,
+    /// What could not be matched with the old model
+    report: UpdateReport,
+}
+
+// Replaces the model of the project with a new version of it, keeping the cuts, the
+// positions of the islands and the options as far as the two models match.
+async fn update_model(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<ProjectId>,
+    mut multipart: Multipart,
+) -> ApiResult<Json<UpdateModelResponse>> {
+    let project_ref = state.project(id)?;
+    if project_ref.lock().unwrap().papercraft.is_none() {
+        return Err(ApiError::no_model());
+    }
+    let received = receive_model(&mut multipart)
+        .await?
+        .ok_or_else(|| ApiError::new(ErrorCode::BadRequest, "No file uploaded"))?;
+    let (mut papercraft, missing) =
+        tokio::task::spawn_blocking(move || import_model(None, &received))
+            .await
+            .map_err(|e| ApiError::new(ErrorCode::Internal, e.to_string()))?
+            .map_err(|e| ApiError::import(&e))?;
+
+    // Matching the models is slow too, but the old one must not change meanwhile
+    tokio::task::spawn_blocking(move || -> ApiResult<_> {
+        let mut project_ref = project_ref.lock().unwrap();
+        let old = project_ref.papercraft.as_ref().ok_or_else(ApiError::no_model)?;
+        // The old model is only read, a panic leaves it as it was and the new one is dropped
+        let report = action::catch_panic(&mut papercraft, false, |p| p.update_from_obj(old))?;
+        eprintln!(
+            "Updated model: {} unmatched edges, {} unmatched islands",
+            report.unmatched_edges.len(),
+            report.unmatched_islands.len()
+        );
+        let upload = UploadResponse {
+            project: papercraft.renderable(),
+            missing,
+        };
+        project_ref.set_papercraft(papercraft);
+        Ok(Json(UpdateModelResponse { upload, report }))
+    })
+    .await
+    .map_err(|e| ApiError::new(ErrorCode::Internal, e.to_string()))?
+}
+
+async fn import_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<ProjectId>,
+    mut multipart: Multipart,
+) -> ApiResult<Json<JobStatus>> {
+    let project_ref = state.project(id)?;
+    let received = receive_model(&mut multipart)
+        .await?
+        .ok_or_else(|| ApiError::new(ErrorCode::BadRequest, "No file uploaded"))?;
+    let job = state.jobs.spawn(id, JobKind::Import, move |job| {
+        let (papercraft, missing) = import_model(Some(job), &received)?;
+        // A cancelled import must not replace the model
+        job.check()?;
+        let mut project_ref = project_ref.lock().unwrap();
+        project_ref.name = received.file_name.clone();
+        project_ref.set_papercraft(papercraft);
+        // The model itself is sent by the project
+        Ok(Some(JobOutput {
+            content_type: "application/json",
+            file_name: String::from("import.json"),
+            data: serde_json::to_vec(&missing)?.into(),
+        }))
+    });
+    Ok(Json(job.status()))
+}
+
+async fn get_project(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<ProjectId>,
+) -> ApiResult<Json<StateResponse>> {
+    let project_ref = state.project(id)?;
+    let project_ref = project_ref.lock().unwrap();
+    if let Some(ref project) = project_ref.papercraft {
+        Ok(Json(StateResponse {
+            revision: project_ref.revision,
+            renames: Vec::new(),
+            state: ProjectState::Full(project.renderable()),
+        }))
+    } else {
+        Err(ApiError::no_model())
+    }
+}
+
+async fn get_model(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<ProjectId>,
+    headers: axum::http::HeaderMap,
+) -> ApiResult<axum::response::Response> {
+    let project_ref = state.project(id)?;
+    let project_ref = project_ref.lock().unwrap();
+    let project = project_ref.papercraft.as_ref().ok_or_else(ApiError::no_model)?;
+
+    // The model never changes with the actions, the client can keep it while the version is
+    // the same
+    let etag = format!("\"{}\"", project.model_version());
+    let cached = headers
+        .get(axum::http::header::IF_NONE_MATCH)
+        .is_some_and(|v| v.as_bytes() == etag.as_bytes());
+    if cached {
+        return Ok((StatusCode::NOT_MODIFIED, [(axum::http::header::ETAG, etag)]).into_response());
+    }
+    Ok((
+        [
+            (axum::http::header::ETAG, etag),
+            (axum::http::header::CACHE_CONTROL, String::from("no-cache")),
+        ],
+        Json(project.model()),
+    )
+        .into_response())
+}
+
+async fn validate_project(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<ProjectId>,
+) -> ApiResult<Json<ValidationReport>> {
+    let project_ref = state.project(id)?;
+    let project_ref = project_ref.lock().unwrap();
+    if let Some(ref project) = project_ref.papercraft {
+        Ok(Json(project.validate()))
     } else {
-        Err(StatusCode::NOT_FOUND)
+        Err(ApiError::no_model())
     }
 }
 
+#[derive(Deserialize)]
+struct ActionParams {
+    // Answer with only what changed, the client keeps the rest
+    #[serde(default)]
+    delta: bool,
+    // Reject the edit if the project is no longer at this revision
+    revision: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ProjectState {
+    Full(RenderablePapercraft),
+    Delta(RenderableDelta),
+}
+
+#[derive(Serialize)]
+struct StateResponse {
+    revision: u64,
+    // The islands removed by the joins of the action, and where they went
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    renames: Vec<IslandRename>,
+    #[serde(flatten)]
+    state: ProjectState,
+}
+
+async fn edit_project(
+    state: &AppState,
+    id: ProjectId,
+    params: ActionParams,
+    edit: Edit,
+) -> ApiResult<Json<StateResponse>> {
+    let project_ref = state.project(id)?;
+    if !edit.is_slow() {
+        return edit_project_ref(&project_ref, params, edit);
+    }
+    // Unfolding or packing a big model takes seconds, with the project locked
+    tokio::task::spawn_blocking(move || edit_project_ref(&project_ref, params, edit))
+        .await
+        .map_err(|e| ApiError::new(ErrorCode::Internal, e.to_string()))?
+}
+
+fn edit_project_ref(
+    project_ref: &ProjectRef,
+    params: ActionParams,
+    edit: Edit,
+) -> ApiResult<Json<StateResponse>> {
+    let mut project_ref = project_ref.lock().unwrap();
+    let applied = live::apply_edit(&mut project_ref, edit, params.revision, params.delta)?;
+    let state = match applied.delta {
+        Some(delta) => ProjectState::Delta(delta),
+        // A successful edit means there is a model
+        None => ProjectState::Full(project_ref.papercraft.as_ref().unwrap().renderable()),
+    };
+    Ok(Json(StateResponse {
+        revision: applied.revision,
+        renames: applied.renames,
+        state,
+    }))
+}
+
+async fn perform_action(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<ProjectId>,
+    Query(params): Query<ActionParams>,
+    action: Result<Json<Action>, JsonRejection>,
+) -> ApiResult<Json<StateResponse>> {
+    let Json(action) = action.map_err(|e| ApiError::new(ErrorCode::BadRequest, e.body_text()))?;
+    edit_project(&state, id, params, Edit::Action { action }).await
+}
+
+async fn perform_actions(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<ProjectId>,
+    Query(params): Query<ActionParams>,
+    actions: Result<Json<Vec<Action>>, JsonRejection>,
+) -> ApiResult<Json<StateResponse>> {
+    let Json(actions) = actions.map_err(|e| ApiError::new(ErrorCode::BadRequest, e.body_text()))?;
+    edit_project(&state, id, params, Edit::Batch { actions }).await
+}
+
+async fn undo_action(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<ProjectId>,
+    Query(params): Query<ActionParams>,
+) -> ApiResult<Json<StateResponse>> {
+    edit_project(&state, id, params, Edit::Undo).await
+}
+
+async fn redo_action(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<ProjectId>,
+    Query(params): Query<ActionParams>,
+) -> ApiResult<Json<StateResponse>> {
+    edit_project(&state, id, params, Edit::Redo).await
+}
+
+async fn project_socket(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<ProjectId>,
+    ws: WebSocketUpgrade,
+) -> ApiResult<axum::response::Response> {
+    let project_ref = state.project(id)?;
+    Ok(ws.on_upgrade(move |socket| live::serve(socket, project_ref)))
+}
+
 async fn get_texture(
-    State(state): State<Arc<Mutex<AppState>>>,
-    Path(index): Path<usize>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let state = state.lock().unwrap();
-    let project = state.project.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    State(state): State<Arc<AppState>>,
+    Path((id, index)): Path<(ProjectId, usize)>,
+) -> ApiResult<impl IntoResponse> {
+    let project_ref = state.project(id)?;
+    let project_ref = project_ref.lock().unwrap();
+    let project = project_ref.papercraft.as_ref().ok_or_else(ApiError::no_model)?;
     
-    let texture = project.model().textures().nth(index).ok_or(StatusCode::NOT_FOUND)?;
-    let pixbuf = texture.pixbuf().ok_or(StatusCode::NOT_FOUND)?;
+    let texture = project.model().textures().nth(index)
+        .ok_or_else(|| ApiError::new(ErrorCode::NotFound, format!("Texture {} not found", index)))?;
+    let pixbuf = texture.pixbuf().ok_or_else(|| {
+        ApiError::new(ErrorCode::NotFound, format!("Texture {} has no image", texture.file_name()))
+    })?;
     
     let mut buffer = std::io::Cursor::new(Vec::new());
     pixbuf.write_to(&mut buffer, image::ImageFormat::Png)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| ApiError::new(ErrorCode::Internal, e.to_string()))?;
     
     Ok((
         [(axum::http::header::CONTENT_TYPE, "image/png")],
@@ -208,55 +618,232 @@ async fn get_texture(
     ))
 }
 
+// The name of the project, safe to use in a file name
+fn file_stem(name: &str) -> String {
+    let stem = std::path::Path::new(name)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("project");
+    stem.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+async fn download_craft(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<ProjectId>,
+) -> ApiResult<impl IntoResponse> {
+    let project_ref = state.project(id)?;
+    let project_ref = project_ref.lock().unwrap();
+    let project = project_ref.papercraft.as_ref().ok_or_else(ApiError::no_model)?;
+
+    let stem = file_stem(&project_ref.name);
+
+    let thumbnail = thumbnail::render_thumbnail(project);
+    let mut buffer = std::io::Cursor::new(Vec::new());
+    project
+        .save(&mut buffer, Some(thumbnail))
+        .map_err(|e| ApiError::internal(&e.context("Error saving the project")))?;
+
+    Ok((
+        [
+            (axum::http::header::CONTENT_TYPE, String::from("application/octet-stream")),
+            (axum::http::header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}.craft\"", stem)),
+        ],
+        buffer.into_inner(),
+    ))
+}
+
 #[derive(Deserialize)]
 struct ExportParams {
-    format: String,  // "svg" or "pdf"
-    page: Option<u32>,  // For SVG: specific page, None = all pages
+    format: String,  // "svg", "pdf" or "dxf"
+    page: Option<u32>,  // For SVG and DXF: specific page, None = all pages
     textures: Option<bool>,  // Whether to include textures in export
+    cut_only: Option<bool>,  // For SVG and DXF: only the outer contours, for print-and-cut
 }
 
-async fn export_file(
-    State(state): State<Arc<Mutex<AppState>>>,
-    Query(params): Query<ExportParams>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let state = state.lock().unwrap();
-    let project = state.project.as_ref().ok_or(StatusCode::NOT_FOUND)?;
-    
+impl ExportParams {
+    fn check_format(&self) -> ApiResult<()> {
+        match self.format.as_str() {
+            "pdf" if self.cut_only == Some(true) => Err(ApiError::new(
+                ErrorCode::BadRequest,
+                "The cut file can only be exported as SVG or DXF",
+            )),
+            "svg" | "pdf" | "dxf" => Ok(()),
+            format => Err(ApiError::new(
+                ErrorCode::BadRequest,
+                format!("Unknown export format {format}"),
+            )),
+        }
+    }
+}
+
+// A copy of the model, so that the export runs without the lock of the project
+fn export_source(project_ref: &ProjectRef) -> ApiResult<(Papercraft, String)> {
+    let project_ref = project_ref.lock().unwrap();
+    let project = project_ref.papercraft.as_ref().ok_or_else(ApiError::no_model)?;
+    Ok((project.clone(), project_ref.name.clone()))
+}
+
+fn export_data(
+    project: &Papercraft,
+    name: &str,
+    params: &ExportParams,
+    progress: vector_export::Progress,
+) -> Result<JobOutput> {
     let with_textures = params.textures.unwrap_or(false);
-    
-    match params.format.as_str() {
+    let cut_only = params.cut_only.unwrap_or(false);
+    let stem = file_stem(name);
+    let output = match params.format.as_str() {
+        // The cut file has a single page, the first one if not told
+        "svg" if cut_only => {
+            let page = params.page.unwrap_or(0);
+            let svg = vector_export::generate_svg_cut(project, page)
+                .inspect_err(|e| eprintln!("SVG export error: {}", e))?;
+            JobOutput {
+                content_type: "image/svg+xml",
+                file_name: format!("{}-cut-{}.svg", stem, page + 1),
+                data: svg.into(),
+            }
+        }
         "svg" => {
             let svg = if let Some(page) = params.page {
                 vector_export::generate_svg(project, page, with_textures)
             } else {
-                vector_export::generate_svg_multipage(project, with_textures)
+                vector_export::generate_svg_multipage_with_progress(project, with_textures, progress)
             };
-            
-            match svg {
-                Ok(content) => Ok((
-                    [(axum::http::header::CONTENT_TYPE, "image/svg+xml")],
-                    content,
-                ).into_response()),
-                Err(e) => {
-                    eprintln!("SVG export error: {}", e);
-                    Err(StatusCode::INTERNAL_SERVER_ERROR)
-                }
+            let svg = svg.inspect_err(|e| eprintln!("SVG export error: {}", e))?;
+            JobOutput {
+                content_type: "image/svg+xml",
+                file_name: format!("{}.svg", stem),
+                data: svg.into(),
             }
         }
         "pdf" => {
-            match vector_export::generate_pdf(project, with_textures) {
-                Ok(pdf_bytes) => Ok((
-                    [(axum::http::header::CONTENT_TYPE, "application/pdf")],
-                    pdf_bytes,
-                ).into_response()),
-                Err(e) => {
-                    eprintln!("PDF export error: {}", e);
-                    Err(StatusCode::INTERNAL_SERVER_ERROR)
-                }
+            let pdf = vector_export::generate_pdf_with_progress(project, with_textures, progress)
+                .inspect_err(|e| eprintln!("PDF export error: {}", e))?;
+            JobOutput {
+                content_type: "application/pdf",
+                file_name: format!("{}.pdf", stem),
+                data: pdf.into(),
             }
         }
-        _ => Err(StatusCode::BAD_REQUEST),
+        "dxf" => {
+            let dxf = if cut_only {
+                vector_export::generate_dxf_cut(project, params.page)
+            } else {
+                vector_export::generate_dxf(project, params.page)
+            };
+            let dxf = dxf.inspect_err(|e| eprintln!("DXF export error: {}", e))?;
+            let suffix = if cut_only { "-cut" } else { "" };
+            JobOutput {
+                content_type: "image/vnd.dxf",
+                file_name: format!("{}{}.dxf", stem, suffix),
+                data: dxf.into(),
+            }
+        }
+        format => anyhow::bail!("Unknown export format {format}"),
+    };
+    Ok(output)
+}
+
+async fn export_file(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<ProjectId>,
+    Query(params): Query<ExportParams>,
+) -> ApiResult<impl IntoResponse> {
+    let project_ref = state.project(id)?;
+    let (project, name) = export_source(&project_ref)?;
+    params.check_format()?;
+
+    let output = tokio::task::spawn_blocking(move || {
+        export_data(&project, &name, &params, &mut |_| Ok(()))
+    })
+    .await
+    .map_err(|e| ApiError::new(ErrorCode::Internal, e.to_string()))?
+    .map_err(|e| ApiError::export(&e))?;
+    Ok((
+        [(axum::http::header::CONTENT_TYPE, output.content_type)],
+        output.data,
+    ))
+}
+
+async fn export_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<ProjectId>,
+    Query(params): Query<ExportParams>,
+) -> ApiResult<Json<JobStatus>> {
+    let project_ref = state.project(id)?;
+    let (project, name) = export_source(&project_ref)?;
+    params.check_format()?;
+
+    let job = state.jobs.spawn(id, JobKind::Export, move |job| {
+        job.progress("Writing the pages", 0.0)?;
+        let output = export_data(&project, &name, &params, &mut |p| {
+            job.progress("Writing the pages", p)
+        })?;
+        Ok(Some(output))
+    });
+    Ok(Json(job.status()))
+}
+
+async fn get_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<JobId>,
+) -> ApiResult<Json<JobStatus>> {
+    Ok(Json(state.job(id)?.status()))
+}
+
+// Sends the status of the job every time it changes, until it finishes
+async fn job_events(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<JobId>,
+) -> ApiResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    let rx = state.job(id)?.subscribe();
+    let events = stream::unfold((Some(rx), true), |(rx, first)| async move {
+        let mut rx = rx?;
+        // If the job is removed the sender is dropped, and the stream ends
+        if !first && rx.changed().await.is_err() {
+            return None;
+        }
+        let status = rx.borrow_and_update().clone();
+        let event = Event::default().event("status").json_data(&status).ok()?;
+        let rx = (!status.is_finished()).then_some(rx);
+        Some((Ok(event), (rx, false)))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+async fn job_result(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<JobId>,
+) -> ApiResult<impl IntoResponse> {
+    let job = state.job(id)?;
+    if !job.status().is_finished() {
+        return Err(ApiError::new(ErrorCode::JobRunning, "The job has not finished yet"));
     }
+    let output = job
+        .output()
+        .ok_or_else(|| ApiError::new(ErrorCode::NotFound, "The job has no result"))?;
+    // Downloaded once, it is not needed anymore
+    state.jobs.remove(id);
+    Ok((
+        [
+            (axum::http::header::CONTENT_TYPE, String::from(output.content_type)),
+            (axum::http::header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", output.file_name)),
+        ],
+        output.data.clone(),
+    ))
+}
+
+async fn delete_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<JobId>,
+) -> ApiResult<StatusCode> {
+    if !state.jobs.remove(id) {
+        return Err(ApiError::new(ErrorCode::JobNotFound, "Job not found"));
+    }
+    Ok(StatusCode::NO_CONTENT)
 }
 
 #[tokio::main]
@@ -280,6 +867,12 @@ async fn main() {
                 }
             }
         }
+        Some(Commands::Export(args)) => {
+            if let Err(e) = export_command(args) {
+                eprintln!("Export error: {:?}", e);
+                std::process::exit(1);
+            }
+        }
         Some(Commands::Serve { port }) => {
             serve(port).await;
         }
@@ -289,14 +882,62 @@ async fn main() {
     }
 }
 
+fn export_command(args: ExportArgs) -> Result<()> {
+    let all_pages = matches!(args.format, ExportFormat::Pdf | ExportFormat::SvgMultipage);
+    if args.page.is_some() && all_pages {
+        anyhow::bail!("--page is only for the svg and dxf formats");
+    }
+    let (mut project, _) = paper::import::import_model_file(&args.path)?;
+
+    let mut options = project.options().clone();
+    if let Some(scale) = args.scale {
+        options.scale = scale;
+    }
+    if let Some(page_size) = args.page_size {
+        options.page_size = page_size;
+    }
+    if let Some(margins) = args.margins {
+        options.margin = margins;
+    }
+    if let Some(pdf_font) = args.pdf_font {
+        options.pdf_font = Some(pdf_font);
+    }
+    if options != *project.options() {
+        project.set_options(options, true);
+    }
+    if args.auto_unfold {
+        project.auto_unfold();
+    } else if args.pack {
+        let mut options = project.options().clone();
+        options.pages = project.pack_islands();
+        project.set_options(options, false);
+    }
+
+    let data = match args.format {
+        ExportFormat::Pdf => vector_export::generate_pdf(&project, args.textures)?,
+        ExportFormat::Svg => {
+            let page = args.page.unwrap_or(0);
+            vector_export::generate_svg(&project, page, args.textures)?.into_bytes()
+        }
+        ExportFormat::SvgMultipage => {
+            vector_export::generate_svg_multipage(&project, args.textures)?.into_bytes()
+        }
+        ExportFormat::Dxf => vector_export::generate_dxf(&project, args.page)?.into_bytes(),
+    };
+    std::fs::write(&args.out, data)
+        .with_context(|| format!("Error writing file {}", args.out.display()))?;
+    println!("Exported {} pages to {:?}", project.options().pages, args.out);
+    Ok(())
+}
+
 async fn serve(port: u16) {
-    let mut initial_project = None;
+    let projects = ProjectStore::new();
     let sphere_path = std::path::Path::new("examples/sphere.pdo");
     if sphere_path.exists() {
         println!("Loading default model: {:?}", sphere_path);
         match paper::import::import_model_file(sphere_path) {
             Ok((project, _)) => {
-                initial_project = Some(project);
+                projects.create(Project::new(String::from("sphere.pdo"), Some(project)));
             }
             Err(e) => {
                 eprintln!("Failed to load default model: {}", e);
@@ -304,15 +945,40 @@ async fn serve(port: u16) {
         }
     }
 
-    let state = Arc::new(Mutex::new(AppState { project: initial_project }));
+    let state = Arc::new(AppState { projects, jobs: JobStore::new() });
+    // Forget the finished jobs whose result nobody downloaded
+    tokio::spawn({
+        let state = state.clone();
+        async move {
+            let mut interval = tokio::time::interval(JOB_TTL / 10);
+            loop {
+                interval.tick().await;
+                state.jobs.expire(JOB_TTL);
+            }
+        }
+    });
 
     let app = Router::new()
         .route("/api/status", get(get_status))
-        .route("/api/upload", post(upload_model))
-        .route("/api/project", get(get_project))
-        .route("/api/action", post(perform_action))
-        .route("/api/export", get(export_file))
-        .route("/api/texture/:index", get(get_texture))
+        .route("/api/projects", get(list_projects).post(create_project))
+        .route("/api/projects/:id", get(get_project).delete(delete_project))
+        .route("/api/projects/:id/upload", post(upload_model))
+        .route("/api/projects/:id/update", post(update_model))
+        .route("/api/projects/:id/model", get(get_model))
+        .route("/api/projects/:id/craft", get(download_craft))
+        .route("/api/projects/:id/validate", get(validate_project))
+        .route("/api/projects/:id/action", post(perform_action))
+        .route("/api/projects/:id/actions", post(perform_actions))
+        .route("/api/projects/:id/undo", post(undo_action))
+        .route("/api/projects/:id/redo", post(redo_action))
+        .route("/api/projects/:id/live", get(project_socket))
+        .route("/api/projects/:id/export", get(export_file))
+        .route("/api/projects/:id/texture/:index", get(get_texture))
+        .route("/api/projects/:id/jobs/import", post(import_job))
+        .route("/api/projects/:id/jobs/export", post(export_job))
+        .route("/api/jobs/:id", get(get_job).delete(delete_job))
+        .route("/api/jobs/:id/events", get(job_events))
+        .route("/api/jobs/:id/result", get(job_result))
         .layer(DefaultBodyLimit::max(50 * 1024 * 1024)) // 50MB
         .layer(CorsLayer::permissive())
         .with_state(state);
@@ -323,3 +989,88 @@ async fn serve(port: u16) {
     axum::serve(listener, app).await.unwrap();
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_page_size() {
+        assert_eq!(parse_page_size("210x297").unwrap(), (210.0, 297.0));
+        assert_eq!(parse_page_size(" 8.5 X 11 ").unwrap(), (8.5, 11.0));
+        assert!(parse_page_size("210").is_err());
+        assert!(parse_page_size("210x").is_err());
+        assert!(parse_page_size("axb").is_err());
+    }
+
+    #[test]
+    fn test_parse_margins() {
+        assert_eq!(parse_margins("10").unwrap(), (10.0, 10.0, 10.0, 10.0));
+        assert_eq!(
+            parse_margins("5, 10,15 ,20").unwrap(),
+            (5.0, 10.0, 15.0, 20.0)
+        );
+        assert!(parse_margins("").is_err());
+        assert!(parse_margins("1,2").is_err());
+        assert!(parse_margins("1,2,3,4,5").is_err());
+        assert!(parse_margins("1,two,3,4").is_err());
+    }
+
+    #[test]
+    fn test_export_args() {
+        let cli = Cli::try_parse_from([
+            "papercraft",
+            "export",
+            "model.pdo",
+            "--out",
+            "model.pdf",
+            "--margins",
+            "5",
+            "--page-size",
+            "100x150",
+        ])
+        .unwrap();
+        let Some(Commands::Export(args)) = cli.command else {
+            panic!("Expected the export command");
+        };
+        assert_eq!(args.margins, Some((5.0, 5.0, 5.0, 5.0)));
+        assert_eq!(args.page_size, Some((100.0, 150.0)));
+        assert_eq!(args.page, None);
+
+        // A page of a DXF, but not of the formats with all the pages
+        let export = |format: &str| {
+            let cli = Cli::try_parse_from([
+                "papercraft",
+                "export",
+                "no-such-model.pdo",
+                "-o",
+                "m.out",
+                "--format",
+                format,
+                "--page",
+                "2",
+            ])
+            .unwrap();
+            let Some(Commands::Export(args)) = cli.command else {
+                panic!("Expected the export command");
+            };
+            assert_eq!(args.page, Some(2));
+            export_command(args).unwrap_err().to_string()
+        };
+        assert!(export("pdf").contains("--page"));
+        assert!(export("svg-multipage").contains("--page"));
+        // It gets as far as reading the model
+        assert!(!export("dxf").contains("--page"));
+
+        let res = Cli::try_parse_from([
+            "papercraft",
+            "export",
+            "m.pdo",
+            "-o",
+            "m.pdf",
+            "--margins",
+            "1,2",
+        ]);
+        assert!(res.is_err());
+    }
+}
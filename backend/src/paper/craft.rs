@@ -13,8 +13,19 @@ use serde::{Deserialize, Serialize};
 use slotmap::{SlotMap, new_key_type};
 
 use super::*;
+mod delta;
 mod file;
+mod labels;
+mod marks;
+mod pack;
+mod unfold;
 mod update;
+mod validate;
+
+pub use delta::DeltaBase;
+pub use unfold::CutLayout;
+pub use update::UpdateReport;
+pub use validate::*;
 
 // Which side of a cut will the flap be drawn, compare with face_sign
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -24,7 +35,7 @@ pub enum FlapSide {
     Hidden,
 }
 
-#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug)]
 pub enum EdgeToggleFlapAction {
     Toggle,
     Hide,
@@ -144,6 +155,14 @@ fn default_edge_id_font_size() -> f32 {
     8.0
 }
 
+fn default_pack_gap() -> f32 {
+    2.0
+}
+
+fn default_pack_rotations() -> u32 {
+    4
+}
+
 fn default_scene_bg_color() -> MyColor {
     MyColor(Color::new(0.2, 0.2, 0.4, 1.0))
 }
@@ -276,6 +295,18 @@ pub struct PaperOptions {
     pub edge_id_position: EdgeIdPosition,
     #[serde(default)]
     pub island_name_only: bool,
+    #[serde(default = "default_pack_gap")]
+    pub pack_gap: f32, //mm between islands when packing
+    #[serde(default = "default_pack_rotations")]
+    pub pack_rotations: u32, //angles tried for each island when packing
+    #[serde(default)]
+    pub registration_marks: bool, //for print-and-cut, see `registration_mark_rects()`
+    #[serde(default)]
+    pub cut_offset: f32, //mm the cut contours are moved outwards, inwards if negative (kerf)
+    #[serde(default)]
+    pub bleed: f32, //mm the printed faces go past the cut edges
+    #[serde(default)]
+    pub pdf_font: Option<String>, //TrueType font of the PDF texts, see `pdf_font::TrueTypeFont::find()`
     #[serde(default = "default_line3d_normal")]
     pub line3d_normal: LineConfig,
     #[serde(default = "default_line3d_rim")]
@@ -324,6 +355,12 @@ impl Default for PaperOptions {
             edge_id_font_size: default_edge_id_font_size(),
             edge_id_position: EdgeIdPosition::default(),
             island_name_only: false,
+            pack_gap: default_pack_gap(),
+            pack_rotations: default_pack_rotations(),
+            registration_marks: false,
+            cut_offset: 0.0,
+            bleed: 0.0,
+            pdf_font: None,
             line3d_normal: default_line3d_normal(),
             line3d_rim: default_line3d_rim(),
             line3d_rim_tab: default_line3d_rim_tab(),
@@ -437,6 +474,12 @@ impl EdgeId {
     }
 }
 
+impl From<EdgeId> for u32 {
+    fn from(id: EdgeId) -> u32 {
+        id.0.get()
+    }
+}
+
 impl std::fmt::Display for EdgeId {
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         self.0.get().fmt(fmt)
@@ -487,6 +530,9 @@ struct Memoization {
 
     // This depends on the islands, but not on the options
     island_perimeters: Mutex<FxHashMap<IslandKey, Arc<[FlapEdgeData]>>>,
+
+    // This depends only on the model, that never changes
+    model_version: Mutex<Option<String>>,
 }
 
 impl Clone for Memoization {
@@ -768,6 +814,7 @@ impl Papercraft {
             rot: Rad(mx[0][1].atan2(mx[0][0])),
             mx: Matrix3::one(),
             name: String::new(),
+            locked: false,
         };
         new_island.recompute_matrix();
 
@@ -976,6 +1023,116 @@ impl Papercraft {
             }
         }
     }
+    // Returns the outline of the flap drawn at `i_edge` beside `i_face`, if there is any visible.
+    // `pos0` and `pos1` are the ends of the edge, in the order of the face, and the flap is
+    // returned in the same coordinates. For triangular flaps the last two vertices are the same.
+    pub fn flap_outline(
+        &self,
+        i_face: FaceIndex,
+        i_edge: EdgeIndex,
+        pos0: Vector2,
+        pos1: Vector2,
+    ) -> Option<[Vector2; 4]> {
+        let EdgeStatus::Cut(flap_side) = self.edge_status(i_edge) else {
+            return None;
+        };
+        let edge = &self.model[i_edge];
+        let maybe_i_face_b = match edge.faces() {
+            (fa, Some(fb)) if i_face == fb => Some(fa),
+            (fa, Some(fb)) if i_face == fa => Some(fb),
+            (_, None) => None,
+            _ => return None,
+        };
+        let visible = if self.options.flap_style == FlapStyle::None {
+            false
+        } else if self.options.flap_double && maybe_i_face_b.is_some() {
+            // Double flaps are visible in both sides, except for rims
+            true
+        } else {
+            flap_side.flap_visible(edge.face_sign(i_face))
+        };
+        let v = pos1 - pos0;
+        let v_len = v.magnitude();
+        if !visible || v_len < f32::EPSILON {
+            return None;
+        }
+
+        let FlapGeom {
+            tan_0,
+            tan_1,
+            width,
+            triangular,
+        } = self.flat_face_flap_dimensions(i_face, maybe_i_face_b, i_edge);
+        let vn = v * (width / v_len);
+        let n = Vector2::new(-vn.y, vn.x);
+        let mut p = [pos0, pos0 + n + vn * tan_1, pos1 + n - vn * tan_0, pos1];
+        if triangular {
+            p[2] = p[3];
+        }
+        Some(p)
+    }
+    // The visible flaps of an island, in paper coordinates, with the face and the edge each one
+    // is attached to. Triangular flaps have only three vertices.
+    pub fn island_flaps(&self, island: &Island) -> Vec<(FaceIndex, EdgeIndex, Vec<Vector2>)> {
+        let scale = self.options.scale;
+        let mut flaps = Vec::new();
+        let _ = self.traverse_faces(island, |i_face, face, mx| {
+            let plane = self.model.face_plane(face);
+            let to_paper = |i_v: VertexIndex| {
+                let p = plane.project(&self.model[i_v].pos(), scale);
+                mx.transform_point(Point2::from_vec(p)).to_vec()
+            };
+            for (i_v0, i_v1, i_edge) in face.vertices_with_edges() {
+                let Some(p) = self.flap_outline(i_face, i_edge, to_paper(i_v0), to_paper(i_v1))
+                else {
+                    continue;
+                };
+                let mut vertices = p.to_vec();
+                if p[2] == p[3] {
+                    vertices.remove(2);
+                }
+                flaps.push((i_face, i_edge, vertices));
+            }
+            ControlFlow::Continue(())
+        });
+        flaps
+    }
+    // Calls `f` with every triangle of paper in the island, the faces and the visible flaps,
+    // using `mx` as the island transformation. The flaps are split in triangles, along with
+    // the edge they are attached to.
+    pub fn island_paper_triangles(
+        &self,
+        island: &Island,
+        mx: Matrix3,
+        mut f: impl FnMut(FaceIndex, Option<EdgeIndex>, [Vector2; 3]),
+    ) {
+        let scale = self.options.scale;
+        let _ = traverse_faces_ex(
+            &self.model,
+            island.root_face(),
+            mx,
+            NormalTraverseFace(self),
+            |i_face, face, mx| {
+                let plane = self.model.face_plane(face);
+                let to_paper = |i_v: VertexIndex| {
+                    let p = plane.project(&self.model[i_v].pos(), scale);
+                    mx.transform_point(Point2::from_vec(p)).to_vec()
+                };
+                f(i_face, None, face.index_vertices().map(to_paper));
+                for (i_v0, i_v1, i_edge) in face.vertices_with_edges() {
+                    let Some(p) = self.flap_outline(i_face, i_edge, to_paper(i_v0), to_paper(i_v1))
+                    else {
+                        continue;
+                    };
+                    f(i_face, Some(i_edge), [p[0], p[1], p[2]]);
+                    if p[2] != p[3] {
+                        f(i_face, Some(i_edge), [p[0], p[2], p[3]]);
+                    }
+                }
+                ControlFlow::Continue(())
+            },
+        );
+    }
     pub fn island_edges(&self, island: &Island) -> FxHashSet<EdgeIndex> {
         let mut res = FxHashSet::default();
         let _ = self.traverse_faces_no_matrix(island, |i_f| {
@@ -1258,150 +1415,153 @@ impl Papercraft {
     }
 
     pub fn renderable(&self) -> RenderablePapercraft {
-        let mut islands = Vec::new();
-        let scale = self.options.scale;
+        let islands = self
+            .islands
+            .iter()
+            .map(|(id, island)| self.renderable_island(id, island))
+            .collect();
 
-        for (id, island) in &self.islands {
-            let mut renderable_faces = Vec::new();
+        RenderablePapercraft {
+            model: self.model.clone(),
+            model_version: self.model_version(),
+            islands,
+            options: self.options.clone(),
+        }
+    }
 
-            let mut renderable_edges = Vec::new();
-            let mut renderable_flaps = Vec::new();
+    pub fn renderable_island(&self, id: IslandKey, island: &Island) -> RenderableIsland {
+        let scale = self.options.scale;
+        let mut renderable_faces = Vec::new();
 
-            // We use traverse_faces_ex with island.matrix() to get GLOBAL coordinates (on the paper)
-            let _ = traverse_faces_ex(
-                &self.model,
-                island.root_face(),
-                island.matrix(),
-                NormalTraverseFace(self),
-                |i_face, face, mx| {
-                    let plane = self.model.face_plane(face);
-                    let mut vertices = Vec::new();
+        let mut renderable_edges = Vec::new();
+        let mut labels: FxHashMap<_, _> = self
+            .island_edge_labels(island)
+            .into_iter()
+            .map(|label| ((label.i_face, label.i_edge), label))
+            .collect();
+
+        // We use traverse_faces_ex with island.matrix() to get GLOBAL coordinates (on the paper)
+        let _ = traverse_faces_ex(
+            &self.model,
+            island.root_face(),
+            island.matrix(),
+            NormalTraverseFace(self),
+            |i_face, face, mx| {
+                let plane = self.model.face_plane(face);
+                let mut vertices = Vec::new();
+                
+                for i_vertex in face.index_vertices() {
+                    let vertex = &self.model[i_vertex];
+                    let v2d = plane.project(&vertex.pos(), scale);
+                    let p = mx.transform_point(Point2::from_vec(v2d));
+                    vertices.push(Vector2::new(p.x, p.y));
+                }
+                
+                renderable_faces.push(RenderableFace {
+                    id: i_face,
+                    vertices: vertices.clone(),
+                    m: usize::from(face.material()) as u32,
+                    vs: face.index_vertices().iter().map(|v| usize::from(*v) as u32).collect(),
+                });
+
+                // Collect edges for this face
+                for i_edge in face.index_edges() {
+                    let edge = &self.model[i_edge];
+                    // Get vertices from face context
+                    let (v1, v2) = face.vertices_of_edge(i_edge).unwrap();
                     
-                    for i_vertex in face.index_vertices() {
-                        let vertex = &self.model[i_vertex];
-                        let v2d = plane.project(&vertex.pos(), scale);
-                        let p = mx.transform_point(Point2::from_vec(v2d));
-                        vertices.push(Vector2::new(p.x, p.y));
-                    }
+                    let p1_2d = plane.project(&self.model[v1].pos(), scale);
+                    let p2_2d = plane.project(&self.model[v2].pos(), scale);
                     
-                    renderable_faces.push(RenderableFace {
-                        id: i_face,
-                        vertices: vertices.clone(),
-                        m: usize::from(face.material()) as u32,
-                        vs: face.index_vertices().iter().map(|v| usize::from(*v) as u32).collect(),
-                    });
+                    let gp1 = mx.transform_point(Point2::from_vec(p1_2d));
+                    let gp2 = mx.transform_point(Point2::from_vec(p2_2d));
+                    
+                    let p1 = Vector2::new(gp1.x, gp1.y);
+                    let p2 = Vector2::new(gp2.x, gp2.y);
+                    
+                    let edge_status = self.edge_status(i_edge);
+                    let is_cut = matches!(edge_status, EdgeStatus::Cut(_));
 
-                    // Collect edges and flaps for this face
-                    for i_edge in face.index_edges() {
-                        let edge = &self.model[i_edge];
-                        // Get vertices from face context
-                        let (v1, v2) = face.vertices_of_edge(i_edge).unwrap();
-                        
-                        let p1_2d = plane.project(&self.model[v1].pos(), scale);
-                        let p2_2d = plane.project(&self.model[v2].pos(), scale);
-                        
-                        let gp1 = mx.transform_point(Point2::from_vec(p1_2d));
-                        let gp2 = mx.transform_point(Point2::from_vec(p2_2d));
-                        
-                        let p1 = Vector2::new(gp1.x, gp1.y);
-                        let p2 = Vector2::new(gp2.x, gp2.y);
+                    // Determine edge kind (string for frontend)
+                    let kind = if is_cut {
+                         "cut".to_string()
+                    } else {
+                        // Check fold angle
+                        let angle = edge.angle();
+                        // Flat is PI (180 deg)
+                        let flat = Rad::full_turn() / 2.0;
+                        let diff = (angle - flat).0;
                         
-                        let edge_status = self.edge_status(i_edge);
-                        let is_cut = matches!(edge_status, EdgeStatus::Cut(_));
-
-                        // Determine edge kind (string for frontend)
-                        let kind = if is_cut {
-                             "cut".to_string()
+                        if diff > 0.1 {
+                            "mountain".to_string()
+                        } else if diff < -0.1 {
+                            "valley".to_string()
                         } else {
-                            // Check fold angle
-                            let angle = edge.angle();
-                            // Flat is PI (180 deg)
-                            let flat = Rad::full_turn() / 2.0;
-                            let diff = (angle - flat).0;
-                            
-                            if diff > 0.1 {
-                                "mountain".to_string()
-                            } else if diff < -0.1 {
-                                "valley".to_string()
-                            } else {
-                                "flat".to_string()
-                            }
-                        };
-                        
-                        // Deduplicate: Emit shared edges only once?
-                        // For Cut edges (boundary), we only visit one side (the visible one for the island).
-                        // For Joined edges, we visit both faces A and B.
-                        // We can use generic logic: only emit if i_face < other_face, OR if boundary.
-                        let should_emit = if is_cut {
-                            true
+                            "flat".to_string()
+                        }
+                    };
+                    
+                    // Deduplicate: Emit shared edges only once?
+                    // For Cut edges (boundary), we only visit one side (the visible one for the island).
+                    // For Joined edges, we visit both faces A and B.
+                    // We can use generic logic: only emit if i_face < other_face, OR if boundary.
+                    let should_emit = if is_cut {
+                        true
+                    } else {
+                        let (f1, f2) = edge.faces();
+                        let other = if f1 == i_face { f2 } else { Some(f1) };
+                        if let Some(o) = other {
+                            i_face < o
                         } else {
-                            let (f1, f2) = edge.faces();
-                            let other = if f1 == i_face { f2 } else { Some(f1) };
-                            if let Some(o) = other {
-                                i_face < o
-                            } else {
-                                true
-                            }
-                        };
-
-                        if should_emit {
-                             renderable_edges.push(RenderableEdge {
-                                id: i_edge,
-                                start: p1,
-                                end: p2,
-                                kind,
-                            });
+                            true
                         }
-
-                        // Flaps
-                        if let EdgeStatus::Cut(flap_side) = edge_status {
-                             let is_visible = flap_side.flap_visible(edge.face_sign(i_face));
-
-                            if is_visible {
-                                let edge_vec = p2 - p1;
-                                let edge_len = edge_vec.magnitude();
-                                if edge_len > 0.001 {
-                                    // Check CCW direction to orient flap OUTwards
-                                    // p1->p2 is based on (v1, v2) returned by face.vertices_of_edge(i_edge).
-                                    // face.vertices_of_edge returns (v_current, v_next) in CCW order.
-                                    // So p1->p2 is CCW along the face perimeter.
-                                    // For CCW winding, perpendicular pointing OUTWARD is (-y, x)
-                                    
-                                    let normal = Vector2::new(-edge_vec.y, edge_vec.x).normalize();
-                                    
-                                    let flap_width = self.options.flap_width.min(edge_len * 0.4);
-                                    let taper = 0.15;
-                                    
-                                    let f0 = p1 + normal * flap_width + edge_vec.normalize() * (edge_len * taper);
-                                    let f1 = p2 + normal * flap_width - edge_vec.normalize() * (edge_len * taper);
-                                    
-                                    renderable_flaps.push(RenderableFlap {
-                                        id: i_face,
-                                        vertices: vec![p1, p2, f1, f0],
-                                    });
+                    };
+
+                    if should_emit {
+                         renderable_edges.push(RenderableEdge {
+                            id: i_edge,
+                            start: p1,
+                            end: p2,
+                            kind,
+                            label: labels.remove(&(i_face, i_edge)).map(|label| {
+                                RenderableEdgeLabel {
+                                    id: label.id.into(),
+                                    text: label.text,
+                                    partner_island: label.partner_island,
+                                    partner_face: label.partner_face,
+                                    pos: label.pos,
+                                    angle: label.angle.0,
                                 }
-                            }
-                        }
+                            }),
+                        });
                     }
-
-                    ControlFlow::Continue(())
                 }
-            );
 
-            islands.push(RenderableIsland {
-                id,
-                pos: island.location(),
-                rot: island.rotation().0,
-                faces: renderable_faces,
-                edges: renderable_edges,
-                flaps: renderable_flaps,
-            });
-        }
+                ControlFlow::Continue(())
+            }
+        );
 
-        RenderablePapercraft {
-            model: self.model.clone(),
-            islands,
-            options: self.options.clone(),
+        // Flaps are computed as in the printed output
+        let renderable_flaps = self
+            .island_flaps(island)
+            .into_iter()
+            .map(|(i_face, i_edge, vertices)| RenderableFlap {
+                id: i_face,
+                edge: i_edge,
+                vertices,
+            })
+            .collect();
+
+        RenderableIsland {
+            id,
+            pos: island.location(),
+            rot: island.rotation().0,
+            locked: island.is_locked(),
+            name: island.name().to_string(),
+            name_pos: self.island_name_position(island),
+            faces: renderable_faces,
+            edges: renderable_edges,
+            flaps: renderable_flaps,
         }
     }
 
@@ -1499,66 +1659,6 @@ impl Papercraft {
         renames
     }
 
-    pub fn pack_islands(&mut self) -> u32 {
-        let mut row_height = 0.0f32;
-        let mut pos_x = 0.0;
-        let mut pos_y = 0.0;
-        let mut num_in_row = 0;
-
-        let mut page = 0;
-        let page_margin = Vector2::new(self.options.margin.1, self.options.margin.0);
-        let page_size = Vector2::new(
-            self.options.page_size.0 - self.options.margin.1 - self.options.margin.2,
-            self.options.page_size.1 - self.options.margin.0 - self.options.margin.3,
-        );
-        let mut zero = self.options().page_position(page) + page_margin;
-
-        // The island position cannot be updated while iterating
-        let mut positions = slotmap::SecondaryMap::<IslandKey, (Rad<f32>, Vector2)>::new();
-
-        let mut ordered_islands: Vec<_> = self
-            .islands
-            .iter()
-            .map(|(i_island, island)| {
-                let (angle, bbox) = self.island_best_bounding_box(island);
-                (i_island, angle, bbox)
-            })
-            .collect();
-        ordered_islands.sort_by_key(|(_, _, bbox)| {
-            let w = bbox.1.x - bbox.0.x;
-            let h = bbox.1.y - bbox.0.y;
-            -(w * h) as i64
-        });
-
-        for (i_island, angle, bbox) in ordered_islands {
-            let mut next_pos_x = pos_x + bbox.1.x - bbox.0.x;
-            if next_pos_x > page_size.x && num_in_row > 0 {
-                pos_x = 0.0;
-                pos_y += row_height;
-                row_height = 0.0;
-                num_in_row = 0;
-                if pos_y > page_size.y {
-                    pos_y = 0.0;
-                    page += 1;
-                    zero = self.options().page_position(page) + page_margin;
-                }
-                next_pos_x = bbox.1.x - bbox.0.x;
-            }
-            let pos = Vector2::new(pos_x - bbox.0.x, pos_y - bbox.0.y);
-            pos_x = next_pos_x;
-            row_height = row_height.max(bbox.1.y - bbox.0.y);
-            num_in_row += 1;
-
-            positions.insert(i_island, (angle, zero + pos));
-        }
-        for (i_island, (angle, pos)) in positions {
-            let island = self.island_by_key_mut(i_island).unwrap();
-            island.loc = pos; // Direct assignment instead of +=
-            island.rot = angle; // Direct assignment instead of +=
-            island.recompute_matrix();
-        }
-        page + 1
-    }
     // Returns the ((face, area), total_area)
     pub fn get_biggest_flat_face(&self, island: &Island) -> (Vec<(FaceIndex, f32)>, f32) {
         let mut biggest_face = None;
@@ -1931,6 +2031,8 @@ pub struct Island {
     loc: Vector2,
     mx: Matrix3,
     name: String,
+    // Locked islands are not moved when packing
+    locked: bool,
 }
 
 impl Island {
@@ -1972,6 +2074,12 @@ impl Island {
     pub fn name(&self) -> &str {
         &self.name
     }
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+    pub fn set_locked(&mut self, locked: bool) {
+        self.locked = locked;
+    }
 }
 
 impl Serialize for EdgeStatus {
@@ -2110,11 +2218,16 @@ impl Serialize for Island {
     where
         S: serde::Serializer,
     {
-        let mut map = serializer.serialize_struct("Island", 4)?;
+        let mut map = serializer.serialize_struct("Island", 5)?;
         map.serialize_field("root", &usize::from(self.root))?;
         map.serialize_field("x", &self.loc.x)?;
         map.serialize_field("y", &self.loc.y)?;
         map.serialize_field("r", &self.rot.0)?;
+        if self.locked {
+            map.serialize_field("locked", &self.locked)?;
+        } else {
+            map.skip_field("locked")?;
+        }
         map.end()
     }
 }
@@ -2130,6 +2243,8 @@ impl<'de> Deserialize<'de> for Island {
             x: f32,
             y: f32,
             r: f32,
+            #[serde(default)]
+            locked: bool,
         }
         let d = Def::deserialize(deserializer)?;
         let mut island = Island {
@@ -2138,6 +2253,7 @@ impl<'de> Deserialize<'de> for Island {
             rot: Rad(d.r),
             mx: Matrix3::one(),
             name: String::new(),
+            locked: d.locked,
         };
         island.recompute_matrix();
         Ok(island)
@@ -0,0 +1,131 @@
+//! The changes in the rendering of a papercraft after an action.
+//!
+//! The actions never change the model, so the client downloads it once and keeps it by its
+//! version. After an action it gets only the islands that look different, the edges with a new
+//! status and the options, if they changed.
+//!
+//! Rendering every island to compare them would be too slow for big models, so the caller says
+//! which islands the action changed, see `action::changed_islands()`.
+
+use super::*;
+
+/// The islands, edges and options before an action, to compare with the papercraft after it.
+pub struct DeltaBase {
+    islands: FxHashSet<IslandKey>,
+    edges: Vec<EdgeStatus>,
+    options: PaperOptions,
+}
+
+impl Papercraft {
+    /// A hash of the model, for the client to know if the one it has is still valid
+    pub fn model_version(&self) -> String {
+        let mut memo = self.memo.model_version.lock().unwrap();
+        memo.get_or_insert_with(|| {
+            let json = serde_json::to_vec(&self.model).unwrap();
+            format!("{:016x}", fxhash::hash64(&json))
+        })
+        .clone()
+    }
+    pub fn delta_base(&self) -> DeltaBase {
+        DeltaBase {
+            islands: self.islands.keys().collect(),
+            edges: self.edges.clone(),
+            options: self.options.clone(),
+        }
+    }
+    /// What changed since `before`, taken from this papercraft before an action.
+    ///
+    /// `changed` are the islands that may look different now, or `None` if any of them may.
+    /// They are sent whole, even if only the labels of their edges changed. Cuts and joins
+    /// replace islands, so they come as removed and added.
+    pub fn renderable_delta(
+        &self,
+        before: &DeltaBase,
+        changed: Option<&FxHashSet<IslandKey>>,
+    ) -> RenderableDelta {
+        let mut added = Vec::new();
+        let mut changed_islands = Vec::new();
+        for (id, island) in &self.islands {
+            if !before.islands.contains(&id) {
+                added.push(self.renderable_island(id, island));
+            } else if changed.is_none_or(|changed| changed.contains(&id)) {
+                changed_islands.push(self.renderable_island(id, island));
+            }
+        }
+        let mut removed: Vec<_> = before
+            .islands
+            .iter()
+            .copied()
+            .filter(|&id| !self.islands.contains_key(id))
+            .collect();
+        removed.sort();
+        let edges = self
+            .edges
+            .iter()
+            .zip(&before.edges)
+            .enumerate()
+            .filter(|(_, (now, old))| now != old)
+            .map(|(i_edge, (&status, _))| RenderableEdgeStatus {
+                id: EdgeIndex::from(i_edge),
+                status,
+            })
+            .collect();
+        RenderableDelta {
+            model_version: self.model_version(),
+            added,
+            changed: changed_islands,
+            removed,
+            edges,
+            options: (self.options != before.options).then(|| self.options.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paper::import::load_example;
+
+    #[test]
+    fn test_delta() {
+        let mut papercraft = load_example("dice.pdo");
+        let version = papercraft.model_version();
+
+        // Moving an island changes only that island
+        let before = papercraft.delta_base();
+        let i_island = papercraft.islands().next().unwrap().0;
+        papercraft
+            .island_by_key_mut(i_island)
+            .unwrap()
+            .translate(Vector2::new(10.0, 0.0));
+        let changed = FxHashSet::from_iter([i_island]);
+        let delta = papercraft.renderable_delta(&before, Some(&changed));
+        assert_eq!(delta.model_version, version);
+        assert!(delta.added.is_empty() && delta.removed.is_empty());
+        assert_eq!(delta.changed.len(), 1);
+        assert_eq!(delta.changed[0].id, i_island);
+        assert!(delta.edges.is_empty() && delta.options.is_none());
+
+        // Cutting an edge replaces the island
+        let before = papercraft.delta_base();
+        let i_edge = (0..papercraft.model().num_edges())
+            .map(EdgeIndex::from)
+            .find(|&i_edge| papercraft.edge_status(i_edge) == EdgeStatus::Joined)
+            .unwrap();
+        papercraft.edge_cut(i_edge, None);
+        let delta = papercraft.renderable_delta(&before, Some(&FxHashSet::default()));
+        assert!(!delta.added.is_empty());
+        assert!(!delta.removed.is_empty());
+        assert!(delta.edges.iter().any(|e| e.id == i_edge));
+        assert_eq!(papercraft.model_version(), version);
+
+        // Nothing changes, nothing is sent
+        let before = papercraft.delta_base();
+        let delta = papercraft.renderable_delta(&before, Some(&FxHashSet::default()));
+        assert!(delta.added.is_empty() && delta.changed.is_empty() && delta.removed.is_empty());
+
+        // Unless any island may have changed
+        let delta = papercraft.renderable_delta(&before, None);
+        assert_eq!(delta.changed.len(), papercraft.islands().count());
+    }
+}
@@ -151,6 +151,7 @@ impl Papercraft {
                     rot: Rad::zero(),
                     mx: Matrix3::one(),
                     name: String::new(),
+                    locked: false,
                 });
                 self.memo = Memoization::default();
                 changed = true;
@@ -268,6 +269,7 @@ impl Papercraft {
                 rot: Rad::zero(),
                 mx: Matrix3::one(),
                 name: String::new(),
+                locked: false,
             };
             islands.insert(island);
         }
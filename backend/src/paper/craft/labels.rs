@@ -0,0 +1,186 @@
+use super::*;
+
+/// The text printed beside a cut edge, that tells which edge it has to be glued to.
+#[derive(Debug, Clone)]
+pub struct EdgeLabel {
+    /// The face of this island the edge belongs to
+    pub i_face: FaceIndex,
+    pub i_edge: EdgeIndex,
+    pub id: EdgeId,
+    /// The island and face at the other side of the cut
+    pub partner_island: IslandKey,
+    pub partner_face: FaceIndex,
+    /// The name of the partner island and the edge id, such as "B:12"
+    pub text: String,
+    /// The center of the baseline of the text, in paper coordinates
+    pub pos: Vector2,
+    pub angle: Rad<f32>,
+}
+
+impl Papercraft {
+    // Size of the edge ids and island names, in mm
+    pub fn edge_id_font_size(&self) -> f32 {
+        self.options.edge_id_font_size * 25.4 / 72.0 // pt to mm
+    }
+
+    /// The labels of the cut edges of an island, as they are printed. Both sides of a cut have
+    /// their own label, and edges without an id (rims) have none.
+    pub fn island_edge_labels(&self, island: &Island) -> Vec<EdgeLabel> {
+        let mut labels = Vec::new();
+        if self.options.island_name_only {
+            return labels;
+        }
+        let scale = self.options.scale;
+        let font_size = self.edge_id_font_size();
+        let _ = self.traverse_faces(island, |i_face, face, mx| {
+            let plane = self.model.face_plane(face);
+            let to_paper = |i_v: VertexIndex| {
+                let p = plane.project(&self.model[i_v].pos(), scale);
+                mx.transform_point(Point2::from_vec(p)).to_vec()
+            };
+            for (i_v0, i_v1, i_edge) in face.vertices_with_edges() {
+                let Some(id) = self.edge_id(i_edge) else {
+                    continue;
+                };
+                if !matches!(self.edge_status(i_edge), EdgeStatus::Cut(_)) {
+                    continue;
+                }
+                let partner_face = match self.model[i_edge].faces() {
+                    (fa, Some(fb)) if fa == i_face => fb,
+                    (fa, Some(_)) => fa,
+                    (_, None) => continue,
+                };
+                let (pos0, pos1) = (to_paper(i_v0), to_paper(i_v1));
+                let v = pos1 - pos0;
+                if v.magnitude() < f32::EPSILON {
+                    continue;
+                }
+                let dir = v.normalize();
+                // Pointing out of the face, where the flap is
+                let normal = Vector2::new(-dir.y, dir.x);
+                let flap_width = self
+                    .flap_outline(i_face, i_edge, pos0, pos1)
+                    .map(|p| (p[1] - p[0]).dot(normal));
+
+                let mut center = (pos0 + pos1) / 2.0;
+                let voffs = match (self.options.edge_id_position, flap_width) {
+                    // inside the face
+                    (EdgeIdPosition::Inside, None) => -0.2,
+                    // in the flap, next to the face
+                    (EdgeIdPosition::Inside, Some(_)) => 1.0,
+                    // outside the face
+                    (EdgeIdPosition::Outside, None) => 1.0,
+                    // outside the flap
+                    (EdgeIdPosition::Outside, Some(width)) => {
+                        center += normal * width;
+                        1.0
+                    }
+                    // edge_id() is None in this case
+                    (EdgeIdPosition::None, _) => 0.0,
+                };
+
+                let partner_island = self.island_by_face(partner_face);
+                let partner_name = self
+                    .island_by_key(partner_island)
+                    .map(|island| island.name())
+                    .unwrap_or("?");
+                labels.push(EdgeLabel {
+                    i_face,
+                    i_edge,
+                    id,
+                    partner_island,
+                    partner_face,
+                    text: format!("{partner_name}:{id}"),
+                    pos: center + normal * (font_size * voffs),
+                    angle: Rad(dir.y.atan2(dir.x)),
+                });
+            }
+            ControlFlow::Continue(())
+        });
+        labels
+    }
+
+    /// Where the name of an island is printed: over its top when the edge ids are outside and
+    /// in the middle of its biggest flat area when they are inside. `None` if it is not printed.
+    pub fn island_name_position(&self, island: &Island) -> Option<Vector2> {
+        let font_size = self.edge_id_font_size();
+        match self.options.edge_id_position {
+            EdgeIdPosition::None => None,
+            EdgeIdPosition::Outside => {
+                let mut top = Vector2::new(f32::MAX, f32::MAX);
+                self.island_paper_triangles(island, island.matrix(), |_, _, tri| {
+                    for p in tri {
+                        if p.y < top.y {
+                            top = p;
+                        }
+                    }
+                });
+                Some(top - Vector2::new(0.0, font_size))
+            }
+            EdgeIdPosition::Inside => {
+                let (flat_face, total_area) = self.get_biggest_flat_face(island);
+                let scale = self.options.scale;
+                // The center of mass of the flat face is the weighted mean of the centers of
+                // its triangles.
+                let mut center = Vector2::zero();
+                let _ = self.traverse_faces(island, |i_face, face, mx| {
+                    if let Some((_, area)) = flat_face.iter().find(|(f, _)| *f == i_face) {
+                        let plane = self.model.face_plane(face);
+                        let vv: Vector2 = face
+                            .index_vertices()
+                            .into_iter()
+                            .map(|i_v| {
+                                let p = plane.project(&self.model[i_v].pos(), scale);
+                                mx.transform_point(Point2::from_vec(p)).to_vec()
+                            })
+                            .sum();
+                        center += vv * *area;
+                    }
+                    ControlFlow::Continue(())
+                });
+                Some(center / total_area / 3.0 + Vector2::new(0.0, font_size))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paper::import::load_example;
+
+    #[test]
+    fn test_edge_labels_match() {
+        let mut papercraft = load_example("dice.pdo");
+        papercraft.auto_unfold();
+        let mut labels = Vec::new();
+        for (i_island, island) in papercraft.islands() {
+            for label in papercraft.island_edge_labels(island) {
+                labels.push((i_island, label));
+            }
+        }
+        assert!(!labels.is_empty());
+        // Every label has a partner at the other side of the cut, with the same id
+        for (i_island, label) in &labels {
+            let (partner_island, partner) = labels
+                .iter()
+                .find(|(_, l)| l.i_edge == label.i_edge && l.i_face == label.partner_face)
+                .unwrap();
+            assert_eq!(*partner_island, label.partner_island);
+            assert_eq!(partner.partner_island, *i_island);
+            assert_eq!(partner.id.to_string(), label.id.to_string());
+            let name = papercraft
+                .island_by_key(label.partner_island)
+                .unwrap()
+                .name();
+            assert_eq!(label.text, format!("{name}:{}", label.id));
+        }
+
+        let mut options = papercraft.options().clone();
+        options.edge_id_position = EdgeIdPosition::None;
+        papercraft.set_options(options, false);
+        let (_, island) = papercraft.islands().next().unwrap();
+        assert!(papercraft.island_edge_labels(island).is_empty());
+        assert!(papercraft.island_name_position(island).is_none());
+    }
+}
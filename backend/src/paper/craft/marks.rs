@@ -0,0 +1,92 @@
+use super::*;
+
+// Sizes, in mm, of the registration marks, as Silhouette Studio draws them
+const MARK_SQUARE: f32 = 5.0;
+const MARK_LENGTH: f32 = 20.0;
+const MARK_THICKNESS: f32 = 0.5;
+// Free space around each mark, so that the optical sensor of the cutter does not see anything
+// else when looking for it
+const MARK_CLEARANCE: f32 = 5.0;
+
+/// A rectangle in a page, as its top-left and bottom-right corners, in mm from the top-left
+/// corner of the page.
+pub type PageRect = (Vector2, Vector2);
+
+impl PaperOptions {
+    /// The filled rectangles of the print-and-cut registration marks of every page: a square
+    /// in the top-left corner of the printable area and an L in the top-right and bottom-left
+    /// ones. Empty if they are disabled.
+    pub fn registration_mark_rects(&self) -> Vec<PageRect> {
+        if !self.registration_marks {
+            return Vec::new();
+        }
+        let (x0, y0, x1, y1) = self.printable_corners();
+        let (l, t) = (MARK_LENGTH, MARK_THICKNESS);
+        vec![
+            (
+                Vector2::new(x0, y0),
+                Vector2::new(x0 + MARK_SQUARE, y0 + MARK_SQUARE),
+            ),
+            (Vector2::new(x1 - l, y0), Vector2::new(x1, y0 + t)),
+            (Vector2::new(x1 - t, y0), Vector2::new(x1, y0 + l)),
+            (Vector2::new(x0, y1 - t), Vector2::new(x0 + l, y1)),
+            (Vector2::new(x0, y1 - l), Vector2::new(x0 + t, y1)),
+        ]
+    }
+    /// The zones of every page where no island can be, because of the registration marks.
+    /// Empty if they are disabled.
+    pub fn registration_zones(&self) -> Vec<PageRect> {
+        if !self.registration_marks {
+            return Vec::new();
+        }
+        let (x0, y0, x1, y1) = self.printable_corners();
+        let (l, c) = (MARK_LENGTH, MARK_CLEARANCE);
+        vec![
+            (
+                Vector2::new(x0 - c, y0 - c),
+                Vector2::new(x0 + MARK_SQUARE + c, y0 + MARK_SQUARE + c),
+            ),
+            (
+                Vector2::new(x1 - l - c, y0 - c),
+                Vector2::new(x1 + c, y0 + l + c),
+            ),
+            (
+                Vector2::new(x0 - c, y1 - l - c),
+                Vector2::new(x0 + l + c, y1 + c),
+            ),
+        ]
+    }
+    // Left, top, right and bottom of the printable area
+    fn printable_corners(&self) -> (f32, f32, f32, f32) {
+        let (top, left, right, bottom) = self.margin;
+        let (width, height) = self.page_size;
+        (left, top, width - right, height - bottom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registration_marks() {
+        let mut options = PaperOptions::default();
+        assert!(options.registration_mark_rects().is_empty());
+        assert!(options.registration_zones().is_empty());
+
+        options.registration_marks = true;
+        let marks = options.registration_mark_rects();
+        let zones = options.registration_zones();
+        assert_eq!(marks.len(), 5);
+        assert_eq!(zones.len(), 3);
+        let inside =
+            |p: Vector2, (a, b): PageRect| p.x >= a.x && p.y >= a.y && p.x <= b.x && p.y <= b.y;
+        for (a, b) in marks {
+            assert!(zones.iter().any(|&z| inside(a, z) && inside(b, z)));
+            // In the printable area
+            assert!(a.x >= options.margin.1 && a.y >= options.margin.0);
+            assert!(b.x <= options.page_size.0 - options.margin.2);
+            assert!(b.y <= options.page_size.1 - options.margin.3);
+        }
+    }
+}
@@ -0,0 +1,485 @@
+use super::*;
+
+// Size, in mm, of the cells of the grid used to pack the islands
+const PACK_RESOLUTION: f32 = 0.5;
+
+// A grid of cells, occupied or free, stored as one bit per cell, row by row.
+#[derive(Clone)]
+struct Grid {
+    width: usize,
+    height: usize,
+    words: usize,
+    bits: Vec<u64>,
+}
+
+impl Grid {
+    fn new(width: usize, height: usize) -> Grid {
+        let words = width.div_ceil(64).max(1);
+        Grid {
+            width,
+            height,
+            words,
+            bits: vec![0; words * height],
+        }
+    }
+    fn row(&self, y: usize) -> &[u64] {
+        &self.bits[y * self.words..(y + 1) * self.words]
+    }
+    fn row_mut(&mut self, y: usize) -> &mut [u64] {
+        &mut self.bits[y * self.words..(y + 1) * self.words]
+    }
+    fn get(&self, x: usize, y: usize) -> bool {
+        self.row(y)[x / 64] & (1 << (x % 64)) != 0
+    }
+    fn count(&self) -> u32 {
+        self.bits.iter().map(|w| w.count_ones()).sum()
+    }
+    fn fill(&mut self) {
+        for y in 0..self.height {
+            self.fill_span(y, 0, self.width - 1);
+        }
+    }
+    // Marks the cells from x0 to x1, both included
+    fn fill_span(&mut self, y: usize, x0: usize, x1: usize) {
+        let row = self.row_mut(y);
+        for x in x0..=x1 {
+            row[x / 64] |= 1 << (x % 64);
+        }
+    }
+    // Marks every cell touched by the triangle, with coordinates in cells
+    fn fill_triangle(&mut self, tri: [Vector2; 3]) {
+        let (a, b) = util_3d::bounding_box_2d(tri);
+        if b.x < 0.0 || b.y < 0.0 || a.x >= self.width as f32 || a.y >= self.height as f32 {
+            return;
+        }
+        let y0 = a.y.max(0.0) as usize;
+        let y1 = (b.y as usize).min(self.height - 1);
+        for y in y0..=y1 {
+            // The intersection of the triangle with the row is convex, its extremes are either
+            // the vertices inside the row or the crossings of the sides with the row borders
+            let (top, bottom) = (y as f32, (y + 1) as f32);
+            let mut range: Option<(f32, f32)> = None;
+            let mut add = |x: f32| {
+                range = Some(range.map_or((x, x), |(r0, r1)| (r0.min(x), r1.max(x))));
+            };
+            for i in 0..3 {
+                let p0 = tri[i];
+                let p1 = tri[(i + 1) % 3];
+                if (top..=bottom).contains(&p0.y) {
+                    add(p0.x);
+                }
+                for border in [top, bottom] {
+                    if (p0.y < border) != (p1.y < border) {
+                        let t = (border - p0.y) / (p1.y - p0.y);
+                        add(p0.x + t * (p1.x - p0.x));
+                    }
+                }
+            }
+            let Some((x0, x1)) = range else {
+                continue;
+            };
+            if x1 < 0.0 || x0 >= self.width as f32 {
+                continue;
+            }
+            let x0 = x0.max(0.0) as usize;
+            let x1 = (x1 as usize).min(self.width - 1);
+            self.fill_span(y, x0, x1);
+        }
+    }
+    // Returns a bigger grid, with every occupied cell grown `d` cells in each direction
+    fn dilate(&self, d: usize) -> Grid {
+        let mut wide = Grid::new(self.width + 2 * d, self.height);
+        for run in self.runs() {
+            wide.fill_span(run.row, run.start, run.start + run.len - 1 + 2 * d);
+        }
+        let mut res = Grid::new(self.width + 2 * d, self.height + 2 * d);
+        for y in 0..self.height {
+            for dy in 0..=2 * d {
+                let (src, dst) = (y * wide.words, (y + dy) * res.words);
+                for i in 0..res.words {
+                    res.bits[dst + i] |= wide.bits[src + i];
+                }
+            }
+        }
+        res
+    }
+    // The spans of occupied cells, row by row
+    fn runs(&self) -> Vec<Run> {
+        let mut runs = Vec::new();
+        for y in 0..self.height {
+            let mut start = None;
+            for x in 0..=self.width {
+                match (start, x < self.width && self.get(x, y)) {
+                    (None, true) => start = Some(x),
+                    (Some(x0), false) => {
+                        runs.push(Run {
+                            row: y,
+                            start: x0,
+                            len: x - x0,
+                        });
+                        start = None;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        runs
+    }
+    // Marks the cells occupied by `other`, with its origin at (x, y)
+    fn stamp(&mut self, other: &Grid, x: usize, y: usize) {
+        let (w0, shift) = (x / 64, x % 64);
+        for r in 0..other.height {
+            let words = self.words;
+            let row = &mut self.bits[(y + r) * words..(y + r + 1) * words];
+            for (i, &bits) in other.row(r).iter().enumerate() {
+                if bits == 0 {
+                    continue;
+                }
+                row[w0 + i] |= bits << shift;
+                if shift > 0 {
+                    let hi = bits >> (64 - shift);
+                    if hi != 0 {
+                        row[w0 + i + 1] |= hi;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Run {
+    row: usize,
+    start: usize,
+    len: usize,
+}
+
+// A page being packed. Along with the occupied cells, it keeps for every cell the number of
+// free cells from it to the right, or minus the number of occupied ones, so that the search
+// can jump over the occupied spans.
+struct Page {
+    cells: Grid,
+    free_runs: Vec<i32>,
+}
+
+impl Page {
+    // `reserved` are rectangles, in cells, where nothing can be placed
+    fn new(width: usize, height: usize, reserved: &[(Vector2, Vector2)]) -> Page {
+        let mut cells = Grid::new(width, height);
+        for &(a, b) in reserved {
+            cells.fill_triangle([a, Vector2::new(b.x, a.y), b]);
+            cells.fill_triangle([a, b, Vector2::new(a.x, b.y)]);
+        }
+        let mut page = Page {
+            cells,
+            free_runs: vec![0; width * height],
+        };
+        page.update_runs(0..height);
+        page
+    }
+    fn update_runs(&mut self, rows: std::ops::Range<usize>) {
+        let width = self.cells.width;
+        for y in rows {
+            let mut free = 0;
+            for x in (0..width).rev() {
+                free = match (self.cells.get(x, y), free > 0) {
+                    (true, true) => -1,
+                    (true, false) => free - 1,
+                    (false, true) => free + 1,
+                    (false, false) => 1,
+                };
+                self.free_runs[y * width + x] = free;
+            }
+        }
+    }
+    // Checks if a shape fits with its origin at (x, y). If it does not, returns how many
+    // cells it has to move to the right, at least, to fit in this row.
+    fn fits(&self, shape: &Shape, x: usize, y: usize) -> Result<(), usize> {
+        let width = self.cells.width;
+        for run in &shape.runs {
+            let free = self.free_runs[(y + run.row) * width + x + run.start];
+            if free <= 0 {
+                return Err(-free as usize);
+            }
+            if (free as usize) < run.len {
+                return Err(free as usize + 1);
+            }
+        }
+        Ok(())
+    }
+    fn place(&mut self, mask: &Grid, x: usize, y: usize) {
+        self.cells.stamp(mask, x, y);
+        self.update_runs(y..y + mask.height);
+    }
+    fn fill(&mut self) {
+        self.cells.fill();
+        self.update_runs(0..self.cells.height);
+    }
+}
+
+// An island drawn in a grid, with a given rotation
+struct Shape {
+    angle: Rad<f32>,
+    // The position of the origin of the grid, relative to the island position
+    offset: Vector2,
+    mask: Grid,
+    // The size and the spans of the mask grown by the gap between islands, the longest first
+    size: (usize, usize),
+    runs: Vec<Run>,
+}
+
+impl Papercraft {
+    /// Places all the islands that are not locked in the pages, leaving the locked ones where
+    /// they are. Returns the number of pages used.
+    ///
+    /// The islands are drawn over a grid, with their flaps, and each one is placed in the
+    /// first page where it fits, as high as possible, trying several rotations. The biggest
+    /// islands are placed first. The zones of the registration marks are kept free.
+    pub fn pack_islands(&mut self) -> u32 {
+        let options = &self.options;
+        let (top, left, right, bottom) = options.margin;
+        let page_w = ((options.page_size.0 - left - right) / PACK_RESOLUTION).max(1.0) as usize;
+        let page_h = ((options.page_size.1 - top - bottom) / PACK_RESOLUTION).max(1.0) as usize;
+        let gap = (options.pack_gap.max(0.0) / PACK_RESOLUTION).ceil() as usize;
+        let page_origin = |page: u32| options.page_position(page) + Vector2::new(left, top);
+        let to_grid = |page: u32, p: Vector2| {
+            (p - page_origin(page)) / PACK_RESOLUTION + Vector2::new(gap as f32, gap as f32)
+        };
+        // The same in every page, the zones are relative to the page
+        let first_page = options.page_position(0);
+        let reserved: Vec<_> = options
+            .registration_zones()
+            .into_iter()
+            .map(|(a, b)| (to_grid(0, a + first_page), to_grid(0, b + first_page)))
+            .collect();
+        // Pages have a free border of `gap` cells, so that islands can touch the margin
+        let new_page = || Page::new(page_w + 2 * gap, page_h + 2 * gap, &reserved);
+
+        // Locked islands are obstacles in the pages where they are. Those out of the current
+        // pages are skipped, a grid for every page up to an island far away would not fit in
+        // memory.
+        let page_count = options.pages.max(1);
+        let page_rows = page_count.div_ceil(options.page_cols.max(1));
+        let mut pages: Vec<Page> = Vec::new();
+        for (_, island) in self.islands.iter().filter(|(_, island)| island.locked) {
+            self.island_paper_triangles(island, island.matrix(), |_, _, tri| {
+                let mut tri_pages: Vec<u32> = Vec::new();
+                for p in tri {
+                    let po = options.global_to_page(p);
+                    if po.col >= options.page_cols as i32 || po.row >= page_rows as i32 {
+                        continue;
+                    }
+                    let page = po.row as u32 * options.page_cols + po.col as u32;
+                    if page >= page_count {
+                        continue;
+                    }
+                    if !tri_pages.contains(&page) {
+                        tri_pages.push(page);
+                    }
+                }
+                for page in tri_pages {
+                    while pages.len() <= page as usize {
+                        pages.push(new_page());
+                    }
+                    pages[page as usize]
+                        .cells
+                        .fill_triangle(tri.map(|p| to_grid(page, p)));
+                }
+            });
+        }
+        for page in &mut pages {
+            page.update_runs(0..page.cells.height);
+        }
+
+        let rotations = options.pack_rotations.max(1);
+        let mut to_pack: Vec<(IslandKey, Vec<Shape>)> = self
+            .islands
+            .iter()
+            .filter(|(_, island)| !island.locked)
+            .map(|(i_island, island)| {
+                let (best_angle, _) = self.island_best_bounding_box(island);
+                let shapes = (0..rotations)
+                    .map(|i| {
+                        let angle = best_angle + Rad::full_turn() * (i as f32 / rotations as f32);
+                        self.island_shape(island, angle, gap)
+                    })
+                    .collect();
+                (i_island, shapes)
+            })
+            .collect();
+        to_pack.sort_by_key(|(_, shapes)| std::cmp::Reverse(shapes[0].mask.count()));
+
+        let mut positions = Vec::with_capacity(to_pack.len());
+        for (i_island, shapes) in to_pack {
+            let mut i_page = 0;
+            let (shape, page, x, y) = loop {
+                let is_new = i_page == pages.len();
+                if is_new {
+                    pages.push(new_page());
+                }
+                let page = &mut pages[i_page];
+                if let Some((shape, x, y)) = find_place(page, &shapes) {
+                    page.place(&shape.mask, x + gap, y + gap);
+                    break (shape, i_page, x, y);
+                }
+                if is_new {
+                    // It does not fit in a page, leave it alone in a page of its own
+                    page.fill();
+                    break (&shapes[0], i_page, 0, 0);
+                }
+                i_page += 1;
+            };
+            let page = page as u32;
+            let pos = page_origin(page) + Vector2::new(x as f32, y as f32) * PACK_RESOLUTION;
+            positions.push((i_island, shape.angle, pos - shape.offset));
+        }
+
+        for (i_island, angle, loc) in positions {
+            let island = &mut self.islands[i_island];
+            island.rot = angle;
+            island.loc = loc;
+            island.recompute_matrix();
+        }
+        pages.len().max(1) as u32
+    }
+
+    fn island_shape(&self, island: &Island, angle: Rad<f32>, gap: usize) -> Shape {
+        let mx = Matrix3::from(Matrix2::from_angle(angle));
+        let mut tris = Vec::new();
+        self.island_paper_triangles(island, mx, |_, _, tri| tris.push(tri));
+        let (a, b) = util_3d::bounding_box_2d(tris.iter().flatten().copied());
+        let size = (b - a) / PACK_RESOLUTION;
+        let mut mask = Grid::new(size.x as usize + 1, size.y as usize + 1);
+        for tri in tris {
+            mask.fill_triangle(tri.map(|p| (p - a) / PACK_RESOLUTION));
+        }
+        let dilated = mask.dilate(gap);
+        let mut runs = dilated.runs();
+        runs.sort_by_key(|run| std::cmp::Reverse(run.len));
+        Shape {
+            angle,
+            offset: a,
+            mask,
+            size: (dilated.width, dilated.height),
+            runs,
+        }
+    }
+}
+
+// Finds the place where the grown shape fits with the lowest bottom side, and then the
+// leftmost one. It returns the position of the origin of the grown shape.
+fn find_place<'a>(page: &Page, shapes: &'a [Shape]) -> Option<(&'a Shape, usize, usize)> {
+    let (page_w, page_h) = (page.cells.width, page.cells.height);
+    let mut best: Option<(&Shape, usize, usize)> = None;
+    let bottom_left = |(shape, x, y): (&Shape, usize, usize)| (y + shape.size.1, x);
+    for shape in shapes {
+        let (w, h) = shape.size;
+        if w > page_w || h > page_h {
+            continue;
+        }
+        'rows: for y in 0..=page_h - h {
+            if best.is_some_and(|b| bottom_left(b) <= (y + h, 0)) {
+                break;
+            }
+            let mut x = 0;
+            while x <= page_w - w {
+                match page.fits(shape, x, y) {
+                    Ok(()) => {
+                        if best.is_none_or(|b| (y + h, x) < bottom_left(b)) {
+                            best = Some((shape, x, y));
+                        }
+                        break 'rows;
+                    }
+                    Err(skip) => x += skip,
+                }
+            }
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paper::import::load_example;
+
+    // Cuts all the edges, so that there are many islands to pack
+    fn cut_all(papercraft: &mut Papercraft) {
+        let edges: Vec<_> = papercraft
+            .model()
+            .edges()
+            .map(|(i_edge, _)| i_edge)
+            .collect();
+        for i_edge in edges {
+            if papercraft.edge_status(i_edge) == EdgeStatus::Joined {
+                papercraft.edge_cut(i_edge, None);
+            }
+        }
+    }
+
+    #[test]
+    fn test_pack_no_overlaps() {
+        let mut papercraft = load_example("dice.pdo");
+        cut_all(&mut papercraft);
+        papercraft.options.pages = papercraft.pack_islands();
+        let report = papercraft.validate();
+        assert!(report.is_ok(), "{report:?}");
+    }
+
+    #[test]
+    fn test_pack_locked() {
+        let mut papercraft = load_example("dice.pdo");
+        cut_all(&mut papercraft);
+        let keys: Vec<_> = papercraft.islands().map(|(k, _)| k).collect();
+        let locked = papercraft.island_by_key_mut(keys[0]).unwrap();
+        locked.set_locked(true);
+        locked.translate(Vector2::new(30.0, 40.0));
+        let loc = locked.location();
+
+        papercraft.options.pages = papercraft.pack_islands();
+        assert_eq!(papercraft.island_by_key(keys[0]).unwrap().location(), loc);
+        let report = papercraft.validate();
+        assert!(report.face_overlaps.is_empty(), "{report:?}");
+        assert!(report.flap_collisions.is_empty(), "{report:?}");
+    }
+
+    #[test]
+    fn test_pack_locked_far_away() {
+        let mut papercraft = load_example("dice.pdo");
+        cut_all(&mut papercraft);
+        let keys: Vec<_> = papercraft.islands().map(|(k, _)| k).collect();
+        let locked = papercraft.island_by_key_mut(keys[0]).unwrap();
+        locked.set_locked(true);
+        locked.translate(Vector2::new(1.0e12, 1.0e12));
+        let loc = locked.location();
+
+        // It is not an obstacle, nor does it add pages
+        let pages = papercraft.pack_islands();
+        assert!(pages < keys.len() as u32, "{pages}");
+        assert_eq!(papercraft.island_by_key(keys[0]).unwrap().location(), loc);
+    }
+
+    #[test]
+    fn test_pack_registration_marks() {
+        let mut papercraft = load_example("dice.pdo");
+        cut_all(&mut papercraft);
+        let mut options = papercraft.options().clone();
+        options.registration_marks = true;
+        papercraft.set_options(options, false);
+        papercraft.options.pages = papercraft.pack_islands();
+        let report = papercraft.validate();
+        assert!(report.is_ok(), "{report:?}");
+    }
+
+    #[test]
+    fn test_pack_minimizes_pages() {
+        let mut papercraft = load_example("dice.pdo");
+        cut_all(&mut papercraft);
+        // A small scale fits everything in one page
+        let mut options = papercraft.options().clone();
+        options.scale /= 4.0;
+        options.pages = 5;
+        papercraft.set_options(options, false);
+        assert_eq!(papercraft.pack_islands(), 1);
+    }
+}
@@ -0,0 +1,244 @@
+use super::*;
+
+// Minimum overlap, in mm, for two faces to be considered overlapping
+const OVERLAP_MARGIN: f32 = 0.01;
+// How much the length of an edge counts against its dihedral angle when choosing what to join
+const LENGTH_WEIGHT: f32 = 0.5;
+
+/// The cuts and the placement of the islands, enough to restore the model after an operation
+/// that rebuilds all the islands.
+#[derive(Clone)]
+pub struct CutLayout {
+    edges: Vec<EdgeStatus>,
+    islands: Vec<Island>,
+    pages: u32,
+}
+
+// A group of faces joined together, laid out in the same plane
+struct Component {
+    faces: Vec<FaceIndex>,
+    bbox: (Vector2, Vector2),
+}
+
+impl Papercraft {
+    pub fn cut_layout(&self) -> CutLayout {
+        CutLayout {
+            edges: self.edges.clone(),
+            islands: self.islands.values().cloned().collect(),
+            pages: self.options.pages,
+        }
+    }
+    // Returns the previous layout
+    pub fn set_cut_layout(&mut self, layout: CutLayout) -> CutLayout {
+        let prev = self.cut_layout();
+        self.edges = layout.edges;
+        self.islands = SlotMap::with_key();
+        for island in layout.islands {
+            self.islands.insert(island);
+        }
+        self.options.pages = layout.pages;
+        self.memo = Memoization::default();
+        prev
+    }
+
+    /// Computes a new set of cuts from scratch.
+    ///
+    /// The faces are joined following a minimum spanning tree, with flat and long edges
+    /// joined first, as long as the resulting island does not overlap with itself.
+    /// Edges hidden by the importer are always kept joined.
+    pub fn auto_unfold(&mut self) {
+        let model = &self.model;
+        let scale = self.options.scale;
+
+        // Every face starts as its own component, in its own local coordinates
+        let mut tris: Vec<[Vector2; 3]> = model
+            .faces()
+            .map(|(_, face)| {
+                let plane = model.face_plane(face);
+                face.index_vertices()
+                    .map(|i_v| plane.project(&model[i_v].pos(), scale))
+            })
+            .collect();
+        let mut face_mx = vec![Matrix3::one(); model.num_faces()];
+        let mut face_comp: Vec<usize> = (0..model.num_faces()).collect();
+        let mut comps: Vec<Component> = tris
+            .iter()
+            .enumerate()
+            .map(|(i, tri)| Component {
+                faces: vec![FaceIndex::from(i)],
+                bbox: util_3d::bounding_box_2d(*tri),
+            })
+            .collect();
+
+        let mut edges: Vec<EdgeStatus> = model
+            .edges()
+            .zip(&self.edges)
+            .map(|((_, edge), status)| match (edge.faces(), status) {
+                ((_, None), _) | (_, EdgeStatus::Hidden) => *status,
+                _ => EdgeStatus::Cut(FlapSide::False),
+            })
+            .collect();
+
+        // Hidden edges first, then flat and long edges
+        let max_len = model
+            .edges()
+            .map(|(_, edge)| {
+                let (v0, v1) = model.edge_pos(edge);
+                v0.distance(v1)
+            })
+            .fold(0.0f32, f32::max)
+            .max(f32::EPSILON);
+        let mut candidates: Vec<(EdgeIndex, bool, f32)> = model
+            .edges()
+            .filter(|(_, edge)| edge.faces().1.is_some())
+            .map(|(i_edge, edge)| {
+                let forced = edges[usize::from(i_edge)] == EdgeStatus::Hidden;
+                let (v0, v1) = model.edge_pos(edge);
+                let weight = edge.angle().0.abs() / std::f32::consts::PI
+                    + LENGTH_WEIGHT * (1.0 - v0.distance(v1) / max_len);
+                (i_edge, forced, weight)
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.1.cmp(&a.1).then(a.2.total_cmp(&b.2)));
+
+        for (i_edge, forced, _) in candidates {
+            let edge = &model[i_edge];
+            let (i_face_a, Some(i_face_b)) = edge.faces() else {
+                continue;
+            };
+            let (mut i_face_a, mut i_face_b) = (i_face_a, i_face_b);
+            let mut comp_a = face_comp[usize::from(i_face_a)];
+            let mut comp_b = face_comp[usize::from(i_face_b)];
+            if comp_a == comp_b {
+                continue;
+            }
+            // Move the smaller component
+            if comps[comp_a].faces.len() < comps[comp_b].faces.len() {
+                std::mem::swap(&mut i_face_a, &mut i_face_b);
+                std::mem::swap(&mut comp_a, &mut comp_b);
+            }
+
+            let medge = self.face_to_face_edge_matrix(edge, &model[i_face_a], &model[i_face_b]);
+            let mx = face_mx[usize::from(i_face_a)]
+                * medge
+                * face_mx[usize::from(i_face_b)].invert().unwrap();
+
+            let moved: Vec<(FaceIndex, [Vector2; 3])> = comps[comp_b]
+                .faces
+                .iter()
+                .map(|&i_face| {
+                    let tri = tris[usize::from(i_face)]
+                        .map(|p| mx.transform_point(Point2::from_vec(p)).to_vec());
+                    (i_face, tri)
+                })
+                .collect();
+            let bbox_b = util_3d::bounding_box_2d(moved.iter().flat_map(|(_, tri)| *tri));
+
+            if !forced && bbox_overlap(comps[comp_a].bbox, bbox_b) {
+                let overlaps = moved.iter().any(|(_, tri_b)| {
+                    let bb_b = util_3d::bounding_box_2d(*tri_b);
+                    comps[comp_a].faces.iter().any(|&i_face| {
+                        let tri_a = tris[usize::from(i_face)];
+                        bbox_overlap(util_3d::bounding_box_2d(tri_a), bb_b)
+                            && util_3d::triangles_overlap(tri_a, *tri_b, OVERLAP_MARGIN)
+                    })
+                });
+                if overlaps {
+                    continue;
+                }
+            }
+
+            // Join them
+            for (i_face, tri) in moved {
+                face_mx[usize::from(i_face)] = mx * face_mx[usize::from(i_face)];
+                tris[usize::from(i_face)] = tri;
+                face_comp[usize::from(i_face)] = comp_a;
+            }
+            let faces_b = std::mem::take(&mut comps[comp_b].faces);
+            let comp = &mut comps[comp_a];
+            comp.faces.extend(faces_b);
+            comp.bbox = (
+                Vector2::new(comp.bbox.0.x.min(bbox_b.0.x), comp.bbox.0.y.min(bbox_b.0.y)),
+                Vector2::new(comp.bbox.1.x.max(bbox_b.1.x), comp.bbox.1.y.max(bbox_b.1.y)),
+            );
+            if !forced {
+                edges[usize::from(i_edge)] = EdgeStatus::Joined;
+            }
+        }
+
+        self.edges = edges;
+        self.islands = SlotMap::with_key();
+        for comp in &comps {
+            let Some(&root) = comp.faces.first() else {
+                continue;
+            };
+            self.islands.insert(Island {
+                root,
+                loc: Vector2::zero(),
+                rot: Rad::zero(),
+                mx: Matrix3::one(),
+                name: String::new(),
+                locked: false,
+            });
+        }
+        self.memo = Memoization::default();
+        self.options.pages = self.pack_islands();
+        self.rebuild_island_names();
+    }
+}
+
+fn bbox_overlap(a: (Vector2, Vector2), b: (Vector2, Vector2)) -> bool {
+    a.0.x < b.1.x && b.0.x < a.1.x && a.0.y < b.1.y && b.0.y < a.1.y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paper::import::load_example;
+
+    // Checks every pair of faces of every island, the slow way
+    fn assert_no_self_overlap(papercraft: &Papercraft) {
+        for (_, island) in papercraft.islands() {
+            let mut tris = Vec::new();
+            let _ = papercraft.traverse_faces(island, |_, face, mx| {
+                let plane = papercraft.model().face_plane(face);
+                let tri = face.index_vertices().map(|i_v| {
+                    let p = plane.project(&papercraft.model()[i_v].pos(), papercraft.options().scale);
+                    mx.transform_point(Point2::from_vec(p)).to_vec()
+                });
+                tris.push(tri);
+                ControlFlow::Continue(())
+            });
+            for (i, a) in tris.iter().enumerate() {
+                for b in &tris[i + 1..] {
+                    assert!(!util_3d::triangles_overlap(*a, *b, OVERLAP_MARGIN * 2.0));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_auto_unfold_sphere() {
+        let mut papercraft = load_example("sphere.pdo");
+        let num_faces = papercraft.model().num_faces();
+        papercraft.auto_unfold();
+
+        // It should not be one piece per face
+        assert!(papercraft.num_islands() < num_faces / 4);
+        let faces: u32 = papercraft
+            .islands()
+            .map(|(_, island)| papercraft.island_face_count(island))
+            .sum();
+        assert_eq!(faces as usize, num_faces);
+        assert_no_self_overlap(&papercraft);
+    }
+
+    #[test]
+    fn test_auto_unfold_cube() {
+        let mut papercraft = load_example("dice.pdo");
+        papercraft.auto_unfold();
+        // A cube always has a net without overlaps
+        assert_eq!(papercraft.num_islands(), 1);
+        assert_no_self_overlap(&papercraft);
+    }
+}
@@ -1,45 +1,122 @@
 use super::*;
+use crate::kdtree::KdTree;
 
-fn compute_edge_map(new: &Papercraft, old: &Papercraft) -> FxHashMap<EdgeIndex, (EdgeIndex, bool)> {
+// Edges farther than this, relative to the size of the model, never match
+const MATCH_TOLERANCE: f32 = 0.05;
+
+// How the old model is placed over the new one to compare their edges
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Fit {
+    scale: f32,
+    offset: Vector3,
+}
+
+impl Fit {
+    const IDENTITY: Fit = Fit {
+        scale: 1.0,
+        offset: Vector3::new(0.0, 0.0, 0.0),
+    };
+
+    // Scales and moves `from` so that its bounding box is that of `to`, for models that were
+    // scaled or moved as a whole
+    fn bounding_boxes(from: &Model, to: &Model) -> Fit {
+        let (fa, fb) = model_bounding_box(from);
+        let (ta, tb) = model_bounding_box(to);
+        let from_size = (fb - fa).magnitude();
+        if from_size == 0.0 {
+            return Fit::IDENTITY;
+        }
+        let scale = (tb - ta).magnitude() / from_size;
+        Fit {
+            scale,
+            offset: (ta + tb) / 2.0 - (fa + fb) / 2.0 * scale,
+        }
+    }
+    fn apply(&self, p: Vector3) -> Vector3 {
+        p * self.scale + self.offset
+    }
+    fn inverse(&self) -> Fit {
+        Fit {
+            scale: 1.0 / self.scale,
+            offset: -self.offset / self.scale,
+        }
+    }
+}
+
+fn model_bounding_box(model: &Model) -> (Vector3, Vector3) {
+    util_3d::bounding_box_3d(model.vertices().map(|(_, v)| v.pos()))
+}
+
+// For each edge of `new` the nearest edge of `old`, after placing it with `fit`, and if it
+// is crossed. The distance between two edges is that of their vertices, squared.
+fn compute_edge_map(
+    new: &Papercraft,
+    old: &Papercraft,
+    fit: Fit,
+) -> FxHashMap<EdgeIndex, (EdgeIndex, bool)> {
     use rayon::prelude::*;
 
     let model = &new.model;
     let omodel = &old.model;
     let n_edges = model.num_edges();
+    let (a, b) = model_bounding_box(model);
+    let tolerance2 = ((b - a).magnitude() * MATCH_TOLERANCE).powi(2);
+
+    let old_pos = |i_old: EdgeIndex| {
+        let (op0, op1) = omodel.edge_pos(&omodel[i_old]);
+        (fit.apply(op0), fit.apply(op1))
+    };
+    let tree = KdTree::new(omodel.edges().map(|(i_old, _)| {
+        let (op0, op1) = old_pos(i_old);
+        ((op0 + op1) / 2.0, i_old)
+    }));
 
-    // Brute force algorithm, probably it could be made much smarter, but it
-    // is easier to invoke the Rayon superpowers.
-    // It is not a function called so frequently.
     (0..n_edges)
         .into_par_iter()
         .map(EdgeIndex::from)
         .filter_map(|i_new| {
-            let e_new = &model[i_new];
-            let (np0, np1) = model.edge_pos(e_new);
-            let distance = |e_old: &Edge| {
-                let (op0, op1) = omodel.edge_pos(e_old);
+            let (np0, np1) = model.edge_pos(&model[i_new]);
+            let distance = |i_old: EdgeIndex| {
+                let (op0, op1) = old_pos(i_old);
                 let da = op0.distance2(np0) + op1.distance2(np1);
                 let db = op0.distance2(np1) + op1.distance2(np0);
-                (da, db)
+                (da.min(db), da > db)
             };
 
-            // f32 is not Eq so min_by_key cannot be used directly
-            let best = omodel.edges().min_by(|&(_, e_old_1), &(_, e_old_2)| {
-                let (da1, db1) = distance(e_old_1);
-                let (da2, db2) = distance(e_old_2);
-                let d1 = da1.min(db1);
-                let d2 = da2.min(db2);
-                d1.total_cmp(&d2)
+            // The midpoints of two edges are at most at half their distance, so the edge
+            // with the nearest midpoint limits how far the best one can be.
+            let mid = (np0 + np1) / 2.0;
+            let (_, &i_near) = tree.nearest(mid)?;
+            let (d_near, crossed) = distance(i_near);
+            let mut best = (d_near <= tolerance2).then_some((d_near, i_near, crossed));
+            let radius = (d_near.min(tolerance2) / 2.0).sqrt();
+            tree.for_each_within(mid, radius, |_, &i_old| {
+                let (d, crossed) = distance(i_old);
+                if d <= tolerance2 && best.is_none_or(|(b, _, _)| d < b) {
+                    best = Some((d, i_old, crossed));
+                }
             });
 
-            let (i_old, e_old) = best?;
-            let (da, db) = distance(e_old);
-            let crossed = da > db;
+            let (_, i_old, crossed) = best?;
             Some((i_new, (i_old, crossed)))
         })
         .collect()
 }
 
+// The edges of `new` that match one of `old`, if the best match for each one is the other.
+fn match_edges(
+    new: &Papercraft,
+    old: &Papercraft,
+    fit: Fit,
+) -> FxHashMap<EdgeIndex, (EdgeIndex, bool)> {
+    let eno_map = compute_edge_map(new, old, fit);
+    let eon_map = compute_edge_map(old, new, fit.inverse());
+    eno_map
+        .into_iter()
+        .filter(|(i_edge, (o, _))| eon_map.get(o).is_some_and(|(i, _)| i == i_edge))
+        .collect()
+}
+
 type IslandFaceMap = FxHashMap<IslandKey, FxHashSet<FaceIndex>>;
 
 fn compute_island_to_faces_map(pc: &Papercraft) -> IslandFaceMap {
@@ -75,41 +152,59 @@ fn compute_island_map(
     map
 }
 
+/// What could not be carried over from the old model by `update_from_obj`.
+#[derive(Debug, Default, Serialize)]
+pub struct UpdateReport {
+    /// Visible edges of the new model without an equivalent in the old one, they keep the
+    /// status they had when imported
+    pub unmatched_edges: Vec<EdgeIndex>,
+    /// Islands of the new model without an equivalent in the old one, they are moved out of
+    /// the pages
+    pub unmatched_islands: Vec<IslandKey>,
+}
+
 impl Papercraft {
-    pub fn update_from_obj(&mut self, old_obj: &Papercraft) {
+    pub fn update_from_obj(&mut self, old_obj: &Papercraft) -> UpdateReport {
         self.options = old_obj.options.clone();
         // Options are changed, discard memo
         self.memo = Memoization::default();
 
-        // Check which edges are nearest, checking the distance between their vertices
-        let eno_map = compute_edge_map(self, old_obj);
-        let eon_map = compute_edge_map(old_obj, self);
+        // Check which edges are nearest, checking the distance between their vertices.
+        // If the mesh was scaled or moved as a whole, fitting the old one into the new one
+        // matches more edges.
+        let mut edge_map = match_edges(self, old_obj, Fit::IDENTITY);
+        if edge_map.len() < self.model.num_edges() {
+            let fit = Fit::bounding_boxes(&old_obj.model, &self.model);
+            if fit != Fit::IDENTITY {
+                let fitted = match_edges(self, old_obj, fit);
+                if fitted.len() > edge_map.len() {
+                    edge_map = fitted;
+                }
+            }
+        }
 
-        // If the best match for A is B and for B is A, then it is a match
         let mut real_edge_map = FxHashMap::default();
         let mut edge_status_map = FxHashMap::default();
-        for (i_edge, _) in self.model.edges() {
-            // If an edge matches in both directions, then it is a match
-            let (o, o_cross) = match eno_map.get(&i_edge) {
-                None => continue,
-                Some(o) => *o,
-            };
-            let (i, _) = match eon_map.get(&o) {
-                None => continue,
-                Some(i) => *i,
-            };
-            if i_edge != i {
-                continue;
-            }
-            real_edge_map.insert(i, o);
+        for (i_edge, (o, o_cross)) in edge_map {
+            real_edge_map.insert(i_edge, o);
 
             let o_status = old_obj.edge_status(o);
-            let i_status = self.edge_status(i);
+            let i_status = self.edge_status(i_edge);
             if i_status != EdgeStatus::Hidden && o_status != EdgeStatus::Hidden {
                 edge_status_map.insert(i_edge, (o_status, o_cross));
             }
         }
 
+        let unmatched_edges = self
+            .model
+            .edges()
+            .map(|(i_edge, _)| i_edge)
+            .filter(|i_edge| {
+                !real_edge_map.contains_key(i_edge)
+                    && self.edge_status(*i_edge) != EdgeStatus::Hidden
+            })
+            .collect();
+
         //Apply the old status to the new model
         for (i_edge, (status, crossed)) in edge_status_map {
             // Is it a rim?
@@ -268,6 +363,8 @@ impl Papercraft {
             };
             new_island_pos.insert(i, Some((iroot, rot, loc)));
         }
+        // Sanitizing may remove islands, so remember them by their root face
+        let mut unmatched_roots = Vec::new();
         for (i_island, maybe_pos) in new_island_pos {
             let island = self.islands.get_mut(i_island).unwrap();
             match maybe_pos {
@@ -275,6 +372,7 @@ impl Papercraft {
                     island.reset_transformation(iroot, rot, loc);
                 }
                 None => {
+                    unmatched_roots.push(island.root_face());
                     // If the island doesn't have a mapping, dump it into the page -1.
                     let mut page_offs = self.options.global_to_page(island.loc);
                     page_offs.row = 0;
@@ -288,5 +386,91 @@ impl Papercraft {
 
         // Mixing two sane things may create something insane, fix it now
         self.sanitize();
+
+        let mut unmatched_islands: Vec<_> = unmatched_roots
+            .into_iter()
+            .map(|root| self.island_by_face(root))
+            .collect();
+        unmatched_islands.sort();
+        unmatched_islands.dedup();
+        UpdateReport {
+            unmatched_edges,
+            unmatched_islands,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paper::import::load_example;
+
+    #[test]
+    fn test_update_same_model() {
+        let mut old = load_example("dice.pdo");
+        let i_edge = old
+            .model()
+            .edges()
+            .map(|(i_edge, _)| i_edge)
+            .find(|&i_edge| old.edge_status(i_edge) == EdgeStatus::Joined)
+            .unwrap();
+        old.edge_cut(i_edge, None);
+        let mut new = load_example("dice.pdo");
+        let report = new.update_from_obj(&old);
+        assert!(report.unmatched_edges.is_empty());
+        assert!(report.unmatched_islands.is_empty());
+        assert!(new.edges().eq(old.edges()));
+        assert_eq!(new.num_islands(), old.num_islands());
+    }
+
+    #[test]
+    fn test_match_edges() {
+        let papercraft = load_example("dice.pdo");
+        let map = match_edges(&papercraft, &papercraft, Fit::IDENTITY);
+        assert_eq!(map.len(), papercraft.model().num_edges());
+        assert!(
+            map.iter()
+                .all(|(i_new, (i_old, crossed))| i_new == i_old && !crossed)
+        );
+
+        // Too far away to match anything
+        let (a, b) = model_bounding_box(papercraft.model());
+        let fit = Fit {
+            scale: 1.0,
+            offset: (b - a) * 2.0,
+        };
+        assert!(match_edges(&papercraft, &papercraft, fit).is_empty());
+
+        assert_eq!(
+            Fit::bounding_boxes(papercraft.model(), papercraft.model()),
+            Fit::IDENTITY
+        );
+    }
+
+    #[test]
+    fn test_update_other_model() {
+        let old = load_example("dice.pdo");
+        let mut new = load_example("sphere.pdo");
+        // Cut the sphere in two islands, the dice has only one
+        let i_edge = new
+            .model()
+            .edges()
+            .map(|(i_edge, _)| i_edge)
+            .find(|&i_edge| new.edge_status(i_edge) == EdgeStatus::Joined)
+            .unwrap();
+        new.edge_cut(i_edge, None);
+        let num_visible = new.edges().filter(|&&s| s != EdgeStatus::Hidden).count();
+        let num_islands = new.num_islands();
+        assert!(num_islands > 1);
+
+        let report = new.update_from_obj(&old);
+        // No edge of a sphere is near an edge of a cube, not even fitting one into the other
+        assert_eq!(report.unmatched_edges.len(), num_visible);
+        // Only one island matches the island of the dice, the rest go left of the first page
+        assert_eq!(report.unmatched_islands.len(), num_islands - 1);
+        for &i_island in &report.unmatched_islands {
+            let island = new.island_by_key(i_island).unwrap();
+            assert!(island.location().x < 0.0);
+        }
     }
 }
@@ -0,0 +1,317 @@
+use super::*;
+
+// Minimum overlap, in mm, for two pieces of paper to be considered overlapping
+const OVERLAP_MARGIN: f32 = 0.01;
+// Tolerance, in mm, when checking if a piece is inside the printable area
+const PAGE_TOLERANCE: f32 = 0.01;
+
+/// The problems found in the layout, that would make the printed model wrong or impossible
+/// to build. An empty report means that the model is ready to be printed.
+#[derive(Debug, Default, Serialize)]
+pub struct ValidationReport {
+    /// Pairs of faces that overlap, in the same island or in different ones
+    pub face_overlaps: Vec<FaceOverlap>,
+    /// Flaps that overlap a face or another flap
+    pub flap_collisions: Vec<FlapCollision>,
+    /// Islands that are not completely inside the printable area of a page
+    pub outside_margin: Vec<IslandKey>,
+    /// Islands that are printed in more than one page
+    pub straddling_pages: Vec<PageStraddle>,
+    /// Islands that touch the zone kept free around a registration mark
+    pub in_registration_zone: Vec<IslandKey>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FaceOverlap {
+    pub island_a: IslandKey,
+    pub face_a: FaceIndex,
+    pub island_b: IslandKey,
+    pub face_b: FaceIndex,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FlapCollision {
+    pub island: IslandKey,
+    /// The edge the flap is attached to
+    pub edge: EdgeIndex,
+    pub other_island: IslandKey,
+    /// Exactly one of `other_face` and `other_edge` is set: the face or the flap it collides with
+    pub other_face: Option<FaceIndex>,
+    pub other_edge: Option<EdgeIndex>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PageStraddle {
+    pub island: IslandKey,
+    pub pages: Vec<u32>,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.face_overlaps.is_empty()
+            && self.flap_collisions.is_empty()
+            && self.outside_margin.is_empty()
+            && self.straddling_pages.is_empty()
+            && self.in_registration_zone.is_empty()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum PieceKind {
+    Face(FaceIndex),
+    // The edge of the flap and the face it is attached to
+    Flap(EdgeIndex, FaceIndex),
+}
+
+// A convex bit of paper, flaps may be split in two
+struct Piece {
+    island: IslandKey,
+    kind: PieceKind,
+    tri: [Vector2; 3],
+    bbox: (Vector2, Vector2),
+}
+
+impl Papercraft {
+    /// Checks the current layout for overlapping pieces and pieces that will not be printed
+    /// properly.
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+
+        let mut pieces = Vec::new();
+        for (i_island, island) in self.islands() {
+            let first_piece = pieces.len();
+            self.island_paper_triangles(island, island.matrix(), |i_face, i_edge, tri| {
+                let kind = match i_edge {
+                    None => PieceKind::Face(i_face),
+                    Some(i_edge) => PieceKind::Flap(i_edge, i_face),
+                };
+                pieces.push(Piece {
+                    island: i_island,
+                    kind,
+                    tri,
+                    bbox: util_3d::bounding_box_2d(tri),
+                });
+            });
+            self.validate_island_pages(i_island, &pieces[first_piece..], &mut report);
+        }
+
+        // Sweep along the X axis, so that only pieces with overlapping bounding boxes are compared
+        pieces.sort_by(|a, b| a.bbox.0.x.total_cmp(&b.bbox.0.x));
+        let mut seen_faces = FxHashSet::default();
+        let mut seen_flaps = FxHashSet::default();
+        for (i, a) in pieces.iter().enumerate() {
+            for b in &pieces[i + 1..] {
+                if b.bbox.0.x >= a.bbox.1.x {
+                    break;
+                }
+                if b.bbox.0.y >= a.bbox.1.y || a.bbox.0.y >= b.bbox.1.y {
+                    continue;
+                }
+                // The flap always touches its own face, and the two halves of a flap touch each other
+                let (a, b) = match (a.kind, b.kind) {
+                    (PieceKind::Face(_), PieceKind::Flap(..)) => (b, a),
+                    _ => (a, b),
+                };
+                match (a.kind, b.kind) {
+                    (PieceKind::Flap(_, f), PieceKind::Face(g))
+                        if a.island == b.island && f == g =>
+                    {
+                        continue;
+                    }
+                    (PieceKind::Flap(e1, f1), PieceKind::Flap(e2, f2)) if e1 == e2 && f1 == f2 => {
+                        continue;
+                    }
+                    _ => {}
+                }
+                if !util_3d::triangles_overlap(a.tri, b.tri, OVERLAP_MARGIN) {
+                    continue;
+                }
+                match (a.kind, b.kind) {
+                    (PieceKind::Face(face_a), PieceKind::Face(face_b)) => {
+                        let (a, face_a, b, face_b) = if face_a < face_b {
+                            (a, face_a, b, face_b)
+                        } else {
+                            (b, face_b, a, face_a)
+                        };
+                        if seen_faces.insert((face_a, face_b)) {
+                            report.face_overlaps.push(FaceOverlap {
+                                island_a: a.island,
+                                face_a,
+                                island_b: b.island,
+                                face_b,
+                            });
+                        }
+                    }
+                    (PieceKind::Flap(edge, face), other) => {
+                        let (other_face, other_edge) = match other {
+                            PieceKind::Face(f) => (Some(f), None),
+                            PieceKind::Flap(e, _) => (None, Some(e)),
+                        };
+                        if seen_flaps.insert((edge, face, other_face, other_edge)) {
+                            report.flap_collisions.push(FlapCollision {
+                                island: a.island,
+                                edge,
+                                other_island: b.island,
+                                other_face,
+                                other_edge,
+                            });
+                        }
+                    }
+                    (PieceKind::Face(_), PieceKind::Flap(..)) => unreachable!(),
+                }
+            }
+        }
+        report
+    }
+
+    fn validate_island_pages(
+        &self,
+        i_island: IslandKey,
+        pieces: &[Piece],
+        report: &mut ValidationReport,
+    ) {
+        let options = &self.options;
+        let page_size = Vector2::from(options.page_size);
+        let (top, left, right, bottom) = options.margin;
+
+        let mut pages = Vec::new();
+        let mut outside = false;
+        for p in pieces.iter().flat_map(|piece| piece.tri) {
+            let po = options.global_to_page(p);
+            let in_page = po.col < options.page_cols as i32
+                && po.offset.x >= -PAGE_TOLERANCE
+                && po.offset.y >= -PAGE_TOLERANCE
+                && po.offset.x <= page_size.x + PAGE_TOLERANCE
+                && po.offset.y <= page_size.y + PAGE_TOLERANCE;
+            if !in_page {
+                // In the gap between pages or out of the paper
+                outside = true;
+                continue;
+            }
+            let page = po.row as u32 * options.page_cols + po.col as u32;
+            if !pages.contains(&page) {
+                pages.push(page);
+            }
+            let printable = page < options.pages
+                && po.offset.x >= left - PAGE_TOLERANCE
+                && po.offset.y >= top - PAGE_TOLERANCE
+                && po.offset.x <= page_size.x - right + PAGE_TOLERANCE
+                && po.offset.y <= page_size.y - bottom + PAGE_TOLERANCE;
+            outside |= !printable;
+        }
+        if outside {
+            report.outside_margin.push(i_island);
+        }
+        let zones = options.registration_zones();
+        let in_zone = pages.iter().any(|&page| {
+            let offset = options.page_position(page);
+            zones.iter().any(|&(a, b)| {
+                let (a, b) = (a + offset, b + offset);
+                let halves = [
+                    [a, Vector2::new(b.x, a.y), b],
+                    [a, b, Vector2::new(a.x, b.y)],
+                ];
+                pieces.iter().any(|piece| {
+                    piece.bbox.0.x < b.x
+                        && piece.bbox.0.y < b.y
+                        && piece.bbox.1.x > a.x
+                        && piece.bbox.1.y > a.y
+                        && halves
+                            .iter()
+                            .any(|&h| util_3d::triangles_overlap(piece.tri, h, OVERLAP_MARGIN))
+                })
+            })
+        });
+        if in_zone {
+            report.in_registration_zone.push(i_island);
+        }
+        if pages.len() > 1 {
+            pages.sort();
+            report.straddling_pages.push(PageStraddle {
+                island: i_island,
+                pages,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paper::import::load_example;
+
+    #[test]
+    fn test_validate_packed() {
+        let mut papercraft = load_example("dice.pdo");
+        // At the default scale the net does not fit in a page
+        let mut options = papercraft.options().clone();
+        options.scale /= 2.0;
+        papercraft.set_options(options, false);
+        papercraft.auto_unfold();
+        let report = papercraft.validate();
+        assert!(report.is_ok(), "{report:?}");
+    }
+
+    #[test]
+    fn test_validate_overlap_and_pages() {
+        let mut papercraft = load_example("dice.pdo");
+        // Split the net in two islands and put one over the other
+        let i_edge = papercraft
+            .model()
+            .edges()
+            .map(|(i_edge, _)| i_edge)
+            .find(|&i_edge| papercraft.edge_status(i_edge) == EdgeStatus::Joined)
+            .unwrap();
+        papercraft.edge_cut(i_edge, None);
+        papercraft.options.pages = papercraft.pack_islands();
+        assert!(papercraft.num_islands() > 1);
+        let keys: Vec<_> = papercraft.islands().map(|(k, _)| k).collect();
+        let loc = papercraft.island_by_key(keys[0]).unwrap().location();
+        let island = papercraft.island_by_key_mut(keys[1]).unwrap();
+        let delta = loc - island.location();
+        island.translate(delta);
+
+        let report = papercraft.validate();
+        assert!(!report.face_overlaps.is_empty());
+        assert!(
+            report
+                .face_overlaps
+                .iter()
+                .all(|o| o.island_a != o.island_b)
+        );
+
+        // Now move it across the right border of the first page
+        let x = papercraft.options.page_size.0
+            - papercraft.island_by_key(keys[1]).unwrap().location().x;
+        let island = papercraft.island_by_key_mut(keys[1]).unwrap();
+        island.translate(Vector2::new(x, 0.0));
+        let report = papercraft.validate();
+        assert!(report.outside_margin.contains(&keys[1]));
+    }
+
+    #[test]
+    fn test_validate_registration_zone() {
+        let mut papercraft = load_example("dice.pdo");
+        let mut options = papercraft.options().clone();
+        options.scale /= 2.0;
+        papercraft.set_options(options, false);
+        papercraft.options.pages = papercraft.pack_islands();
+        assert!(papercraft.validate().is_ok());
+
+        let mut options = papercraft.options().clone();
+        options.registration_marks = true;
+        papercraft.set_options(options, false);
+        // Move the net next to the top-left corner of the printable area, where the square is
+        let (top, left, _, _) = papercraft.options.margin;
+        let key = papercraft.islands().next().unwrap().0;
+        let island = papercraft.island_by_key_mut(key).unwrap();
+        let delta = Vector2::new(left + 2.0, top + 2.0) - island.location();
+        island.translate(delta);
+        let report = papercraft.validate();
+        assert!(!report.in_registration_zone.is_empty(), "{report:?}");
+
+        papercraft.options.pages = papercraft.pack_islands();
+        let report = papercraft.validate();
+        assert!(report.is_ok(), "{report:?}");
+    }
+}
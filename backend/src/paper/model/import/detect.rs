@@ -0,0 +1,161 @@
+//! Detection of the format of a model file from its contents.
+//!
+//! File names cannot be trusted, an upload may have any extension or none at all, so the
+//! format is guessed from the magic bytes and the structure of the start of the file.
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ModelFormat {
+    Craft,
+    Pepakura,
+    Glb,
+    Gltf,
+    WaveObj,
+    Stl { binary: bool },
+}
+
+impl ModelFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            ModelFormat::Craft => "Papercraft",
+            ModelFormat::Pepakura => "Pepakura",
+            ModelFormat::Glb | ModelFormat::Gltf => "glTF",
+            ModelFormat::WaveObj => "Wavefront",
+            ModelFormat::Stl { .. } => "STL",
+        }
+    }
+}
+
+// Lines that can start an OBJ file, other than comments
+const OBJ_KEYWORDS: &[&str] = &[
+    "v", "vt", "vn", "vp", "f", "l", "p", "o", "g", "s", "mtllib", "usemtl",
+];
+
+/// Guesses the format of a model, `head` is the start of the file and `len` its full length.
+///
+/// Returns `None` if it does not look like any model that can be imported.
+pub fn detect_format(head: &[u8], len: u64) -> Option<ModelFormat> {
+    if head.starts_with(b"glTF") {
+        return Some(ModelFormat::Glb);
+    }
+    // Craft files are zip files
+    if head.starts_with(b"PK\x03\x04") {
+        return Some(ModelFormat::Craft);
+    }
+    if head.starts_with(b"version 3\n") {
+        return Some(ModelFormat::Pepakura);
+    }
+    // A binary STL is an 80 bytes header, the number of triangles and 50 bytes per triangle.
+    // The header may start with "solid", so this goes before the check of the text STL.
+    if let Some(count) = head.get(80..84) {
+        let count = u32::from_le_bytes(count.try_into().unwrap());
+        if 84 + 50 * u64::from(count) == len {
+            return Some(ModelFormat::Stl { binary: true });
+        }
+    }
+
+    // The rest are text formats
+    if head.contains(&0) {
+        return None;
+    }
+    let text = head.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(head);
+    let text = String::from_utf8_lossy(text);
+    let text = text.trim_start();
+    // Any JSON starts like this, a glTF must have an asset object, usually at the start
+    if text.starts_with('{') {
+        return has_json_key(text, "asset").then_some(ModelFormat::Gltf);
+    }
+    if text.starts_with("solid") {
+        return Some(ModelFormat::Stl { binary: false });
+    }
+    detect_obj(&text, head.len() as u64 == len)
+}
+
+fn has_json_key(text: &str, key: &str) -> bool {
+    let quoted = format!("\"{key}\"");
+    text.match_indices(&quoted)
+        .any(|(i, _)| text[i + quoted.len()..].trim_start().starts_with(':'))
+}
+
+fn detect_obj(text: &str, is_complete: bool) -> Option<ModelFormat> {
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let keyword = line.split_whitespace().next().unwrap();
+        return OBJ_KEYWORDS
+            .contains(&keyword)
+            .then_some(ModelFormat::WaveObj);
+    }
+    // Only comments: a long header of an OBJ may fill the whole `head`, a short file is no model
+    (!is_complete).then_some(ModelFormat::WaveObj)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(data: &[u8]) -> Option<ModelFormat> {
+        detect_format(data, data.len() as u64)
+    }
+
+    #[test]
+    fn test_detect_binary() {
+        assert_eq!(detect(b"glTF\x02\0\0\0"), Some(ModelFormat::Glb));
+        assert_eq!(detect(b"PK\x03\x04\x14\0"), Some(ModelFormat::Craft));
+        assert_eq!(
+            detect(b"version 3\n\x06\0\0\0"),
+            Some(ModelFormat::Pepakura)
+        );
+
+        // A binary STL with a misleading header
+        let mut stl = b"solid but binary".to_vec();
+        stl.resize(80, b' ');
+        stl.extend_from_slice(&2u32.to_le_bytes());
+        stl.resize(84 + 2 * 50, 0);
+        assert_eq!(detect(&stl), Some(ModelFormat::Stl { binary: true }));
+        // With a wrong size it is nothing
+        stl.push(0);
+        assert_eq!(detect(&stl), None);
+    }
+
+    #[test]
+    fn test_detect_text() {
+        assert_eq!(
+            detect(b"\xEF\xBB\xBF  {\"asset\": {\"version\": \"2.0\"}}"),
+            Some(ModelFormat::Gltf)
+        );
+        assert_eq!(
+            detect(b"{\"accessors\": [], \"asset\" : {\"version\": \"2.0\"}}"),
+            Some(ModelFormat::Gltf)
+        );
+        // Other JSON files are not models
+        assert_eq!(detect(b"{}"), None);
+        assert_eq!(detect(b"{\"name\": \"asset\"}"), None);
+        assert_eq!(
+            detect(b"solid cube\n  facet normal 0 0 1\n"),
+            Some(ModelFormat::Stl { binary: false })
+        );
+        assert_eq!(
+            detect(b"# Blender\n\nmtllib cube.mtl\no Cube\nv 1 1 1\n"),
+            Some(ModelFormat::WaveObj)
+        );
+        assert_eq!(detect(b"newmtl Material\nKd 1 1 1\n"), None);
+        assert_eq!(detect(b"# only a comment\n"), None);
+        assert_eq!(detect(b"Hello, world!\n"), None);
+        assert_eq!(detect(b""), None);
+    }
+
+    #[test]
+    fn test_detect_examples() {
+        let examples = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        for (name, format) in [
+            ("triangle.obj", ModelFormat::WaveObj),
+            ("dice.pdo", ModelFormat::Pepakura),
+            ("die.craft", ModelFormat::Craft),
+        ] {
+            let data = std::fs::read(examples.join(name)).unwrap();
+            assert_eq!(detect(&data), Some(format), "{name}");
+        }
+    }
+}
@@ -110,11 +110,27 @@ impl<'a> Gltf<'a> {
             return decode_data_uri(data);
         }
 
-        let file_name = uri_decode(uri)?;
-        let bs = fs::read(self.dir.join(file_name))?;
+        let path = self
+            .find_uri_file(uri)?
+            .ok_or_else(|| anyhow!("file {uri} not found"))?;
+        let bs = fs::read(path)?;
         Ok(bs)
     }
 
+    // Files are looked for next to the glTF, first with their directories then without them
+    fn find_uri_file(&self, uri: &str) -> Result<Option<PathBuf>> {
+        let file_name = uri_decode(uri)?;
+        let path = self.dir.join(&file_name);
+        if path.exists() {
+            return Ok(Some(path));
+        }
+        let Some(base_name) = Path::new(&file_name).file_name() else {
+            return Ok(None);
+        };
+        let path = self.dir.join(base_name);
+        Ok(path.exists().then_some(path))
+    }
+
     fn load_buffers(&mut self, mut bin_buffer: Option<&'a [u8]>) -> Result<()> {
         for buf in &self.header.buffers {
             match buf.uri {
@@ -140,7 +156,8 @@ impl<'a> Gltf<'a> {
         Ok(())
     }
 
-    pub fn load_images(&self) -> Result<Vec<(String, DynamicImage)>> {
+    // Images in external files that are not found are `None`, named as their uri
+    pub fn load_images(&self) -> Result<Vec<(String, Option<DynamicImage>)>> {
         let mut images = Vec::new();
         for (i, img) in self.header.images.iter().enumerate() {
             let data;
@@ -151,6 +168,13 @@ impl<'a> Gltf<'a> {
                         .ok_or(anyhow!("missing bufferView {bv}"))?;
                     bs
                 }
+                Binary::Uri(uri)
+                    if !uri.starts_with("data:") && self.find_uri_file(uri)?.is_none() =>
+                {
+                    log::warn!("image {uri} not found");
+                    images.push((uri_decode(uri)?, None));
+                    continue;
+                }
                 Binary::Uri(uri) => {
                     data = self.load_uri(uri)?;
                     &data[..]
@@ -174,7 +198,7 @@ impl<'a> Gltf<'a> {
                 .name
                 .map(String::from)
                 .unwrap_or_else(|| format!("tex_{i}"));
-            images.push((name, pixbuf));
+            images.push((name, Some(pixbuf)));
         }
         Ok(images)
     }
@@ -8,7 +8,7 @@ use super::data::*;
 
 pub struct GltfImporter {
     // Cell to avoid cloning
-    images: Cell<Vec<(String, DynamicImage)>>,
+    images: Cell<Vec<(String, Option<DynamicImage>)>>,
     // 3 vertices per face
     vertices: Vec<Vertex>,
     // 1 tex_id per face
@@ -85,10 +85,7 @@ impl Importer for GltfImporter {
     fn build_textures(&self) -> Vec<Texture> {
         let mut texs = vec![Texture::default()];
         for (file_name, pixbuf) in self.images.take() {
-            texs.push(Texture {
-                file_name,
-                pixbuf: Some(pixbuf),
-            })
+            texs.push(Texture { file_name, pixbuf })
         }
         texs
     }
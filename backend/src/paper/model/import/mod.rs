@@ -1,12 +1,15 @@
-use anyhow::{Context, Result, anyhow, bail};
-use std::io::{BufRead, Read};
-use std::panic::catch_unwind;
+use anyhow::{Context, Result, anyhow};
+use cancel_rw::{Cancellable, CancellationToken};
+use std::io::{BufRead, Read, Seek, SeekFrom};
+use std::panic::{AssertUnwindSafe, catch_unwind};
 use std::path::Path;
 
 use super::{EdgeStatus, Island, MaterialIndex, Model, PaperOptions, Texture, Vertex, VertexIndex};
 use crate::paper::{FlapSide, PageOffset, Papercraft};
 use crate::util_3d::{Vector2, Vector3};
+pub use detect::{ModelFormat, detect_format};
 
+mod detect;
 pub mod gltf;
 pub mod pepakura;
 pub mod stl;
@@ -99,27 +102,134 @@ pub trait Importer: Sized {
     fn build_options(&self) -> Option<PaperOptions> {
         None
     }
+    // Other files the model refers to that could not be found, such as the MTL of an OBJ.
+    // The missing textures are in the model, see `missing_textures()`.
+    fn missing_files(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// A model read by `import_model_file_with_progress`
+pub struct ImportedModel {
+    pub papercraft: Papercraft,
+    pub is_native: bool,
+    /// See `Importer::missing_files()`
+    pub missing_files: Vec<String>,
 }
 
 // Returns (model, is_native_format)
 pub fn import_model_file(file_name: &Path) -> Result<(Papercraft, bool)> {
+    let imported = import_model_file_with_progress(file_name, &CancellationToken::new(), &|_| {})?;
+    Ok((imported.papercraft, imported.is_native))
+}
+
+/// Loads one of the models in the `examples` directory, for the tests.
+#[cfg(test)]
+pub(crate) fn load_example(file_name: &str) -> Papercraft {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("examples")
+        .join(file_name);
+    import_model_file(&path).unwrap().0
+}
+
+// Like `import_model_file`, but reading the file stops with an error if `ct` is cancelled, and
+// `on_read` is called with the fraction of the file that has been read so far.
+pub fn import_model_file_with_progress(
+    file_name: &Path,
+    ct: &CancellationToken,
+    on_read: &dyn Fn(f32),
+) -> Result<ImportedModel> {
     // Models have a lot of indices and unwraps, a corrupted file could easily panic
-    match catch_unwind(|| import_model_file_priv(file_name)) {
+    match catch_unwind(AssertUnwindSafe(|| {
+        import_model_file_priv(file_name, ct, on_read)
+    })) {
         Ok(res) => res,
         Err(err) => {
-            if let Some(msg) = err.downcast_ref::<&str>() {
-                bail!(
-                    "Panic importing the model '{}'!\n{}",
-                    file_name.display(),
-                    msg
-                );
-            } else {
-                bail!("Panic importing the model '{}'!", file_name.display());
-            }
+            let msg = err
+                .downcast_ref::<&str>()
+                .map(|s| String::from(*s))
+                .or_else(|| err.downcast_ref::<String>().cloned());
+            Err(anyhow!(ImporterPanic(msg)))
+                .with_context(|| format!("Panic importing the model '{}'!", file_name.display()))
         }
     }
 }
-pub fn import_model_file_priv(file_name: &Path) -> Result<(Papercraft, bool)> {
+
+/// The error of a file that is not a model in any supported format
+#[derive(Debug)]
+pub struct UnsupportedFormat;
+
+impl std::fmt::Display for UnsupportedFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Unknown model format")
+    }
+}
+
+impl std::error::Error for UnsupportedFormat {}
+
+/// The error of an importer that panicked, with the panic message if any
+#[derive(Debug)]
+pub struct ImporterPanic(pub Option<String>);
+
+impl std::fmt::Display for ImporterPanic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Some(msg) => f.write_str(msg),
+            None => f.write_str("Unknown panic"),
+        }
+    }
+}
+
+impl std::error::Error for ImporterPanic {}
+
+// Names of the textures used by the model whose image could not be found when importing it
+pub fn missing_textures(papercraft: &Papercraft) -> Vec<String> {
+    papercraft
+        .model()
+        .textures()
+        .filter(|t| !t.file_name().is_empty() && t.pixbuf().is_none())
+        .map(|t| t.file_name().to_owned())
+        .collect()
+}
+
+// Reports the position in the file after every read
+struct ProgressReader<'a, R> {
+    inner: R,
+    len: u64,
+    on_read: &'a dyn Fn(f32),
+}
+
+impl<R: Seek> ProgressReader<'_, R> {
+    fn report(&mut self) -> std::io::Result<()> {
+        if self.len > 0 {
+            let pos = self.inner.stream_position()?;
+            (self.on_read)(pos as f32 / self.len as f32);
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.report()?;
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for ProgressReader<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let res = self.inner.seek(pos)?;
+        self.report()?;
+        Ok(res)
+    }
+}
+
+pub fn import_model_file_priv(
+    file_name: &Path,
+    ct: &CancellationToken,
+    on_read: &dyn Fn(f32),
+) -> Result<ImportedModel> {
     let ext = match file_name.extension() {
         None => String::new(),
         Some(ext) => {
@@ -128,44 +238,62 @@ pub fn import_model_file_priv(file_name: &Path) -> Result<(Papercraft, bool)> {
             ext
         }
     };
+    if ext == "mtl" {
+        return Err(anyhow!(UnsupportedFormat))
+            .context("MTL are material files for OBJ models. Try opening the OBJ file instead.");
+    }
 
     let f = std::fs::File::open(file_name)
         .with_context(|| format!("Error opening file {}", file_name.display()))?;
-    let f = std::io::BufReader::new(f);
-    let mut is_native = false;
-
-    let papercraft = match ext.as_str() {
-        "craft" => {
-            is_native = true;
-            Papercraft::load(f)
-                .with_context(|| format!("Error reading Papercraft file {}", file_name.display()))?
-        }
-        "pdo" | "pbo" => {
-            let importer = pepakura::PepakuraImporter::new(f)
-                .with_context(|| format!("Error reading Pepakura file {}", file_name.display()))?;
-            Papercraft::import(importer)
-        }
-        "stl" => {
-            let importer = stl::StlImporter::new(f)
-                .with_context(|| format!("Error reading STL file {}", file_name.display()))?;
-            Papercraft::import(importer)
+    let len = f.metadata().map(|m| m.len()).unwrap_or(0);
+    let f = ProgressReader {
+        inner: Cancellable::new(f, ct.clone()),
+        len,
+        on_read,
+    };
+    let mut f = std::io::BufReader::new(f);
+
+    // The extension may be wrong or missing, the contents say what the file is
+    let head = f
+        .fill_buf()
+        .with_context(|| format!("Error reading file {}", file_name.display()))?;
+    let format = detect::detect_format(head, len)
+        .ok_or_else(|| anyhow!(UnsupportedFormat))
+        .with_context(|| format!("Error reading file {}", file_name.display()))?;
+    let err = || {
+        format!(
+            "Error reading {} file {}",
+            format.name(),
+            file_name.display()
+        )
+    };
+
+    fn import(importer: impl Importer) -> (Papercraft, Vec<String>) {
+        let missing_files = importer.missing_files();
+        (Papercraft::import(importer), missing_files)
+    }
+    let (papercraft, missing_files) = match format {
+        ModelFormat::Craft => (Papercraft::load(f).with_context(err)?, Vec::new()),
+        ModelFormat::Pepakura => {
+            let importer = pepakura::PepakuraImporter::new(f).with_context(err)?;
+            import(importer)
         }
-        "mtl" => {
-            anyhow::bail!(
-                "MTL are material files for OBJ models. Try opening the OBJ file instead."
-            );
+        ModelFormat::Stl { binary } => {
+            let importer = stl::StlImporter::new(f, binary).with_context(err)?;
+            import(importer)
         }
-        "glb" | "gltf" => {
-            let importer = gltf::GltfImporter::new(f, file_name)
-                .with_context(|| format!("Error reading glTF file {}", file_name.display()))?;
-            Papercraft::import(importer)
+        ModelFormat::Glb | ModelFormat::Gltf => {
+            let importer = gltf::GltfImporter::new(f, file_name).with_context(err)?;
+            import(importer)
         }
-        // "obj" plus unknown extensions are tried as obj, that was the default previously
-        _ => {
-            let importer = waveobj::WaveObjImporter::new(f, file_name)
-                .with_context(|| format!("Error reading Wavefront file {}", file_name.display()))?;
-            Papercraft::import(importer)
+        ModelFormat::WaveObj => {
+            let importer = waveobj::WaveObjImporter::new(f, file_name).with_context(err)?;
+            import(importer)
         }
     };
-    Ok((papercraft, is_native))
+    Ok(ImportedModel {
+        papercraft,
+        is_native: format == ModelFormat::Craft,
+        missing_files,
+    })
 }
@@ -14,15 +14,18 @@ pub struct Triangle {
 }
 
 impl Stl {
-    pub fn new<R: BufRead>(mut f: R) -> Result<Stl> {
+    // Binary files may have a header starting with "solid" too, so the caller tells which one it is
+    pub fn new<R: BufRead>(mut f: R, binary: bool) -> Result<Stl> {
         let mut hdr = [0; 80];
+        if binary {
+            f.read_exact(&mut hdr)?;
+            return Self::new_binary(f);
+        }
         f.read_exact(&mut hdr[..5])?;
-        if &hdr[..5] == b"solid" {
-            Self::new_text(f)
-        } else {
-            f.read_exact(&mut hdr[5..])?;
-            Self::new_binary(f)
+        if &hdr[..5] != b"solid" {
+            bail!("missing solid in text STL");
         }
+        Self::new_text(f)
     }
     fn new_binary<R: BufRead>(mut f: R) -> Result<Stl> {
         let rdr = &mut f;
@@ -7,8 +7,8 @@ pub struct StlImporter {
 }
 
 impl StlImporter {
-    pub fn new<R: BufRead>(f: R) -> Result<StlImporter> {
-        let stl = data::Stl::new(f)?;
+    pub fn new<R: BufRead>(f: R, binary: bool) -> Result<StlImporter> {
+        let stl = data::Stl::new(f, binary)?;
 
         Ok(StlImporter { stl })
     }
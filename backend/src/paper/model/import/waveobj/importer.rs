@@ -8,20 +8,25 @@ use image::DynamicImage;
 
 pub struct WaveObjImporter {
     obj: data::Model,
-    texture_map: FxHashMap<String, Cell<(String, DynamicImage)>>,
+    // A texture that is not found has only its name
+    texture_map: FxHashMap<String, Cell<(String, Option<DynamicImage>)>>,
     // VertexIndex -> FaceVertex
     all_vertices: Vec<data::FaceVertex>,
+    // The name of the matlib, if it is not found
+    missing_matlib: Option<String>,
 }
 
 impl WaveObjImporter {
     pub fn new<R: BufRead>(f: R, file_name: &Path) -> Result<Self> {
         let (matlib, obj) = data::Model::from_reader(f)?;
+        let mut missing_matlib = None;
         let matlib = match matlib {
             Some(matlib) => {
                 match data::solve_find_matlib_file(matlib.as_ref(), file_name) {
                     Some(path) => Some(path),
                     None => {
                         eprintln!("Warning: {} matlib not found, proceeding without materials", matlib);
+                        missing_matlib = Some(matlib);
                         None
                     }
                 }
@@ -50,10 +55,24 @@ impl WaveObjImporter {
                             .file_name()
                             .and_then(|f| f.to_str())
                             .ok_or_else(|| anyhow!("Invalid texture name"))?;
-                        texture_map
-                            .insert(lib.name().to_owned(), Cell::new((map_name.to_owned(), img)));
+                        texture_map.insert(
+                            lib.name().to_owned(),
+                            Cell::new((map_name.to_owned(), Some(img))),
+                        );
                     } else {
-                        anyhow::bail!("{} texture from {} matlib not found", map, matlib.display());
+                        log::warn!(
+                            "{} texture from {} matlib not found, proceeding without it",
+                            map,
+                            matlib.display()
+                        );
+                        let map_name = Path::new(map)
+                            .file_name()
+                            .and_then(|f| f.to_str())
+                            .unwrap_or(map);
+                        texture_map.insert(
+                            lib.name().to_owned(),
+                            Cell::new((map_name.to_owned(), None)),
+                        );
                     }
                 }
             }
@@ -73,6 +92,7 @@ impl WaveObjImporter {
             obj,
             texture_map,
             all_vertices,
+            missing_matlib,
         })
     }
 }
@@ -136,10 +156,7 @@ impl Importer for WaveObjImporter {
                 match tex {
                     Some(tex) => {
                         let (file_name, pixbuf) = tex.take();
-                        Texture {
-                            file_name,
-                            pixbuf: Some(pixbuf),
-                        }
+                        Texture { file_name, pixbuf }
                     }
                     None => Texture::default(),
                 }
@@ -150,4 +167,7 @@ impl Importer for WaveObjImporter {
         }
         textures
     }
+    fn missing_files(&self) -> Vec<String> {
+        self.missing_matlib.iter().cloned().collect()
+    }
 }
@@ -9,24 +9,45 @@ use crate::paper::{IslandKey, PaperOptions};
 // Actually ModelInfo is in model.rs. We need to be careful with imports.
 // Let's just use generic or specific types.
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 pub struct RenderablePapercraft {
     pub model: crate::paper::Model,
+    pub model_version: String, // Changes only if the model does
     pub islands: Vec<RenderableIsland>,
     pub options: PaperOptions,
 }
 
-#[derive(Serialize)]
+// What an action changed, the rest is as it was before the action
+#[derive(Clone, Serialize)]
+pub struct RenderableDelta {
+    pub model_version: String,
+    pub added: Vec<RenderableIsland>,
+    pub changed: Vec<RenderableIsland>,
+    pub removed: Vec<IslandKey>,
+    pub edges: Vec<RenderableEdgeStatus>,
+    pub options: Option<PaperOptions>, // None if unchanged
+}
+
+#[derive(Clone, Serialize)]
+pub struct RenderableEdgeStatus {
+    pub id: crate::paper::EdgeIndex,
+    pub status: crate::paper::EdgeStatus, // As in the craft file: 0 hidden, 1 joined, 2-4 cut
+}
+
+#[derive(Clone, Serialize)]
 pub struct RenderableIsland {
     pub id: IslandKey,
     pub pos: Vector2,
     pub rot: f32,
+    pub locked: bool,
+    pub name: String,
+    pub name_pos: Option<Vector2>, // None if island names are not printed
     pub faces: Vec<RenderableFace>,
     pub edges: Vec<RenderableEdge>,
     pub flaps: Vec<RenderableFlap>,
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 pub struct RenderableFace {
     pub id: crate::paper::FaceIndex,
     pub vertices: Vec<Vector2>,
@@ -34,17 +55,29 @@ pub struct RenderableFace {
     pub vs: Vec<u32>, // Vertex indices for UV coordinate lookup
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 pub struct RenderableEdge {
     pub id: crate::paper::EdgeIndex,
     pub start: Vector2,
     pub end: Vector2,
     pub kind: String, // "cut", "mountain", "valley"
+    pub label: Option<RenderableEdgeLabel>, // Only for cut edges with an edge id
+}
+
+#[derive(Clone, Serialize)]
+pub struct RenderableEdgeLabel {
+    pub id: u32,
+    pub text: String, // Partner island name and edge id, "B:12"
+    pub partner_island: IslandKey,
+    pub partner_face: crate::paper::FaceIndex,
+    pub pos: Vector2, // Center of the text baseline
+    pub angle: f32,
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 pub struct RenderableFlap {
     pub id: crate::paper::FaceIndex, // Associated face
+    pub edge: crate::paper::EdgeIndex,
     pub vertices: Vec<Vector2>,
 }
 
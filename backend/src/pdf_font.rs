@@ -0,0 +1,555 @@
+//! TrueType fonts embedded in the PDF export, for the texts that the standard Helvetica cannot
+//! write, such as Japanese, Cyrillic or Greek island names.
+//!
+//! The font is written as a Type0 font with the Identity-H encoding, so the strings are just the
+//! glyph ids, and only the glyphs used by the texts are kept in the embedded file.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result, anyhow, bail};
+use flate2::{Compression, write::ZlibEncoder};
+use lopdf::{Dictionary, Document, Object, Stream, dictionary};
+use ttf_parser::{Face, GlyphId, Tag};
+
+// Directories where the fonts are looked for, after those in `PAPERCRAFT_FONTS`
+const SYSTEM_FONT_DIRS: &[&str] = &[
+    "/usr/share/fonts",
+    "/usr/local/share/fonts",
+    "/Library/Fonts",
+    "/System/Library/Fonts",
+    "C:\\Windows\\Fonts",
+];
+// How deep the font directories are searched
+const MAX_FONT_DIR_DEPTH: u32 = 4;
+
+// The files of the fonts already found, by lowercase name, searching the directories is slow.
+// A font that is not found is not remembered, it may be installed later.
+static FOUND_FONTS: Mutex<BTreeMap<String, PathBuf>> = Mutex::new(BTreeMap::new());
+
+// The tables needed to draw the glyphs of a TrueType font in a PDF
+const SUBSET_TABLES: [&[u8; 4]; 9] = [
+    b"cvt ", b"fpgm", b"glyf", b"head", b"hhea", b"hmtx", b"loca", b"maxp", b"prep",
+];
+
+/// A TrueType font, and the glyphs that the texts written with it use.
+pub struct TrueTypeFont {
+    data: Vec<u8>,
+    /// The glyphs used, with the text each one writes
+    used: BTreeMap<u16, char>,
+}
+
+impl TrueTypeFont {
+    /// Finds the font named `name` and loads it.
+    ///
+    /// The name is the file name, without the extension, of a `.ttf`, `.otf` or `.ttc` file in
+    /// the directories of the `PAPERCRAFT_FONTS` variable or the fonts directories of the system.
+    pub fn find(name: &str) -> Result<TrueTypeFont> {
+        let key = name.to_lowercase();
+        let found = FOUND_FONTS.lock().unwrap().get(&key).cloned();
+        // If it fails it may have been removed, look for it again
+        if let Some(font) = found.and_then(|path| TrueTypeFont::load(&path).ok()) {
+            return Ok(font);
+        }
+
+        let mut dirs: Vec<PathBuf> = std::env::var_os("PAPERCRAFT_FONTS")
+            .map(|dirs| std::env::split_paths(&dirs).collect())
+            .unwrap_or_default();
+        if let Some(home) = std::env::var_os("HOME") {
+            dirs.push(Path::new(&home).join(".fonts"));
+            dirs.push(Path::new(&home).join(".local/share/fonts"));
+        }
+        dirs.extend(SYSTEM_FONT_DIRS.iter().map(PathBuf::from));
+
+        let (path, font) = find_in(&dirs, name)?;
+        FOUND_FONTS.lock().unwrap().insert(key, path);
+        Ok(font)
+    }
+
+    fn load(path: &Path) -> Result<TrueTypeFont> {
+        let data = std::fs::read(path)
+            .with_context(|| format!("Error reading font {}", path.display()))?;
+        TrueTypeFont::new(data).with_context(|| format!("Error loading font {}", path.display()))
+    }
+
+    pub fn new(data: Vec<u8>) -> Result<TrueTypeFont> {
+        let face = Face::parse(&data, 0)?;
+        if face.raw_face().table(Tag::from_bytes(b"glyf")).is_none() {
+            bail!("Only fonts with TrueType outlines can be embedded");
+        }
+        Ok(TrueTypeFont {
+            data,
+            used: BTreeMap::new(),
+        })
+    }
+
+    fn face(&self) -> Face<'_> {
+        // Already checked in `new()`
+        Face::parse(&self.data, 0).unwrap()
+    }
+
+    /// Converts `text` into a string of glyph ids for the Identity-H encoding, and returns it
+    /// with its width, in thousandths of the font size.
+    ///
+    /// The characters that are not in the font are drawn as the missing glyph.
+    pub fn encode(&mut self, text: &str) -> (Vec<u8>, f32) {
+        let face = self.face();
+        let scale = 1000.0 / f32::from(face.units_per_em());
+        let mut bytes = Vec::with_capacity(2 * text.len());
+        let mut width = 0.0;
+        for c in text.chars() {
+            let glyph = face.glyph_index(c).unwrap_or(GlyphId(0));
+            width += f32::from(face.glyph_hor_advance(glyph).unwrap_or(0)) * scale;
+            bytes.extend_from_slice(&glyph.0.to_be_bytes());
+            self.used.entry(glyph.0).or_insert(c);
+        }
+        (bytes, width)
+    }
+
+    /// Embeds the subset of the used glyphs in `doc` and returns the font dictionary.
+    pub fn pdf_font(&self, doc: &mut Document) -> Result<Dictionary> {
+        let face = self.face();
+        let scale = 1000.0 / f32::from(face.units_per_em());
+        let glyphs: BTreeSet<u16> = self.used.keys().copied().collect();
+
+        // The subset gets a tag made from its glyphs, as the PDF standard asks
+        let hash = fxhash::hash64(&glyphs);
+        let tag: String = (0..6)
+            .map(|i| char::from(b'A' + ((hash >> (i * 5)) % 26) as u8))
+            .collect();
+        let ps_name = face
+            .names()
+            .into_iter()
+            .filter(|name| name.name_id == ttf_parser::name_id::POST_SCRIPT_NAME)
+            .find_map(|name| name.to_string())
+            .unwrap_or_else(|| String::from("TrueType"));
+        let base_font = format!("{tag}+{}", ps_name.replace(' ', ""));
+
+        let subset = self.subset(&face, &glyphs)?;
+        let mut file = Stream::new(
+            dictionary! { "Length1" => subset.len() as i64 },
+            deflate(&subset)?,
+        );
+        file.dict.set("Filter", "FlateDecode");
+        let id_file = doc.add_object(file);
+
+        let bbox = face.global_bounding_box();
+        let to_pdf = |v: i16| (f32::from(v) * scale).round() as i64;
+        let id_descriptor = doc.add_object(dictionary! {
+            "Type" => "FontDescriptor",
+            "FontName" => Object::Name(base_font.clone().into_bytes()),
+            // Symbolic, the glyphs are not those of the standard Latin set
+            "Flags" => 4,
+            "FontBBox" => vec![
+                to_pdf(bbox.x_min).into(),
+                to_pdf(bbox.y_min).into(),
+                to_pdf(bbox.x_max).into(),
+                to_pdf(bbox.y_max).into(),
+            ],
+            "ItalicAngle" => face.italic_angle(),
+            "Ascent" => to_pdf(face.ascender()),
+            "Descent" => to_pdf(face.descender()),
+            "CapHeight" => to_pdf(face.capital_height().unwrap_or(face.ascender())),
+            "StemV" => 80,
+            "FontFile2" => id_file,
+        });
+
+        // Each glyph with its own width, as they are usually not consecutive
+        let mut widths: Vec<Object> = Vec::new();
+        for &glyph in &glyphs {
+            let advance = face.glyph_hor_advance(GlyphId(glyph)).unwrap_or(0);
+            let width = (f32::from(advance) * scale).round() as i64;
+            widths.push(Object::Integer(i64::from(glyph)));
+            widths.push(Object::Array(vec![Object::Integer(width)]));
+        }
+        let id_cid_font = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "CIDFontType2",
+            "BaseFont" => Object::Name(base_font.clone().into_bytes()),
+            "CIDSystemInfo" => dictionary! {
+                "Registry" => Object::string_literal("Adobe"),
+                "Ordering" => Object::string_literal("Identity"),
+                "Supplement" => 0,
+            },
+            "FontDescriptor" => id_descriptor,
+            "W" => widths,
+            "CIDToGIDMap" => "Identity",
+        });
+
+        let id_to_unicode = doc.add_object(Stream::new(dictionary! {}, self.to_unicode()));
+        Ok(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type0",
+            "BaseFont" => Object::Name(base_font.into_bytes()),
+            "Encoding" => "Identity-H",
+            "DescendantFonts" => vec![id_cid_font.into()],
+            "ToUnicode" => id_to_unicode,
+        })
+    }
+
+    // A CMap from the glyphs to the text, so that it can be copied and searched
+    fn to_unicode(&self) -> Vec<u8> {
+        let mut cmap = String::from(
+            "/CIDInit /ProcSet findresource begin\n\
+             12 dict begin\n\
+             begincmap\n\
+             /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+             /CMapName /Adobe-Identity-UCS def\n\
+             /CMapType 2 def\n\
+             1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n",
+        );
+        let used: Vec<_> = self.used.iter().collect();
+        // At most 100 entries in each block
+        for block in used.chunks(100) {
+            cmap += &format!("{} beginbfchar\n", block.len());
+            for (glyph, c) in block {
+                let utf16: String = c
+                    .encode_utf16(&mut [0; 2])
+                    .iter()
+                    .map(|u| format!("{u:04X}"))
+                    .collect();
+                cmap += &format!("<{glyph:04X}> <{utf16}>\n");
+            }
+            cmap += "endbfchar\n";
+        }
+        cmap += "endcmap\n\
+                 CMapName currentdict /CMap defineresource pop\n\
+                 end\n\
+                 end\n";
+        cmap.into_bytes()
+    }
+
+    // A copy of the font with only the tables needed by the PDF and the outlines of `glyphs`.
+    // The rest of the glyphs are left empty, so that the glyph ids do not change.
+    fn subset(&self, face: &Face, glyphs: &BTreeSet<u16>) -> Result<Vec<u8>> {
+        let raw = face.raw_face();
+        let table = |tag: &[u8; 4]| raw.table(Tag::from_bytes(tag));
+        let missing =
+            |tag: &[u8; 4]| anyhow!("Missing font table {}", String::from_utf8_lossy(tag));
+        let head = table(b"head").ok_or_else(|| missing(b"head"))?;
+        let loca = table(b"loca").ok_or_else(|| missing(b"loca"))?;
+        let glyf = table(b"glyf").ok_or_else(|| missing(b"glyf"))?;
+        if head.len() < 54 {
+            bail!("Malformed font table head");
+        }
+
+        let num_glyphs = usize::from(face.number_of_glyphs());
+        let long_loca = head[50..52] != [0, 0];
+        let offsets: Vec<usize> = (0..=num_glyphs)
+            .map(|i| {
+                if long_loca {
+                    read_u32(loca, 4 * i).map(|o| o as usize)
+                } else {
+                    read_u16(loca, 2 * i).map(|o| 2 * usize::from(o))
+                }
+            })
+            .collect::<Option<_>>()
+            .context("Malformed font table loca")?;
+        let glyph_data = |gid: usize| {
+            let (start, end) = (offsets[gid], offsets[gid + 1]);
+            glyf.get(start..end.max(start)).unwrap_or(&[])
+        };
+
+        // The missing glyph is always there, and composite glyphs need their components
+        let mut keep = BTreeSet::new();
+        let mut pending: Vec<usize> = glyphs.iter().map(|&g| usize::from(g)).collect();
+        pending.push(0);
+        while let Some(gid) = pending.pop() {
+            if gid >= num_glyphs || !keep.insert(gid) {
+                continue;
+            }
+            pending.extend(composite_components(glyph_data(gid)).map(usize::from));
+        }
+
+        let mut new_glyf = Vec::new();
+        let mut new_loca = Vec::with_capacity(4 * (num_glyphs + 1));
+        for gid in 0..num_glyphs {
+            new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+            if keep.contains(&gid) {
+                new_glyf.extend_from_slice(glyph_data(gid));
+                new_glyf.resize(new_glyf.len().next_multiple_of(4), 0);
+            }
+        }
+        new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+        // Long offsets in loca, and the checksum adjustment computed again
+        let mut new_head = head.to_vec();
+        new_head[8..12].fill(0);
+        new_head[50..52].copy_from_slice(&1i16.to_be_bytes());
+
+        let mut tables: Vec<(&[u8; 4], Vec<u8>)> = Vec::new();
+        for tag in SUBSET_TABLES {
+            let data = match tag {
+                b"glyf" => std::mem::take(&mut new_glyf),
+                b"loca" => std::mem::take(&mut new_loca),
+                b"head" => std::mem::take(&mut new_head),
+                _ => match table(tag) {
+                    Some(data) => data.to_vec(),
+                    None if matches!(tag, b"hhea" | b"hmtx" | b"maxp") => return Err(missing(tag)),
+                    // Hinting tables are optional
+                    None => continue,
+                },
+            };
+            tables.push((tag, data));
+        }
+
+        let mut font = write_sfnt(&tables);
+        // The head table goes after the directory, in the order of SUBSET_TABLES
+        let head_offset = 12
+            + 16 * tables.len()
+            + tables
+                .iter()
+                .take_while(|(tag, _)| *tag != b"head")
+                .map(|(_, data)| data.len().next_multiple_of(4))
+                .sum::<usize>();
+        let adjustment = 0xB1B0_AFBAu32.wrapping_sub(checksum(&font));
+        font[head_offset + 8..head_offset + 12].copy_from_slice(&adjustment.to_be_bytes());
+        Ok(font)
+    }
+}
+
+// Looks for a font file named `name` in `dir` and its subdirectories, up to `depth` levels
+// The first font named `name` in `dirs` that can be embedded.
+//
+// The same font is often installed as OpenType with CFF outlines, that cannot be embedded, and as
+// TrueType, so every file with that name is tried.
+fn find_in(dirs: &[PathBuf], name: &str) -> Result<(PathBuf, TrueTypeFont)> {
+    let mut paths = Vec::new();
+    for dir in dirs {
+        find_font_files(dir, name, MAX_FONT_DIR_DEPTH, &mut paths);
+    }
+    let mut first_error = None;
+    for path in paths {
+        match TrueTypeFont::load(&path) {
+            Ok(font) => return Ok((path, font)),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    Err(first_error.unwrap_or_else(|| anyhow!("Font {name} not found")))
+}
+
+// Adds to `found` the font files named `name`, in `dir` and its subdirectories up to `depth`
+// levels down.
+fn find_font_files(dir: &Path, name: &str, depth: u32, found: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut subdirs = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            subdirs.push(path);
+            continue;
+        }
+        let is_font = path.extension().is_some_and(|ext| {
+            ["ttf", "otf", "ttc"]
+                .iter()
+                .any(|e| ext.eq_ignore_ascii_case(e))
+        });
+        let same_name = path
+            .file_stem()
+            .is_some_and(|stem| stem.eq_ignore_ascii_case(name));
+        if file_type.is_file() && is_font && same_name {
+            found.push(path);
+        }
+    }
+    if depth == 0 {
+        return;
+    }
+    for subdir in subdirs {
+        find_font_files(&subdir, name, depth - 1, found);
+    }
+}
+
+// The glyphs that a composite glyph is made of, none for simple glyphs
+fn composite_components(glyph: &[u8]) -> impl Iterator<Item = u16> + '_ {
+    // Flags of each component
+    const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+    const WE_HAVE_A_SCALE: u16 = 0x0008;
+    const MORE_COMPONENTS: u16 = 0x0020;
+    const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+    const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+    let is_composite = read_u16(glyph, 0).is_some_and(|n| (n as i16) < 0);
+    // After the header with the number of contours and the bounding box
+    let mut pos = is_composite.then_some(10);
+    std::iter::from_fn(move || {
+        let p = pos?;
+        let flags = read_u16(glyph, p)?;
+        let gid = read_u16(glyph, p + 2)?;
+        let mut next = p + 4;
+        next += if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+            4
+        } else {
+            2
+        };
+        if flags & WE_HAVE_A_SCALE != 0 {
+            next += 2;
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            next += 4;
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            next += 8;
+        }
+        pos = (flags & MORE_COMPONENTS != 0).then_some(next);
+        Some(gid)
+    })
+}
+
+// Writes a TrueType file with the given tables, sorted by tag
+fn write_sfnt(tables: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+    let num_tables = tables.len() as u16;
+    let entry_selector = num_tables.max(1).ilog2() as u16;
+    let search_range = 16 << entry_selector;
+
+    let mut font = Vec::new();
+    font.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+    for v in [
+        num_tables,
+        search_range,
+        entry_selector,
+        16 * num_tables - search_range,
+    ] {
+        font.extend_from_slice(&v.to_be_bytes());
+    }
+    let mut offset = 12 + 16 * tables.len();
+    for (tag, data) in tables {
+        font.extend_from_slice(*tag);
+        font.extend_from_slice(&checksum(data).to_be_bytes());
+        font.extend_from_slice(&(offset as u32).to_be_bytes());
+        font.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        offset += data.len().next_multiple_of(4);
+    }
+    for (_, data) in tables {
+        font.extend_from_slice(data);
+        font.resize(font.len().next_multiple_of(4), 0);
+    }
+    font
+}
+
+// The sum of the data as big-endian u32, padded with zeros
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+fn deflate(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The font of the desktop interface, it has Latin glyphs only
+    fn karla() -> TrueTypeFont {
+        let data = include_bytes!("Karla-Regular.ttf").to_vec();
+        TrueTypeFont::new(data).unwrap()
+    }
+
+    #[test]
+    fn test_encode_and_subset() {
+        let mut font = karla();
+        let (bytes, width) = font.encode("Añ");
+        assert_eq!(bytes.len(), 4);
+        assert!(width > 0.0);
+        // Not in the font, but it takes its place
+        let (bytes, _) = font.encode("Ж");
+        assert_eq!(bytes, [0, 0]);
+        assert_eq!(font.used.len(), 3);
+
+        let face = font.face();
+        let glyphs: BTreeSet<u16> = font.used.keys().copied().collect();
+        let subset = font.subset(&face, &glyphs).unwrap();
+        assert!(subset.len() < font.data.len());
+        assert_eq!(checksum(&subset), 0xB1B0_AFBA);
+
+        // The subset is still a font, with the same glyphs where they were
+        let sub = Face::parse(&subset, 0).unwrap();
+        assert_eq!(sub.number_of_glyphs(), face.number_of_glyphs());
+        let a = face.glyph_index('A').unwrap();
+        assert_eq!(sub.glyph_hor_advance(a), face.glyph_hor_advance(a));
+        assert_eq!(sub.glyph_bounding_box(a), face.glyph_bounding_box(a));
+        let b = face.glyph_index('B').unwrap();
+        assert!(face.glyph_bounding_box(b).is_some());
+        assert_eq!(sub.glyph_bounding_box(b), None);
+    }
+
+    #[test]
+    fn test_find_font_files() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let find = |name: &str, depth: u32| {
+            let mut found = Vec::new();
+            find_font_files(dir, name, depth, &mut found);
+            found
+        };
+        let found = find("karla-regular", 1);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].file_name().unwrap(), "Karla-Regular.ttf");
+        assert!(find("karla-regular", 0).is_empty());
+        assert!(find("Cargo", 1).is_empty());
+    }
+
+    #[test]
+    fn test_find_in() {
+        // A file that cannot be embedded, as an OpenType font with CFF outlines, next to one
+        // that can
+        let dir = std::env::temp_dir().join(format!("papercraft-fonts-{}", std::process::id()));
+        let sub = dir.join("truetype");
+        std::fs::create_dir_all(&sub).unwrap();
+        std::fs::write(dir.join("Karla-Regular.otf"), b"OTTO").unwrap();
+        let karla = include_bytes!("Karla-Regular.ttf");
+        std::fs::write(sub.join("Karla-Regular.ttf"), karla).unwrap();
+        let dirs = [dir.clone()];
+
+        let (path, _) = find_in(&dirs, "karla-regular").unwrap();
+        assert_eq!(path, sub.join("Karla-Regular.ttf"));
+        std::fs::remove_dir_all(&sub).unwrap();
+        let err = find_in(&dirs, "karla-regular").err().unwrap();
+        assert!(err.to_string().starts_with("Error loading font"));
+        let err = find_in(&dirs, "Karla-Bold").err().unwrap();
+        assert_eq!(err.to_string(), "Font Karla-Bold not found");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_pdf_font() {
+        let mut font = karla();
+        font.encode("Page 1/2");
+        let mut doc = Document::with_version("1.5");
+        let dict = font.pdf_font(&mut doc).unwrap();
+        assert_eq!(dict.get(b"Subtype").unwrap().as_name().unwrap(), b"Type0");
+        assert_eq!(
+            dict.get(b"Encoding").unwrap().as_name().unwrap(),
+            b"Identity-H"
+        );
+        let name = dict.get(b"BaseFont").unwrap().as_name().unwrap();
+        assert_eq!(name[6], b'+');
+        assert!(name[..6].iter().all(u8::is_ascii_uppercase));
+
+        let to_unicode = dict.get(b"ToUnicode").unwrap().as_reference().unwrap();
+        let cmap = doc.get_object(to_unicode).unwrap().as_stream().unwrap();
+        let cmap = String::from_utf8_lossy(&cmap.content);
+        let glyph = font.face().glyph_index('P').unwrap().0;
+        assert!(cmap.contains(&format!("<{glyph:04X}> <0050>")));
+    }
+}
@@ -7,14 +7,12 @@ mod helvetica {
     include!(concat!(env!("OUT_DIR"), "/helvetica_afm.rs"));
 }
 
-#[allow(dead_code)]
 fn find_in_vec_tuple<V>(key: char, data: &[(char, V)]) -> Option<&V> {
     let i = data.binary_search_by_key(&key, |(a, _)| *a).ok()?;
     Some(&data[i].1)
 }
 
 /// Given a text returns the total width and a list of (kerning, glyph-id).
-#[allow(dead_code)]
 pub fn measure_helvetica(text: &str) -> (i32, Vec<(i64, u16)>) {
     let mut width = 0;
     let mut prev = '\u{0}';
@@ -31,3 +29,21 @@ pub fn measure_helvetica(text: &str) -> (i32, Vec<(i64, u16)>) {
     }
     (width, cps)
 }
+
+/// The byte that writes `c` with the WinAnsiEncoding of the standard fonts, if any.
+///
+/// It is Latin-1, but for 0x80 to 0x9F, which are typographic characters instead of controls.
+pub fn win_ansi(c: char) -> Option<u8> {
+    // The unused codes are '\0'
+    const HIGH: [char; 32] = [
+        // 0x80
+        '€', '\0', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\0', 'Ž', '\0',
+        // 0x90
+        '\0', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\0', 'ž', 'Ÿ',
+    ];
+    match c {
+        ' '..='~' | '\u{A0}'..='\u{FF}' => Some(c as u8),
+        '\0' => None,
+        _ => HIGH.iter().position(|&h| h == c).map(|i| 0x80 + i as u8),
+    }
+}
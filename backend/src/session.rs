@@ -0,0 +1,144 @@
+//! Projects open in the server.
+//!
+//! Every project has its own lock, so users working in different projects do not block
+//! each other. The store itself is only locked to add, find or remove projects.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::action::History;
+use crate::live::Update;
+use crate::paper::Papercraft;
+
+// Updates kept for a client that is slow to read them, before it has to start over
+const UPDATES_CAPACITY: usize = 64;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ProjectId(u64);
+
+impl std::fmt::Display for ProjectId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+pub struct Project {
+    pub name: String,
+    /// `None` until a model is uploaded
+    pub papercraft: Option<Papercraft>,
+    pub history: History,
+    /// Incremented with every change, so that clients know if they are up to date
+    pub revision: u64,
+    /// The changes, for the clients connected to the project
+    pub updates: broadcast::Sender<Arc<Update>>,
+}
+
+impl Project {
+    pub fn new(name: String, papercraft: Option<Papercraft>) -> Project {
+        let (updates, _) = broadcast::channel(UPDATES_CAPACITY);
+        Project {
+            name,
+            papercraft,
+            history: History::default(),
+            revision: 0,
+            updates,
+        }
+    }
+    /// Replaces the model, the undo history of the old one is no longer valid
+    pub fn set_papercraft(&mut self, papercraft: Papercraft) {
+        self.papercraft = Some(papercraft);
+        self.history = History::default();
+        self.revision += 1;
+        // No one listening is not an error
+        let _ = self.updates.send(Arc::new(Update::Reload {
+            revision: self.revision,
+        }));
+    }
+}
+
+#[derive(Serialize)]
+pub struct ProjectInfo {
+    pub id: ProjectId,
+    pub name: String,
+    pub has_model: bool,
+    pub can_undo: bool,
+    pub can_redo: bool,
+}
+
+pub type ProjectRef = Arc<Mutex<Project>>;
+
+#[derive(Default)]
+pub struct ProjectStore {
+    next_id: AtomicU64,
+    projects: RwLock<BTreeMap<ProjectId, ProjectRef>>,
+}
+
+impl ProjectStore {
+    pub fn new() -> ProjectStore {
+        ProjectStore::default()
+    }
+
+    pub fn create(&self, project: Project) -> ProjectId {
+        let id = ProjectId(self.next_id.fetch_add(1, Ordering::Relaxed) + 1);
+        self.projects
+            .write()
+            .unwrap()
+            .insert(id, Arc::new(Mutex::new(project)));
+        id
+    }
+    pub fn get(&self, id: ProjectId) -> Option<ProjectRef> {
+        self.projects.read().unwrap().get(&id).cloned()
+    }
+    pub fn remove(&self, id: ProjectId) -> bool {
+        self.projects.write().unwrap().remove(&id).is_some()
+    }
+    /// Every project, sorted by id, that is in the order they were created.
+    pub fn list(&self) -> Vec<ProjectInfo> {
+        // Clone the references first, so that the store is not locked while waiting for each
+        // project. Listing still waits for a busy one, such as one being unfolded, to read its
+        // name. The map is sorted by id already.
+        let projects: Vec<_> = self
+            .projects
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(id, p)| (*id, p.clone()))
+            .collect();
+        projects
+            .into_iter()
+            .map(|(id, p)| {
+                let p = p.lock().unwrap();
+                ProjectInfo {
+                    id,
+                    name: p.name.clone(),
+                    has_model: p.papercraft.is_some(),
+                    can_undo: p.history.can_undo(),
+                    can_redo: p.history.can_redo(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_in_creation_order() {
+        let store = ProjectStore::new();
+        let ids: Vec<_> = (0..12)
+            .map(|i| store.create(Project::new(format!("p{i}"), None)))
+            .collect();
+        assert!(store.remove(ids[3]));
+        let listed: Vec<_> = store.list().into_iter().map(|p| p.id).collect();
+        let expected: Vec<_> = ids.iter().copied().filter(|&id| id != ids[3]).collect();
+        assert_eq!(listed, expected);
+        assert!(listed.is_sorted());
+    }
+}
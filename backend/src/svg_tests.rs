@@ -556,4 +556,280 @@ mod tests {
             fills.len()
         );
     }
+
+    #[test]
+    fn test_renderable_flaps_match_svg() {
+        use crate::paper::FlapStyle;
+
+        let path = test_data_path("dice.pdo");
+        let (mut papercraft, _) =
+            crate::paper::import::import_model_file(&path).expect("Failed to load dice.pdo");
+
+        let count = |papercraft: &crate::paper::Papercraft| {
+            let renderable = papercraft.renderable();
+            let flaps: Vec<_> = renderable.islands.iter().flat_map(|i| &i.flaps).collect();
+            assert!(flaps.iter().all(|f| matches!(f.vertices.len(), 3 | 4)));
+            let svg = generate_svg_multipage(papercraft, false).unwrap();
+            assert_eq!(svg.matches(r#"id="flap_"#).count(), flaps.len());
+            flaps.len()
+        };
+
+        let single = count(&papercraft);
+        assert!(single > 0);
+
+        let mut options = papercraft.options().clone();
+        options.flap_double = true;
+        papercraft.set_options(options.clone(), false);
+        assert!(count(&papercraft) > single);
+
+        options.flap_style = FlapStyle::None;
+        papercraft.set_options(options, false);
+        assert_eq!(count(&papercraft), 0);
+    }
+
+    #[test]
+    fn test_dice_pdo_dxf_export() {
+        use crate::vector_export::generate_dxf;
+
+        let path = test_data_path("dice.pdo");
+        let (papercraft, _) =
+            crate::paper::import::import_model_file(&path).expect("Failed to load dice.pdo");
+
+        let dxf = generate_dxf(&papercraft, None).expect("Failed to generate DXF");
+        let lines: Vec<&str> = dxf.lines().collect();
+        // Pairs of group code and value
+        assert_eq!(lines.len() % 2, 0);
+        let pairs: Vec<(u16, &str)> = lines
+            .chunks(2)
+            .map(|p| (p[0].trim().parse().expect("Bad group code"), p[1]))
+            .collect();
+        assert_eq!(pairs.last(), Some(&(0, "EOF")));
+        // R12, without the variables of later versions
+        assert!(pairs.contains(&(1, "AC1009")));
+        assert!(!dxf.contains("$INSUNITS"));
+        for layer in ["CUT", "MOUNTAIN", "VALLEY", "FLAPS"] {
+            assert!(pairs.contains(&(2, layer)), "Missing layer {layer}");
+        }
+
+        // One closed cut per island, and the flaps of the SVG
+        let entities_on = |layer: &str| {
+            pairs
+                .windows(2)
+                .filter(|w| w[0] == (0, "POLYLINE") && w[1] == (8, layer))
+                .count()
+        };
+        assert_eq!(entities_on("CUT"), papercraft.num_islands());
+        let svg = generate_svg_multipage(&papercraft, false).unwrap();
+        assert_eq!(entities_on("FLAPS"), svg.matches(r#"id="flap_"#).count());
+
+        // A single page has the same entities as that page of the SVG
+        let page = generate_dxf(&papercraft, Some(0)).unwrap();
+        let svg_page = crate::vector_export::generate_svg(&papercraft, 0, false).unwrap();
+        assert_eq!(
+            page.matches("\nPOLYLINE\n  8\nCUT\n").count(),
+            svg_page.matches(r#"id="cut_"#).count()
+        );
+    }
+
+    #[test]
+    fn test_print_and_cut_export() {
+        use crate::vector_export::{
+            generate_dxf_cut, generate_pdf, generate_svg, generate_svg_cut,
+        };
+
+        let path = test_data_path("dice.pdo");
+        let (mut papercraft, _) =
+            crate::paper::import::import_model_file(&path).expect("Failed to load dice.pdo");
+        let mut options = papercraft.options().clone();
+        options.registration_marks = true;
+        papercraft.set_options(options, false);
+
+        let svg = generate_svg(&papercraft, 0, false).unwrap();
+        assert!(svg.contains(r#"id="Registration""#));
+        assert_eq!(svg.matches(r#"id="mark_"#).count(), 5);
+
+        // The same cut lines, going around the flaps, and nothing else
+        let cut = generate_svg_cut(&papercraft, 0).unwrap();
+        let re_cut = Regex::new(r#"<path id="cut_\d+" [^>]*d="M ([^"]+)Z""#).unwrap();
+        let contours = |svg: &str| -> Vec<String> {
+            let contours = re_cut.captures_iter(svg);
+            contours.map(|c| c[1].to_string()).collect()
+        };
+        assert!(!contours(&cut).is_empty());
+        assert_eq!(contours(&cut), contours(&svg));
+        assert!(!cut.contains("flap_") && !cut.contains("mark_") && !cut.contains("<text"));
+
+        let dxf = generate_dxf_cut(&papercraft, Some(0)).unwrap();
+        assert!(dxf.ends_with("EOF\n"));
+        assert!(!dxf.contains("MOUNTAIN") && !dxf.contains("FLAPS"));
+        assert_eq!(
+            dxf.matches("\nPOLYLINE\n  8\nCUT\n").count(),
+            cut.matches(r#"id="cut_"#).count()
+        );
+
+        let pdf = generate_pdf(&papercraft, false).expect("Failed to generate PDF");
+        assert!(pdf.starts_with(b"%PDF"));
+    }
+
+    // All the "x,y" points in the attributes matched by `re`
+    fn svg_points(svg: &str, re: &Regex) -> Vec<Vector2> {
+        re.captures_iter(svg)
+            .flat_map(|c| {
+                c[1].split_whitespace()
+                    .filter_map(|p| p.split_once(','))
+                    .map(|(x, y)| Vector2::new(x.parse().unwrap(), y.parse().unwrap()))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    // The bounding box of all the "x,y" points in the attributes matched by `re`
+    fn points_bbox(svg: &str, re: &Regex) -> (Vector2, Vector2) {
+        crate::util_3d::bounding_box_2d(svg_points(svg, re))
+    }
+
+    #[test]
+    fn test_bleed_and_cut_offset_export() {
+        use crate::vector_export::{generate_pdf, generate_svg};
+
+        let path = test_data_path("triangle.obj");
+        let (mut papercraft, _) =
+            crate::paper::import::import_model_file(&path).expect("Failed to load triangle.obj");
+        let re_face = Regex::new(r#"<polygon id="face_\d+" [^>]*points="([^"]+)""#).unwrap();
+        let re_cut = Regex::new(r#"<path id="cut_\d+" [^>]*d="M ([^"]+)Z""#).unwrap();
+
+        let svg = generate_svg(&papercraft, 0, false).unwrap();
+        let faces = points_bbox(&svg, &re_face);
+        let cuts = points_bbox(&svg, &re_cut);
+
+        let mut options = papercraft.options().clone();
+        options.bleed = 1.0;
+        options.cut_offset = 0.5;
+        papercraft.set_options(options, false);
+        let svg = generate_svg(&papercraft, 0, false).unwrap();
+        let bled = points_bbox(&svg, &re_face);
+        let offset = points_bbox(&svg, &re_cut);
+        // Every side of the lone triangle is a cut edge
+        let grown = |(a0, b0): (Vector2, Vector2), (a1, b1): (Vector2, Vector2), d: f32| {
+            let (da, db) = (a0 - a1, b1 - b0);
+            [da.x, da.y, db.x, db.y]
+                .iter()
+                .all(|&g| g >= d * 0.99 && g <= d * 4.01)
+        };
+        assert!(grown(faces, bled, 1.0), "{faces:?} {bled:?}");
+        assert!(grown(cuts, offset, 0.5), "{cuts:?} {offset:?}");
+
+        let pdf = generate_pdf(&papercraft, false).expect("Failed to generate PDF");
+        assert!(pdf.starts_with(b"%PDF"));
+    }
+
+    #[test]
+    fn test_cut_offset_flaps() {
+        use crate::vector_export::{generate_svg, generate_svg_cut};
+        use cgmath::{InnerSpace, MetricSpace};
+
+        let path = test_data_path("dice.pdo");
+        let (mut papercraft, _) =
+            crate::paper::import::import_model_file(&path).expect("Failed to load dice.pdo");
+        let mut options = papercraft.options().clone();
+        options.cut_offset = 0.5;
+        papercraft.set_options(options.clone(), false);
+
+        let re_cut = Regex::new(r#"<path id="cut_\d+" [^>]*d="M ([^"]+)Z""#).unwrap();
+        let cut = generate_svg_cut(&papercraft, 0).unwrap();
+        let points = svg_points(&cut, &re_cut);
+        let svg = generate_svg(&papercraft, 0, false).unwrap();
+        assert_eq!(points, svg_points(&svg, &re_cut));
+
+        // The tips of the flaps move outwards, those of four-sided flaps by exactly the offset
+        let page_offset = options.page_position(0);
+        let mut flaps = 0;
+        for (_, island) in papercraft.islands() {
+            for (_, _, flap) in papercraft.island_flaps(island) {
+                let flap: Vec<_> = flap.iter().map(|&p| p - page_offset).collect();
+                let base = (flap[0] + flap[flap.len() - 1]) / 2.0;
+                let tips = &flap[1..flap.len() - 1];
+                let normal = match *tips {
+                    [a, b] => {
+                        let normal = Vector2::new(b.y - a.y, a.x - b.x).normalize();
+                        Some(normal * normal.dot(a - base).signum())
+                    }
+                    _ => None,
+                };
+                for &tip in tips {
+                    let moved = points.iter().any(|&p| {
+                        let d = p.distance(tip);
+                        (0.49..=2.01).contains(&d)
+                            && (p - tip).dot(tip - base) > 0.0
+                            && normal.is_none_or(|n| ((p - tip).dot(n) - 0.5).abs() < 0.01)
+                    });
+                    assert!(moved, "Flap tip {tip:?} not moved");
+                }
+                flaps += 1;
+            }
+        }
+        assert!(flaps > 0);
+    }
+
+    #[test]
+    fn test_pdf_layers() {
+        use crate::vector_export::generate_pdf;
+
+        let path = test_data_path("dice.pdo");
+        let (papercraft, _) =
+            crate::paper::import::import_model_file(&path).expect("Failed to load dice.pdo");
+        let pdf = generate_pdf(&papercraft, false).expect("Failed to generate PDF");
+        assert!(pdf.starts_with(b"%PDF-1.5"));
+        let pdf = String::from_utf8_lossy(&pdf);
+
+        assert!(pdf.contains("/OCProperties"));
+        let re_ocg = Regex::new(r"/Type\s*/OCG\b").unwrap();
+        assert_eq!(re_ocg.find_iter(&pdf).count(), 7);
+        for name in ["Textures", "Flaps", "Mountain", "Cut", "Furniture"] {
+            let re = Regex::new(&format!(r"/OC\s*/{name}\s+BDC")).unwrap();
+            assert!(re.is_match(&pdf), "Missing layer {name}");
+        }
+        // Every marked layer is closed
+        let re_bdc = Regex::new(r"\sBDC\s").unwrap();
+        let re_emc = Regex::new(r"\sEMC\s").unwrap();
+        assert_eq!(
+            re_bdc.find_iter(&pdf).count(),
+            re_emc.find_iter(&pdf).count()
+        );
+    }
+
+    #[test]
+    fn test_win_ansi() {
+        use crate::pdf_metrics::win_ansi;
+
+        assert_eq!(win_ansi('A'), Some(b'A'));
+        assert_eq!(win_ansi('ñ'), Some(0xF1));
+        // Not the C1 controls of Latin-1
+        assert_eq!(win_ansi('€'), Some(0x80));
+        assert_eq!(win_ansi('“'), Some(0x93));
+        assert_eq!(win_ansi('Ÿ'), Some(0x9F));
+        assert_eq!(win_ansi('\u{93}'), None);
+        assert_eq!(win_ansi('Ж'), None);
+        assert_eq!(win_ansi('\n'), None);
+    }
+
+    #[test]
+    fn test_pdf_text_font() {
+        use crate::vector_export::generate_pdf;
+
+        let path = test_data_path("dice.pdo");
+        let (mut papercraft, _) =
+            crate::paper::import::import_model_file(&path).expect("Failed to load dice.pdo");
+        let pdf = generate_pdf(&papercraft, false).expect("Failed to generate PDF");
+        let pdf = String::from_utf8_lossy(&pdf);
+        assert!(pdf.contains("/Helvetica"));
+        assert!(!pdf.contains("/Type0"));
+
+        // A missing font is an error, not a silent fallback to Helvetica
+        let mut options = papercraft.options().clone();
+        options.pdf_font = Some(String::from("No-Such-Font-Anywhere"));
+        papercraft.set_options(options, false);
+        let err = generate_pdf(&papercraft, false).err().unwrap();
+        assert!(err.to_string().contains("No-Such-Font-Anywhere"));
+    }
 }
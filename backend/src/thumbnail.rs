@@ -0,0 +1,189 @@
+//! Software renderer for the `.craft` thumbnail.
+//!
+//! The desktop application draws the thumbnail with OpenGL, but the web server has no GL
+//! context, so here the 3D model is rasterized on the CPU with a plain z-buffer.
+
+use cgmath::{Deg, InnerSpace, MetricSpace};
+use image::{Rgba, RgbaImage};
+
+use crate::paper::Papercraft;
+use crate::util_3d::{self, Matrix3, Vector2, Vector3};
+
+pub const THUMBNAIL_SIZE: u32 = 256;
+
+// Render at a bigger size and then downscale, that is a cheap antialiasing
+const SUPERSAMPLE: u32 = 2;
+
+pub fn render_thumbnail(papercraft: &Papercraft) -> RgbaImage {
+    let size = THUMBNAIL_SIZE * SUPERSAMPLE;
+    let mut img = RgbaImage::new(size, size);
+    let mut zbuf = vec![f32::NEG_INFINITY; (size * size) as usize];
+
+    let model = papercraft.model();
+    let options = papercraft.options();
+
+    let (v_min, v_max) = util_3d::bounding_box_3d(model.vertices().map(|(_, v)| v.pos()));
+    let center = (v_min + v_max) / 2.0;
+    let radius = model
+        .vertices()
+        .map(|(_, v)| v.pos().distance(center))
+        .fold(0.0f32, f32::max);
+    if radius <= 0.0 {
+        return image::imageops::thumbnail(&img, THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+    }
+
+    // A fixed 3/4 view, similar to the default camera of the 3D scene
+    let rot = Matrix3::from_angle_x(Deg(25.0)) * Matrix3::from_angle_y(Deg(-35.0));
+    let light = Vector3::new(0.3, 0.5, 1.0).normalize();
+
+    let half = size as f32 / 2.0;
+    let factor = half * 0.95 / radius;
+    let to_screen = |p: Vector3| -> Vector3 {
+        let p = rot * (p - center);
+        // Y is up in the model and down in the image
+        Vector3::new(half + p.x * factor, half - p.y * factor, p.z)
+    };
+
+    let textures: Vec<Option<RgbaImage>> = model
+        .textures()
+        .map(|t| {
+            if options.texture {
+                t.pixbuf().map(|p| p.to_rgba8())
+            } else {
+                None
+            }
+        })
+        .collect();
+    let paper_color = &options.paper_color.0;
+    let paper_color = [paper_color.r, paper_color.g, paper_color.b];
+
+    for (_, face) in model.faces() {
+        let vs = face.index_vertices().map(|i_v| &model[i_v]);
+        let ps = vs.map(|v| to_screen(v.pos()));
+        let uvs = vs.map(|v| v.uv());
+
+        // Double sided lighting, papercraft models are not always closed
+        let normal = rot * (vs[1].pos() - vs[0].pos()).cross(vs[2].pos() - vs[0].pos());
+        if normal.magnitude2() == 0.0 {
+            continue;
+        }
+        let shade = 0.35 + 0.65 * normal.normalize().dot(light).abs();
+
+        let texture = textures
+            .get(usize::from(face.material()))
+            .and_then(|t| t.as_ref());
+
+        rasterize_triangle(ps, size, |x, y, bary| {
+            let z = ps[0].z * bary[0] + ps[1].z * bary[1] + ps[2].z * bary[2];
+            let zi = (y * size + x) as usize;
+            if z <= zbuf[zi] {
+                return;
+            }
+            zbuf[zi] = z;
+
+            let rgb = match texture {
+                Some(tex) => {
+                    let uv = uvs[0] * bary[0] + uvs[1] * bary[1] + uvs[2] * bary[2];
+                    sample_texture(tex, uv)
+                }
+                None => paper_color,
+            };
+            let px = rgb.map(|c| (c * shade * 255.0).round().clamp(0.0, 255.0) as u8);
+            img.put_pixel(x, y, Rgba([px[0], px[1], px[2], 255]));
+        });
+    }
+
+    image::imageops::thumbnail(&img, THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+}
+
+// Calls `plot` for every pixel whose center is inside the triangle, with its barycentric coordinates
+fn rasterize_triangle(ps: [Vector3; 3], size: u32, mut plot: impl FnMut(u32, u32, [f32; 3])) {
+    let [a, b, c] = ps.map(|p| Vector2::new(p.x, p.y));
+    let edge = |p0: Vector2, p1: Vector2, p: Vector2| {
+        (p1.x - p0.x) * (p.y - p0.y) - (p1.y - p0.y) * (p.x - p0.x)
+    };
+    let area = edge(a, b, c);
+    if area.abs() < f32::EPSILON {
+        return;
+    }
+
+    let (bb_min, bb_max) = util_3d::bounding_box_2d([a, b, c]);
+    let max = (size - 1) as f32;
+    let x0 = bb_min.x.floor().clamp(0.0, max) as u32;
+    let x1 = bb_max.x.ceil().clamp(0.0, max) as u32;
+    let y0 = bb_min.y.floor().clamp(0.0, max) as u32;
+    let y1 = bb_max.y.ceil().clamp(0.0, max) as u32;
+
+    for y in y0..=y1 {
+        for x in x0..=x1 {
+            let p = Vector2::new(x as f32 + 0.5, y as f32 + 0.5);
+            let w0 = edge(b, c, p) / area;
+            let w1 = edge(c, a, p) / area;
+            let w2 = edge(a, b, p) / area;
+            if w0 >= 0.0 && w1 >= 0.0 && w2 >= 0.0 {
+                plot(x, y, [w0, w1, w2]);
+            }
+        }
+    }
+}
+
+fn sample_texture(tex: &RgbaImage, uv: Vector2) -> [f32; 3] {
+    let (w, h) = tex.dimensions();
+    if w == 0 || h == 0 {
+        return [1.0, 1.0, 1.0];
+    }
+    // Textures repeat, and V goes upwards, the same as in the vector export
+    let x = ((uv.x * w as f32).floor() as i64).rem_euclid(w as i64) as u32;
+    let y = (((1.0 - uv.y) * h as f32).floor() as i64).rem_euclid(h as i64) as u32;
+    let p = tex.get_pixel(x, y).0;
+    [p[0], p[1], p[2]].map(|c| c as f32 / 255.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn test_data_path(filename: &str) -> PathBuf {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("examples");
+        d.push(filename);
+        d
+    }
+
+    #[test]
+    fn test_thumbnail_is_not_empty() {
+        let (papercraft, _) = crate::paper::import::import_model_file(&test_data_path("dice.pdo"))
+            .expect("Failed to load dice.pdo");
+        let thumb = render_thumbnail(&papercraft);
+        assert_eq!(thumb.dimensions(), (THUMBNAIL_SIZE, THUMBNAIL_SIZE));
+        assert!(thumb.pixels().any(|p| p.0[3] != 0), "Thumbnail is fully transparent");
+    }
+
+    #[test]
+    fn test_craft_round_trip() {
+        let (papercraft, _) = crate::paper::import::import_model_file(&test_data_path("dice.pdo"))
+            .expect("Failed to load dice.pdo");
+        let thumb = render_thumbnail(&papercraft);
+
+        let mut buffer = std::io::Cursor::new(Vec::new());
+        papercraft.save(&mut buffer, Some(thumb)).expect("Failed to save");
+        buffer.set_position(0);
+
+        let mut zip = zip::ZipArchive::new(&mut buffer).unwrap();
+        assert!(zip.by_name("thumb.png").is_ok());
+        buffer.set_position(0);
+
+        let loaded = crate::paper::Papercraft::load(buffer).expect("Failed to load");
+        assert_eq!(loaded.num_islands(), papercraft.num_islands());
+        assert_eq!(loaded.faces().count(), papercraft.faces().count());
+        assert_eq!(
+            loaded.edges().collect::<Vec<_>>(),
+            papercraft.edges().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            loaded.model().has_textures(),
+            papercraft.model().has_textures()
+        );
+    }
+}
@@ -0,0 +1,326 @@
+//! Model files uploaded through the HTTP API.
+//!
+//! A model may come with other files: an OBJ with its MTL and textures, a glTF with its
+//! binary buffers and images. They are uploaded together, as several files or as a single
+//! `.zip`, and written into a directory private to that upload, so that the importers find
+//! them next to the model.
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Context, Result, anyhow, bail};
+
+use crate::paper::import::{ModelFormat, UnsupportedFormat, detect_format};
+
+// Bytes read from every file to detect its format
+const HEAD_SIZE: u64 = 8192;
+// Limit of the size of all the files unpacked from the zips of an upload, far bigger than any
+// real model
+const MAX_UNPACKED_SIZE: u64 = 256 * 1024 * 1024;
+
+/// A temporary directory with the files of one upload, removed when dropped.
+pub struct UploadDir {
+    path: PathBuf,
+    // Bytes unpacked from zips so far, an upload may have several of them
+    unpacked: u64,
+}
+
+impl UploadDir {
+    pub fn new() -> Result<UploadDir> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        let tmp = std::env::temp_dir();
+        let mut builder = std::fs::DirBuilder::new();
+        // The temporary directory is shared with other users
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        loop {
+            let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+            let path = tmp.join(format!("papercraft-upload-{}-{}", std::process::id(), id));
+            match builder.create(&path) {
+                Ok(()) => return Ok(UploadDir { path, unpacked: 0 }),
+                // Left behind by a previous run
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Error creating {}", path.display()));
+                }
+            }
+        }
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// Writes a file of the upload, `name` is the name given by the client.
+    ///
+    /// A zip file is unpacked instead, unless it is a `.craft` project.
+    pub fn add_file(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        let rel = safe_relative_path(name).ok_or_else(|| anyhow!("Invalid file name {name}"))?;
+        if data.starts_with(b"PK\x03\x04") {
+            let zip = zip::ZipArchive::new(std::io::Cursor::new(data))
+                .with_context(|| format!("Error reading zip file {name}"))?;
+            if zip.index_for_name("model.json").is_none() {
+                return self
+                    .add_zip(zip)
+                    .with_context(|| format!("Error unpacking {name}"));
+            }
+        }
+        self.write(&rel, data)
+    }
+    fn add_zip(&mut self, mut zip: zip::ZipArchive<std::io::Cursor<&[u8]>>) -> Result<()> {
+        // Do not trust the sizes in the zip directory, a small zip may unpack to a huge size
+        for i in 0..zip.len() {
+            let entry = zip.by_index(i)?;
+            if entry.is_dir() {
+                continue;
+            }
+            let name = entry.name().to_owned();
+            let Some(rel) = safe_relative_path(&name) else {
+                log::warn!("Skipping {name} from the zip: invalid file name");
+                continue;
+            };
+            // Straight into the file, a single byte over the limit is enough to fail
+            let mut file = self.create(&rel)?;
+            let mut entry = std::io::Read::take(entry, MAX_UNPACKED_SIZE + 1 - self.unpacked);
+            self.unpacked += std::io::copy(&mut entry, &mut file)
+                .with_context(|| format!("Error writing {}", rel.display()))?;
+            if self.unpacked > MAX_UNPACKED_SIZE {
+                bail!("The zip files are too big");
+            }
+        }
+        Ok(())
+    }
+    fn create(&self, rel: &Path) -> Result<std::fs::File> {
+        let path = self.path.join(rel);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::File::create(&path).with_context(|| format!("Error creating {}", rel.display()))
+    }
+    fn write(&self, rel: &Path, data: &[u8]) -> Result<()> {
+        std::io::Write::write_all(&mut self.create(rel)?, data)
+            .with_context(|| format!("Error writing {}", rel.display()))
+    }
+    /// Finds the model among the uploaded files, by their contents.
+    ///
+    /// If there are several kinds of models, such as a `.craft` and the OBJ it was made from,
+    /// the native formats are preferred.
+    pub fn model(&self) -> Result<PathBuf> {
+        let mut models = Vec::new();
+        for file in list_files(&self.path)? {
+            if let Some(format) = sniff_file(&file)? {
+                models.push((model_rank(format), file));
+            }
+        }
+        models.sort();
+        match models.as_slice() {
+            [] => Err(anyhow!(UnsupportedFormat)).context("The upload has no model file"),
+            [(rank_a, a), (rank_b, b), ..] if rank_a == rank_b => bail!(
+                "The upload has more than one model: {} and {}",
+                self.display(a),
+                self.display(b)
+            ),
+            [(_, model), ..] => Ok(model.clone()),
+        }
+    }
+    /// The path of an uploaded file as the client named it
+    pub fn display(&self, path: &Path) -> String {
+        path.strip_prefix(&self.path)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    }
+}
+
+impl Drop for UploadDir {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.path) {
+            log::warn!("Error removing {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+// Keeps only the plain components of the path, so that it cannot escape the upload directory.
+// Browsers uploading a whole folder send names such as `model/textures/a.png`.
+fn safe_relative_path(name: &str) -> Option<PathBuf> {
+    let name = name.replace('\\', "/");
+    let mut rel = PathBuf::new();
+    for c in Path::new(&name).components() {
+        match c {
+            Component::Normal(c) => rel.push(c),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    (!rel.as_os_str().is_empty()).then_some(rel)
+}
+
+fn sniff_file(path: &Path) -> Result<Option<ModelFormat>> {
+    let err = || format!("Error reading {}", path.display());
+    let f = std::fs::File::open(path).with_context(err)?;
+    let len = f.metadata().with_context(err)?.len();
+    let mut head = Vec::new();
+    std::io::Read::read_to_end(&mut std::io::Read::take(f, HEAD_SIZE), &mut head)
+        .with_context(err)?;
+    Ok(detect_format(&head, len))
+}
+
+fn model_rank(format: ModelFormat) -> u8 {
+    match format {
+        ModelFormat::Craft => 0,
+        ModelFormat::Pepakura => 1,
+        ModelFormat::Glb | ModelFormat::Gltf => 2,
+        ModelFormat::WaveObj => 3,
+        ModelFormat::Stl { .. } => 4,
+    }
+}
+
+// All the files in `dir` and its subdirectories, sorted
+fn list_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_owned()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                pending.push(entry.path());
+            } else {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn zip_of(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (name, data) in files {
+            zip.start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(data.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_safe_relative_path() {
+        assert_eq!(safe_relative_path("a.obj"), Some(PathBuf::from("a.obj")));
+        assert_eq!(
+            safe_relative_path("./tex\\a.png"),
+            Some(PathBuf::from("tex/a.png"))
+        );
+        assert_eq!(safe_relative_path("../a.obj"), None);
+        assert_eq!(safe_relative_path("/etc/passwd"), None);
+        assert_eq!(safe_relative_path(""), None);
+    }
+
+    #[test]
+    fn test_upload_zip() {
+        let mut upload = UploadDir::new().unwrap();
+        let zip = zip_of(&[
+            ("box/box.obj", "mtllib box.mtl\n"),
+            ("box/box.mtl", ""),
+            ("box/textures/wood.png", ""),
+            ("../evil.obj", ""),
+        ]);
+        upload.add_file("box.zip", &zip).unwrap();
+        let model = upload.model().unwrap();
+        assert_eq!(upload.display(&model), "box/box.obj");
+        assert!(upload.path().join("box/textures/wood.png").exists());
+        assert!(!upload.path().join("../evil.obj").exists());
+
+        let path = upload.path().to_owned();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+        drop(upload);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_upload_zips_size() {
+        let mut upload = UploadDir::new().unwrap();
+        let part = zip_of(&[("a.obj", "0123456789")]);
+        upload.add_file("a.zip", &part).unwrap();
+        assert_eq!(upload.unpacked, 10);
+
+        // The limit is for all the zips of the upload together
+        upload.unpacked = MAX_UNPACKED_SIZE - 15;
+        let part = zip_of(&[("b.obj", "0123456789")]);
+        upload.add_file("b.zip", &part).unwrap();
+        let part = zip_of(&[("c.obj", "0123456789")]);
+        assert!(upload.add_file("c.zip", &part).is_err());
+    }
+
+    #[test]
+    fn test_upload_missing_matlib() {
+        let mut upload = UploadDir::new().unwrap();
+        upload
+            .add_file(
+                "box.obj",
+                b"mtllib box.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n",
+            )
+            .unwrap();
+        let model = upload.model().unwrap();
+        let imported = crate::paper::import::import_model_file_with_progress(
+            &model,
+            &cancel_rw::CancellationToken::new(),
+            &|_| {},
+        )
+        .unwrap();
+        assert_eq!(imported.missing_files, ["box.mtl"]);
+
+        upload.add_file("box.mtl", b"newmtl Box\n").unwrap();
+        let imported = crate::paper::import::import_model_file_with_progress(
+            &model,
+            &cancel_rw::CancellationToken::new(),
+            &|_| {},
+        )
+        .unwrap();
+        assert!(imported.missing_files.is_empty());
+    }
+
+    #[test]
+    fn test_upload_craft_is_not_unpacked() {
+        let mut upload = UploadDir::new().unwrap();
+        let craft = std::fs::read(
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/die.craft"),
+        )
+        .unwrap();
+        upload.add_file("die", &craft).unwrap();
+        assert_eq!(upload.display(&upload.model().unwrap()), "die");
+        assert!(!upload.path().join("model.json").exists());
+    }
+
+    #[test]
+    fn test_upload_model_choice() {
+        let mut upload = UploadDir::new().unwrap();
+        upload
+            .add_file("scene.gltf", br#"{"asset": {"version": "2.0"}}"#)
+            .unwrap();
+        upload.add_file("scene.bin", b"\0\0\0\0").unwrap();
+        upload.add_file("preview.stl", b"solid preview\n").unwrap();
+        assert_eq!(upload.display(&upload.model().unwrap()), "scene.gltf");
+
+        upload.add_file("other.glb", b"glTF\x02\0\0\0").unwrap();
+        assert!(upload.model().is_err());
+
+        let mut upload = UploadDir::new().unwrap();
+        upload.add_file("readme.txt", b"Not a model\n").unwrap();
+        upload.add_file("fake.obj", b"\x89PNG\r\n\x1a\n\0").unwrap();
+        assert!(upload.model().is_err());
+
+        // The extension does not matter
+        upload
+            .add_file("model", b"v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n")
+            .unwrap();
+        assert_eq!(upload.display(&upload.model().unwrap()), "model");
+    }
+}
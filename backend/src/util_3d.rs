@@ -141,6 +141,33 @@ pub fn point_in_triangle(p: Vector2, tri: [Vector2; 3]) -> bool {
     }
 }
 
+// Checks if the interiors of two triangles overlap by more than `margin`.
+// Triangles that just touch, such as neighbor faces, do not overlap.
+pub fn triangles_overlap(a: [Vector2; 3], b: [Vector2; 3], margin: f32) -> bool {
+    // Separating axis theorem: two convex polygons do not overlap if their projections over
+    // the normal of any of their sides do not overlap.
+    fn project(tri: &[Vector2; 3], n: Vector2) -> (f32, f32) {
+        let ds = tri.map(|p| p.dot(n));
+        (ds[0].min(ds[1]).min(ds[2]), ds[0].max(ds[1]).max(ds[2]))
+    }
+    for tri in [&a, &b] {
+        for i in 0..3 {
+            let side = tri[(i + 1) % 3] - tri[i];
+            let len = side.magnitude();
+            if len == 0.0 {
+                continue;
+            }
+            let n = Vector2::new(-side.y, side.x) / len;
+            let (min_a, max_a) = project(&a, n);
+            let (min_b, max_b) = project(&b, n);
+            if max_a <= min_b + margin || max_b <= min_a + margin {
+                return false;
+            }
+        }
+    }
+    true
+}
+
 pub fn bounding_box_3d(vs: impl IntoIterator<Item = Vector3>) -> (Vector3, Vector3) {
     let mut vs = vs.into_iter();
     let (mut a, mut b) = match vs.next() {
@@ -316,6 +343,60 @@ pub fn line_line_intersection(
     ((line_1.0 + s * s1), s, t)
 }
 
+// Corners of an offset polygon further than this, relative to the offset, are beveled
+const MITER_LIMIT: f32 = 4.0;
+
+// Moves every side of the closed polygon `vs` outwards by `ds[i]`, or inwards if it is negative,
+// where side `i` goes from `vs[i]` to the next vertex. The corners are mitered, and beveled if
+// they are too sharp. It works with either orientation.
+pub fn offset_polygon(vs: &[Vector2], ds: &[f32]) -> Vec<Vector2> {
+    // Zero-length sides have no normal, drop them
+    let sides: Vec<(Vector2, f32)> = (0..vs.len())
+        .filter(|&i| vs[i].distance2(vs[(i + 1) % vs.len()]) > 1e-12)
+        .map(|i| (vs[i], ds[i]))
+        .collect();
+    let n = sides.len();
+    if n < 3 {
+        return vs.to_vec();
+    }
+    let area2: f32 = (0..n)
+        .map(|i| {
+            let (a, b) = (sides[i].0, sides[(i + 1) % n].0);
+            a.x * b.y - b.x * a.y
+        })
+        .sum();
+    let sign = if area2 < 0.0 { -1.0 } else { 1.0 };
+    let normal = |i: usize| {
+        let side = (sides[(i + 1) % n].0 - sides[i].0).normalize();
+        Vector2::new(side.y, -side.x) * sign
+    };
+
+    let mut res = Vec::with_capacity(n);
+    for i in 0..n {
+        let prev = (i + n - 1) % n;
+        let (p, d1, d2) = (sides[i].0, sides[prev].1, sides[i].1);
+        if d1 == 0.0 && d2 == 0.0 {
+            res.push(p);
+            continue;
+        }
+        let (n1, n2) = (normal(prev), normal(i));
+        let line_1 = (sides[prev].0 + n1 * d1, p + n1 * d1);
+        let line_2 = (p + n2 * d2, sides[(i + 1) % n].0 + n2 * d2);
+        let (miter, s, _) = line_line_intersection(line_1, line_2);
+        let limit = MITER_LIMIT * d1.abs().max(d2.abs());
+        if s != f32::MAX && miter.distance(p) <= limit {
+            res.push(miter);
+        } else if n1.dot(n2) > 0.0 && d1 == d2 {
+            // Collinear sides
+            res.push(p + n1 * d1);
+        } else {
+            res.push(line_1.1);
+            res.push(line_2.0);
+        }
+    }
+    res
+}
+
 #[allow(dead_code)]
 pub fn ortho2d(width: f32, height: f32) -> Matrix3 {
     let right = width / 2.0;
@@ -350,3 +431,41 @@ pub fn ortho2d_zero(width: f32, height: f32) -> Matrix3 {
         1.0,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offset_polygon() {
+        let square = [
+            Vector2::new(0.0, 0.0),
+            Vector2::new(10.0, 0.0),
+            Vector2::new(10.0, 10.0),
+            Vector2::new(0.0, 10.0),
+        ];
+        let close = |a: Vector2, b: Vector2| (a - b).x.abs() < 1e-4 && (a - b).y.abs() < 1e-4;
+        // Either orientation grows outwards
+        for vs in [square.to_vec(), square.iter().rev().copied().collect()] {
+            let out = offset_polygon(&vs, &[1.0; 4]);
+            assert_eq!(out.len(), 4);
+            let (a, b) = bounding_box_2d(out);
+            assert!(close(a, Vector2::new(-1.0, -1.0)) && close(b, Vector2::new(11.0, 11.0)));
+        }
+        let inside = offset_polygon(&square, &[-2.0; 4]);
+        let (a, b) = bounding_box_2d(inside);
+        assert!(close(a, Vector2::new(2.0, 2.0)) && close(b, Vector2::new(8.0, 8.0)));
+        // Only the bottom side moves
+        let one = offset_polygon(&square, &[1.0, 0.0, 0.0, 0.0]);
+        assert!(close(one[0], Vector2::new(0.0, -1.0)) && close(one[2], square[2]));
+        // A needle-like corner is beveled instead of going far away
+        let thin = [
+            Vector2::new(0.0, 0.0),
+            Vector2::new(100.0, 1.0),
+            Vector2::new(0.0, 2.0),
+        ];
+        let out = offset_polygon(&thin, &[1.0; 3]);
+        assert_eq!(out.len(), 4);
+        assert!(out.iter().all(|p| p.x < 105.0));
+    }
+}
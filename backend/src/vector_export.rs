@@ -4,14 +4,14 @@
 
 use anyhow::Result;
 use base64::prelude::*;
-use cgmath::{EuclideanSpace, InnerSpace, Rad, SquareMatrix, Transform};
+use cgmath::{EuclideanSpace, Rad, SquareMatrix, Transform};
 use std::io::Write;
 use std::ops::ControlFlow;
 
-use crate::paper::{
-    signature, EdgeIdPosition, EdgeStatus, FlapStyle, FoldStyle, IslandKey, Papercraft,
-};
-use crate::util_3d::{Matrix3, Point2, Vector2};
+use crate::paper::{signature, EdgeIndex, EdgeStatus, FaceIndex, FoldStyle, IslandKey, Papercraft};
+use crate::pdf_font::TrueTypeFont;
+use crate::pdf_metrics;
+use crate::util_3d::{offset_polygon, Matrix3, Point2, Vector2};
 
 /// Text alignment for labels
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -42,6 +42,9 @@ pub enum EdgeDrawKind {
 // Font size for page footer
 const FONT_SIZE: f32 = 3.0;
 
+/// Receives the fraction of the export already done, from 0 to 1.
+pub type Progress<'a> = &'a mut dyn FnMut(f32) -> Result<()>;
+
 /// Generate a single-page SVG for the given papercraft project.
 ///
 /// Returns the SVG as a string.
@@ -51,10 +54,29 @@ pub fn generate_svg(papercraft: &Papercraft, page: u32, with_textures: bool) ->
     Ok(String::from_utf8(output)?)
 }
 
+/// Generate the cut file of a page for print-and-cut: an SVG of the same size with only the
+/// outer contours of the islands, flaps included, aligned with the registration marks.
+pub fn generate_svg_cut(papercraft: &Papercraft, page: u32) -> Result<String> {
+    let mut output = Vec::new();
+    write_svg_cut(papercraft, page, &mut output)?;
+    Ok(String::from_utf8(output)?)
+}
+
 /// Generate a multi-page SVG (Inkscape-style with sodipodi:namedview).
 pub fn generate_svg_multipage(papercraft: &Papercraft, with_textures: bool) -> Result<String> {
+    generate_svg_multipage_with_progress(papercraft, with_textures, &mut |_| Ok(()))
+}
+
+/// Like `generate_svg_multipage`, calling `progress` with the fraction of the work done.
+///
+/// If `progress` returns an error the export is aborted with it.
+pub fn generate_svg_multipage_with_progress(
+    papercraft: &Papercraft,
+    with_textures: bool,
+    progress: Progress,
+) -> Result<String> {
     let mut output = Vec::new();
-    write_svg_multipage(papercraft, with_textures, &mut output)?;
+    write_svg_multipage(papercraft, with_textures, progress, &mut output)?;
     Ok(String::from_utf8(output)?)
 }
 
@@ -93,10 +115,51 @@ fn write_svg_page(
     Ok(())
 }
 
+/// Write the cut contours of a page to the given writer.
+fn write_svg_cut(papercraft: &Papercraft, page: u32, w: &mut impl Write) -> Result<()> {
+    let options = papercraft.options();
+    let page_size = Vector2::new(options.page_size.0, options.page_size.1);
+    let cut_color_hex = options.cut_line_color.to_hex();
+
+    writeln!(
+        w,
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>"#
+    )?;
+    writeln!(
+        w,
+        r#"<svg width="{0}mm" height="{1}mm" viewBox="0 0 {0} {1}" version="1.1" xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape">"#,
+        page_size.x, page_size.y
+    )?;
+    writeln!(
+        w,
+        r#"<g inkscape:label="Cut" inkscape:groupmode="layer" id="Cut">"#
+    )?;
+    let geometry = collect_page_geometry(papercraft, page);
+    for (idx, contour) in geometry.outer_paths.iter().enumerate() {
+        write!(
+            w,
+            r##"<path id="cut_{}" fill="none" stroke="{}" stroke-width="0.3" d="M "##,
+            idx, cut_color_hex
+        )?;
+        for (i, p) in contour.iter().enumerate() {
+            if i == 0 {
+                write!(w, "{},{} ", p.x, p.y)?;
+            } else {
+                write!(w, "L {},{} ", p.x, p.y)?;
+            }
+        }
+        writeln!(w, r#"Z"/>"#)?;
+    }
+    writeln!(w, r#"</g>"#)?;
+    writeln!(w, r#"</svg>"#)?;
+    Ok(())
+}
+
 /// Write multi-page SVG.
 fn write_svg_multipage(
     papercraft: &Papercraft,
     with_textures: bool,
+    progress: Progress,
     w: &mut impl Write,
 ) -> Result<()> {
     let options = papercraft.options();
@@ -159,6 +222,7 @@ fn write_svg_multipage(
         )?;
         write_svg_layers(papercraft, p, with_textures, &tex_dimensions, w)?;
         writeln!(w, r#"</g>"#)?;
+        progress((p + 1) as f32 / page_count as f32)?;
     }
 
     writeln!(w, r#"</svg>"#)?;
@@ -206,6 +270,31 @@ fn triangulate_polygon(vertex_count: usize) -> Vec<[usize; 3]> {
     triangles
 }
 
+/// How far each side of a face is extended by the bleed: only the cut edges, the joined ones
+/// continue into the next face.
+fn face_bleed(papercraft: &Papercraft, face: &crate::paper::Face) -> Vec<f32> {
+    let bleed = papercraft.options().bleed;
+    face.index_edges()
+        .into_iter()
+        .map(|i_edge| match papercraft.edge_status(i_edge) {
+            EdgeStatus::Cut(_) => bleed,
+            _ => 0.0,
+        })
+        .collect()
+}
+
+/// The bleed of the sides of a triangle of `triangulate_polygon`, from that of the polygon.
+/// The diagonals are inside the face, they are not extended.
+fn triangle_bleed(bleed: &[f32], tri: [usize; 3]) -> [f32; 3] {
+    let n = bleed.len();
+    let side = |a: usize, b: usize| if (a + 1) % n == b { bleed[a] } else { 0.0 };
+    [
+        side(tri[0], tri[1]),
+        side(tri[1], tri[2]),
+        side(tri[2], tri[0]),
+    ]
+}
+
 /// Calculate the transform matrix to map the texture unit square to the face polygon.
 /// Returns None if matrix is singular (degenerate triangle).
 fn calc_texture_matrix(uvs: [Vector2; 3], pts: [Vector2; 3]) -> Option<Matrix3> {
@@ -275,26 +364,35 @@ fn calc_svg_texture_matrix_triangle(
     }
 }
 
-/// Write all SVG layers for a single page.
-fn write_svg_layers(
-    papercraft: &Papercraft,
-    page: u32,
-    with_textures: bool,
-    tex_dimensions: &[(u32, u32)],
-    w: &mut impl Write,
-) -> Result<()> {
+/// The lines and polygons drawn in a page, relative to its top-left corner, in mm.
+struct PageGeometry {
+    /// Island, face, vertices, texture and bleed of each side of every face
+    faces_data: Vec<(
+        IslandKey,
+        crate::paper::FaceIndex,
+        Vec<Vector2>,
+        Option<usize>,
+        Vec<f32>,
+    )>,
+    /// The closed perimeter of every island going around its flaps, what a cutter must cut
+    outer_paths: Vec<Vec<Vector2>>,
+    mountain_lines: Vec<(Vector2, Vector2)>,
+    valley_lines: Vec<(Vector2, Vector2)>,
+    flap_polygons: Vec<Vec<Vector2>>,
+}
+
+fn collect_page_geometry(papercraft: &Papercraft, page: u32) -> PageGeometry {
     let options = papercraft.options();
     let scale = options.scale;
 
-    // Collect data
-    // Collect data
     let mut faces_data: Vec<(
         IslandKey,
         crate::paper::FaceIndex,
         Vec<Vector2>,
         Option<usize>,
+        Vec<f32>,
     )> = Vec::new();
-    let mut cut_paths: Vec<Vec<Vector2>> = Vec::new();
+    let mut outer_paths: Vec<Vec<Vector2>> = Vec::new();
     let mut mountain_lines: Vec<(Vector2, Vector2)> = Vec::new();
     let mut valley_lines: Vec<(Vector2, Vector2)> = Vec::new();
     let mut flap_polygons: Vec<Vec<Vector2>> = Vec::new();
@@ -323,15 +421,7 @@ fn write_svg_layers(
 
         let page_offset = options.page_position(page);
 
-        // 1. Build Face -> Island matrix map
-        let mut face_matrices: std::collections::HashMap<crate::paper::FaceIndex, Matrix3> =
-            std::collections::HashMap::new();
-        let _ = papercraft.traverse_faces(island, |i_face, _, mx| {
-            face_matrices.insert(i_face, *mx);
-            ControlFlow::Continue(())
-        });
-
-        // 2. Collect Faces
+        // 1. Collect Faces
         let _ = papercraft.traverse_faces(island, |i_face, face, full_mx| {
             let plane = papercraft.model().face_plane(face);
 
@@ -361,41 +451,20 @@ fn write_svg_layers(
                     }
                 });
 
-            faces_data.push((i_island, i_face, face_vertices, texture_idx));
+            let bleed = face_bleed(papercraft, face);
+            faces_data.push((i_island, i_face, face_vertices, texture_idx, bleed));
             ControlFlow::Continue(())
         });
 
-        // 3. Collect Cut Paths (Perimeter)
-        let perimeter = papercraft.island_perimeter(i_island);
-        if !perimeter.is_empty() {
-            let mut contour_points: Vec<Vector2> = Vec::new();
-
-            for peri in perimeter.iter() {
-                let edge = &papercraft.model()[peri.i_edge()];
-                let i_face = edge.face_by_sign(peri.face_sign()).unwrap();
-                let face = &papercraft.model()[i_face];
-                let plane = papercraft.model().face_plane(face);
-
-                let full_mx = face_matrices
-                    .get(&i_face)
-                    .cloned()
-                    .unwrap_or(Matrix3::identity());
-
-                let (i_v0, _) = face.vertices_of_edge(peri.i_edge()).unwrap();
-                let v0 = &papercraft.model()[i_v0];
-
-                let p0_2d = plane.project(&v0.pos(), scale);
-                let p0 = full_mx.transform_point(Point2::from_vec(p0_2d)).to_vec();
-
-                contour_points.push(p0 - page_offset);
-            }
+        let flaps = papercraft.island_flaps(island);
 
-            if !contour_points.is_empty() {
-                cut_paths.push(contour_points);
-            }
+        // 2. Collect Cut Paths (Perimeter)
+        let contour = island_cut_contour(papercraft, i_island, &flaps);
+        if !contour.is_empty() {
+            outer_paths.push(contour.into_iter().map(|p| p - page_offset).collect());
         }
 
-        // 4. Collect Folds
+        // 3. Collect Folds
         let _ = papercraft.traverse_faces(island, |i_face, face, full_mx| {
             for i_edge in face.index_edges() {
                 let edge_status = papercraft.edge_status(i_edge);
@@ -444,65 +513,86 @@ fn write_svg_layers(
             ControlFlow::Continue(())
         });
 
-        // 5. Collect Flaps
-        // 5. Collect Flaps
-        if options.flap_style != FlapStyle::None {
-            for peri in papercraft.island_perimeter(i_island).iter() {
-                let edge_status = papercraft.edge_status(peri.i_edge());
-                if let EdgeStatus::Cut(flap_side) = edge_status {
-                    if !flap_side.flap_visible(peri.face_sign()) {
-                        continue;
-                    }
-
-                    if !flap_side.flap_visible(peri.face_sign()) {
-                        continue;
-                    }
-
-                    let edge = &papercraft.model()[peri.i_edge()];
-                    let i_face = edge.face_by_sign(peri.face_sign()).unwrap();
-                    let face = &papercraft.model()[i_face];
-                    let plane = papercraft.model().face_plane(face);
-
-                    let full_mx = face_matrices
-                        .get(&i_face)
-                        .cloned()
-                        .unwrap_or(Matrix3::identity());
-
-                    let Some((i_v0, i_v1)) = face.vertices_of_edge(peri.i_edge()) else {
-                        continue;
-                    };
-                    let v0 = &papercraft.model()[i_v0];
-                    let v1 = &papercraft.model()[i_v1];
-
-                    let p0 = full_mx
-                        .transform_point(Point2::from_vec(plane.project(&v0.pos(), scale)))
-                        .to_vec();
-                    let p1 = full_mx
-                        .transform_point(Point2::from_vec(plane.project(&v1.pos(), scale)))
-                        .to_vec();
-
-                    let p0_rel = p0 - page_offset;
-                    let p1_rel = p1 - page_offset;
+        // 4. Collect Flaps
+        for (_, _, vertices) in flaps {
+            flap_polygons.push(vertices.into_iter().map(|p| p - page_offset).collect());
+        }
+    }
 
-                    // Calculation of flap geometry needs original vector direction,
-                    // but we can use relative points since vector difference is same.
-                    let edge_vec = p1_rel - p0_rel;
-                    let edge_len = edge_vec.magnitude();
-                    let normal = Vector2::new(-edge_vec.y, edge_vec.x).normalize();
-                    let flap_width = options.flap_width.min(edge_len * 0.4);
-                    let taper = 0.15;
+    PageGeometry {
+        faces_data,
+        outer_paths,
+        mountain_lines,
+        valley_lines,
+        flap_polygons,
+    }
+}
 
-                    let f0 =
-                        p0_rel + normal * flap_width + edge_vec.normalize() * (edge_len * taper);
-                    let f1 =
-                        p1_rel + normal * flap_width - edge_vec.normalize() * (edge_len * taper);
+/// The closed perimeter of an island going around its `flaps`, in paper coordinates, moved
+/// outwards by the cut offset. It is empty if the island has no perimeter.
+fn island_cut_contour(
+    papercraft: &Papercraft,
+    i_island: IslandKey,
+    flaps: &[(FaceIndex, EdgeIndex, Vec<Vector2>)],
+) -> Vec<Vector2> {
+    let options = papercraft.options();
+    let Some(island) = papercraft.island_by_key(i_island) else {
+        return Vec::new();
+    };
+    let mut face_matrices: std::collections::HashMap<FaceIndex, Matrix3> =
+        std::collections::HashMap::new();
+    let _ = papercraft.traverse_faces(island, |i_face, _, mx| {
+        face_matrices.insert(i_face, *mx);
+        ControlFlow::Continue(())
+    });
 
-                    flap_polygons.push(vec![p0_rel, p1_rel, f1, f0]);
-                }
-            }
+    let mut contour = Vec::new();
+    for peri in papercraft.island_perimeter(i_island).iter() {
+        let edge = &papercraft.model()[peri.i_edge()];
+        let i_face = edge.face_by_sign(peri.face_sign()).unwrap();
+        let face = &papercraft.model()[i_face];
+        let plane = papercraft.model().face_plane(face);
+        let mx = face_matrices
+            .get(&i_face)
+            .cloned()
+            .unwrap_or(Matrix3::identity());
+
+        let (i_v0, _) = face.vertices_of_edge(peri.i_edge()).unwrap();
+        let p0 = plane.project(&papercraft.model()[i_v0].pos(), options.scale);
+        contour.push(mx.transform_point(Point2::from_vec(p0)).to_vec());
+        // The flap goes from this vertex to the next one, add the points in between
+        let flap = flaps
+            .iter()
+            .find(|(f, e, _)| *f == i_face && *e == peri.i_edge());
+        if let Some((_, _, vertices)) = flap {
+            contour.extend_from_slice(&vertices[1..vertices.len() - 1]);
         }
     }
 
+    // Kerf compensation, the flaps are cut too
+    if options.cut_offset != 0.0 && !contour.is_empty() {
+        contour = offset_polygon(&contour, &vec![options.cut_offset; contour.len()]);
+    }
+    contour
+}
+
+/// Write all SVG layers for a single page.
+fn write_svg_layers(
+    papercraft: &Papercraft,
+    page: u32,
+    with_textures: bool,
+    tex_dimensions: &[(u32, u32)],
+    w: &mut impl Write,
+) -> Result<()> {
+    let options = papercraft.options();
+    let PageGeometry {
+        faces_data,
+        outer_paths,
+        mountain_lines,
+        valley_lines,
+        flap_polygons,
+    } = collect_page_geometry(papercraft, page);
+
     // Colors from options
     let paper_color_hex = options.paper_color.to_hex();
     let cut_color_hex = options.cut_line_color.to_hex();
@@ -513,7 +603,7 @@ fn write_svg_layers(
         w,
         r#"<g inkscape:label="Faces" inkscape:groupmode="layer" id="Faces">"#
     )?;
-    for (idx, (_, face_idx, vertices, texture_idx)) in faces_data.iter().enumerate() {
+    for (idx, (_, face_idx, vertices, texture_idx, bleed)) in faces_data.iter().enumerate() {
         if vertices.len() >= 3 {
             let has_texture = with_textures && texture_idx.is_some();
 
@@ -584,18 +674,14 @@ fn write_svg_layers(
                             writeln!(w, r#"</pattern>"#)?;
                             writeln!(w, r#"</defs>"#)?;
 
-                            // Fill the polygon with the pattern
-                            writeln!(
-                                w,
-                                r##"<polygon points="{},{} {},{} {},{}" fill="url(#{})" stroke="none"/>"##,
-                                tri_pts[0].x,
-                                tri_pts[0].y,
-                                tri_pts[1].x,
-                                tri_pts[1].y,
-                                tri_pts[2].x,
-                                tri_pts[2].y,
-                                pattern_id
-                            )?;
+                            // Fill the polygon with the pattern, the bleed continues the texture
+                            let outline =
+                                offset_polygon(&tri_pts, &triangle_bleed(bleed, *tri_indices));
+                            write!(w, r#"<polygon points=""#)?;
+                            for p in &outline {
+                                write!(w, "{},{} ", p.x, p.y)?;
+                            }
+                            writeln!(w, r##"" fill="url(#{})" stroke="none"/>"##, pattern_id)?;
                         }
                     }
                 }
@@ -606,7 +692,7 @@ fn write_svg_layers(
                     r#"<polygon id="face_{}" fill="{}" stroke="none" points=""#,
                     idx, paper_color_hex
                 )?;
-                for v in vertices {
+                for v in &offset_polygon(vertices, bleed) {
                     write!(w, "{},{} ", v.x, v.y)?;
                 }
                 writeln!(w, r#""/>"#)?;
@@ -672,7 +758,7 @@ fn write_svg_layers(
         w,
         r#"<g inkscape:label="Cut" inkscape:groupmode="layer" id="Cut">"#
     )?;
-    for (idx, contour) in cut_paths.iter().enumerate() {
+    for (idx, contour) in outer_paths.iter().enumerate() {
         write!(
             w,
             r##"<path id="cut_{}" fill="none" stroke="{}" stroke-width="0.3" d="M "##,
@@ -689,6 +775,9 @@ fn write_svg_layers(
     }
     writeln!(w, r#"</g>"#)?;
 
+    // Write Registration marks layer
+    write_svg_registration_marks(options, w)?;
+
     // Write Text layer
     let texts = collect_texts(papercraft, options, page);
     if !texts.is_empty() {
@@ -734,12 +823,47 @@ fn write_svg_layers(
     Ok(())
 }
 
+/// Write the print-and-cut registration marks layer, if they are enabled.
+fn write_svg_registration_marks(
+    options: &crate::paper::PaperOptions,
+    w: &mut impl Write,
+) -> Result<()> {
+    let marks = options.registration_mark_rects();
+    if marks.is_empty() {
+        return Ok(());
+    }
+    writeln!(
+        w,
+        r#"<g inkscape:label="Registration" inkscape:groupmode="layer" id="Registration">"#
+    )?;
+    for (idx, (a, b)) in marks.iter().enumerate() {
+        writeln!(
+            w,
+            r##"<rect id="mark_{}" x="{}" y="{}" width="{}" height="{}" fill="#000000" stroke="none"/>"##,
+            idx,
+            a.x,
+            a.y,
+            b.x - a.x,
+            b.y - a.y
+        )?;
+    }
+    writeln!(w, r#"</g>"#)?;
+    Ok(())
+}
+
 /// Collect text elements for a page (page numbers, edge IDs, signature).
 fn collect_texts(
     papercraft: &Papercraft,
     options: &crate::paper::PaperOptions,
     page: u32,
 ) -> Vec<PrintableText> {
+    let mut texts = collect_page_texts(options, page);
+    texts.extend(collect_label_texts(papercraft, options, page));
+    texts
+}
+
+/// Collect the texts of the page itself (page number, signature).
+fn collect_page_texts(options: &crate::paper::PaperOptions, page: u32) -> Vec<PrintableText> {
     let page_size = Vector2::new(options.page_size.0, options.page_size.1);
     let (_margin_top, margin_left, margin_right, margin_bottom) = options.margin;
     let page_count = options.pages;
@@ -772,25 +896,42 @@ fn collect_texts(
         });
     }
 
-    // Edge IDs
-    if options.edge_id_position != EdgeIdPosition::None {
-        let in_page = options.is_in_page_fn(page);
-        let edge_id_font_size = options.edge_id_font_size * 25.4 / 72.0; // pt to mm
+    texts
+}
+
+/// Collect the labels of the islands in a page (edge IDs, island names).
+fn collect_label_texts(
+    papercraft: &Papercraft,
+    options: &crate::paper::PaperOptions,
+    page: u32,
+) -> Vec<PrintableText> {
+    let mut texts = Vec::new();
 
-        for (_i_island, island) in papercraft.islands() {
-            // Island name
-            let island_center = island.location();
-            let (is_in, pos) = in_page(island_center);
+    // Edge IDs and island names
+    let in_page = options.is_in_page_fn(page);
+    let edge_id_font_size = papercraft.edge_id_font_size();
+    for (_i_island, island) in papercraft.islands() {
+        for label in papercraft.island_edge_labels(island) {
+            let (is_in, pos) = in_page(label.pos);
             if is_in {
                 texts.push(PrintableText {
                     size: edge_id_font_size,
                     pos,
-                    angle: Rad(0.0),
+                    angle: label.angle,
                     align: TextAlign::Center,
-                    text: island.name().to_string(),
+                    text: label.text,
                 });
             }
         }
+        if let Some((true, pos)) = papercraft.island_name_position(island).map(&in_page) {
+            texts.push(PrintableText {
+                size: edge_id_font_size,
+                pos,
+                angle: Rad(0.0),
+                align: TextAlign::Center,
+                text: island.name().to_string(),
+            });
+        }
     }
 
     texts
@@ -804,6 +945,183 @@ fn html_escape(s: &str) -> String {
         .replace('"', "&quot;")
 }
 
+// ============================================================================
+// DXF Generation
+// ============================================================================
+
+// Layers of the DXF output: name, AutoCAD color index and line type
+const DXF_CUT: (&str, u8, &str) = ("CUT", 1, "CONTINUOUS"); // red
+const DXF_MOUNTAIN: (&str, u8, &str) = ("MOUNTAIN", 5, "CONTINUOUS"); // blue
+const DXF_VALLEY: (&str, u8, &str) = ("VALLEY", 3, "DASHED"); // green
+const DXF_FLAPS: (&str, u8, &str) = ("FLAPS", 6, "CONTINUOUS"); // magenta
+
+/// Generate a DXF drawing for cutting plotters and CAD programs, in mm.
+///
+/// Cut lines, mountain folds, valley folds and flap outlines go into their own layers. With
+/// `page` only that page is drawn, if not all the pages are tiled as in the layout.
+pub fn generate_dxf(papercraft: &Papercraft, page: Option<u32>) -> Result<String> {
+    let mut output = Vec::new();
+    write_dxf(papercraft, page, false, &mut output)?;
+    Ok(String::from_utf8(output)?)
+}
+
+/// Like `generate_dxf`, but only with the outer contours of the islands, flaps included, in
+/// the cut layer. That is the cut file of print-and-cut.
+pub fn generate_dxf_cut(papercraft: &Papercraft, page: Option<u32>) -> Result<String> {
+    let mut output = Vec::new();
+    write_dxf(papercraft, page, true, &mut output)?;
+    Ok(String::from_utf8(output)?)
+}
+
+fn write_dxf(
+    papercraft: &Papercraft,
+    page: Option<u32>,
+    cut_only: bool,
+    w: &mut impl Write,
+) -> Result<()> {
+    let options = papercraft.options();
+    let page_size = Vector2::new(options.page_size.0, options.page_size.1);
+    let pages = match page {
+        Some(page) => page..page + 1,
+        None => 0..options.pages,
+    };
+    // The last page is in the last row
+    let height = match page {
+        Some(_) => page_size.y,
+        None => options.page_position(options.pages.saturating_sub(1)).y + page_size.y,
+    };
+
+    dxf_pair(w, 0, "SECTION")?;
+    dxf_pair(w, 2, "HEADER")?;
+    dxf_pair(w, 9, "$ACADVER")?;
+    dxf_pair(w, 1, "AC1009")?;
+    // R12 has no header variable for the units, the drawing is in millimetres
+    dxf_pair(w, 0, "ENDSEC")?;
+
+    dxf_pair(w, 0, "SECTION")?;
+    dxf_pair(w, 2, "TABLES")?;
+    dxf_pair(w, 0, "TABLE")?;
+    dxf_pair(w, 2, "LTYPE")?;
+    dxf_pair(w, 70, 2)?;
+    for (name, description, dashes) in [
+        ("CONTINUOUS", "Solid line", &[][..]),
+        ("DASHED", "Dashed line", &[1.0, -1.0][..]),
+    ] {
+        dxf_pair(w, 0, "LTYPE")?;
+        dxf_pair(w, 2, name)?;
+        dxf_pair(w, 70, 0)?;
+        dxf_pair(w, 3, description)?;
+        dxf_pair(w, 72, 65)?;
+        dxf_pair(w, 73, dashes.len())?;
+        dxf_pair(w, 40, dashes.iter().map(|d: &f32| d.abs()).sum::<f32>())?;
+        for dash in dashes {
+            dxf_pair(w, 49, dash)?;
+        }
+    }
+    dxf_pair(w, 0, "ENDTAB")?;
+    let layers: &[_] = if cut_only {
+        &[DXF_CUT]
+    } else {
+        &[DXF_CUT, DXF_MOUNTAIN, DXF_VALLEY, DXF_FLAPS]
+    };
+    dxf_pair(w, 0, "TABLE")?;
+    dxf_pair(w, 2, "LAYER")?;
+    dxf_pair(w, 70, layers.len())?;
+    for &(name, color, line_type) in layers {
+        dxf_pair(w, 0, "LAYER")?;
+        dxf_pair(w, 2, name)?;
+        dxf_pair(w, 70, 0)?;
+        dxf_pair(w, 62, color)?;
+        dxf_pair(w, 6, line_type)?;
+    }
+    dxf_pair(w, 0, "ENDTAB")?;
+    dxf_pair(w, 0, "ENDSEC")?;
+
+    dxf_pair(w, 0, "SECTION")?;
+    dxf_pair(w, 2, "ENTITIES")?;
+    for p in pages {
+        let page_offset = match page {
+            Some(_) => Vector2::new(0.0, 0.0),
+            None => options.page_position(p),
+        };
+        // DXF has the Y axis pointing up
+        let to_dxf = |v: &Vector2| {
+            let v = *v + page_offset;
+            Vector2::new(v.x, height - v.y)
+        };
+        let geometry = collect_page_geometry(papercraft, p);
+
+        if cut_only {
+            for contour in &geometry.outer_paths {
+                let vertices: Vec<_> = contour.iter().map(to_dxf).collect();
+                dxf_polyline(w, DXF_CUT.0, &vertices)?;
+            }
+            continue;
+        }
+        for vertices in &geometry.flap_polygons {
+            let vertices: Vec<_> = vertices.iter().map(to_dxf).collect();
+            dxf_polyline(w, DXF_FLAPS.0, &vertices)?;
+        }
+        if options.fold_style != FoldStyle::None {
+            for (layer, lines) in [
+                (DXF_MOUNTAIN.0, &geometry.mountain_lines),
+                (DXF_VALLEY.0, &geometry.valley_lines),
+            ] {
+                for (p0, p1) in lines {
+                    dxf_line(w, layer, to_dxf(p0), to_dxf(p1))?;
+                }
+            }
+        }
+        for contour in &geometry.outer_paths {
+            let vertices: Vec<_> = contour.iter().map(to_dxf).collect();
+            dxf_polyline(w, DXF_CUT.0, &vertices)?;
+        }
+    }
+    dxf_pair(w, 0, "ENDSEC")?;
+    dxf_pair(w, 0, "EOF")?;
+    Ok(())
+}
+
+// A DXF file is a list of pairs of lines, a group code and its value
+fn dxf_pair(w: &mut impl Write, code: u16, value: impl std::fmt::Display) -> Result<()> {
+    writeln!(w, "{code:>3}")?;
+    writeln!(w, "{value}")?;
+    Ok(())
+}
+
+fn dxf_line(w: &mut impl Write, layer: &str, p0: Vector2, p1: Vector2) -> Result<()> {
+    dxf_pair(w, 0, "LINE")?;
+    dxf_pair(w, 8, layer)?;
+    dxf_pair(w, 10, p0.x)?;
+    dxf_pair(w, 20, p0.y)?;
+    dxf_pair(w, 30, 0.0)?;
+    dxf_pair(w, 11, p1.x)?;
+    dxf_pair(w, 21, p1.y)?;
+    dxf_pair(w, 31, 0.0)?;
+    Ok(())
+}
+
+// A closed polyline, as R12 writes them, with a VERTEX entity for each point
+fn dxf_polyline(w: &mut impl Write, layer: &str, vertices: &[Vector2]) -> Result<()> {
+    dxf_pair(w, 0, "POLYLINE")?;
+    dxf_pair(w, 8, layer)?;
+    dxf_pair(w, 66, 1)?;
+    dxf_pair(w, 10, 0.0)?;
+    dxf_pair(w, 20, 0.0)?;
+    dxf_pair(w, 30, 0.0)?;
+    dxf_pair(w, 70, 1)?;
+    for v in vertices {
+        dxf_pair(w, 0, "VERTEX")?;
+        dxf_pair(w, 8, layer)?;
+        dxf_pair(w, 10, v.x)?;
+        dxf_pair(w, 20, v.y)?;
+        dxf_pair(w, 30, 0.0)?;
+    }
+    dxf_pair(w, 0, "SEQEND")?;
+    dxf_pair(w, 8, layer)?;
+    Ok(())
+}
+
 // ============================================================================
 // PDF Generation
 // ============================================================================
@@ -850,10 +1168,13 @@ fn calc_pdf_texture_matrix_triangle(uvs: [Vector2; 3], pts: [Point2; 3]) -> Opti
 fn embed_pdf_textures(
     papercraft: &Papercraft,
     doc: &mut Document,
+    progress: Progress,
 ) -> Result<Vec<(lopdf::ObjectId, lopdf::ObjectId, u32, u32)>> {
     let mut texture_info = Vec::new();
 
-    for texture in papercraft.model().textures() {
+    let texture_count = papercraft.model().textures().count();
+    for (i_texture, texture) in papercraft.model().textures().enumerate() {
+        progress(i_texture as f32 / texture_count as f32)?;
         if let Some(pixbuf) = texture.pixbuf() {
             let width = pixbuf.width();
             let height = pixbuf.height();
@@ -915,6 +1236,17 @@ fn embed_pdf_textures(
 
 /// Generate a PDF document from the papercraft project.
 pub fn generate_pdf(papercraft: &Papercraft, with_textures: bool) -> Result<Vec<u8>> {
+    generate_pdf_with_progress(papercraft, with_textures, &mut |_| Ok(()))
+}
+
+/// Like `generate_pdf`, calling `progress` with the fraction of the work done.
+///
+/// If `progress` returns an error the export is aborted with it.
+pub fn generate_pdf_with_progress(
+    papercraft: &Papercraft,
+    with_textures: bool,
+    progress: Progress,
+) -> Result<Vec<u8>> {
     let mut options = papercraft.options().clone();
 
     // Auto-detect page columns if islands extend beyond current cols
@@ -938,32 +1270,58 @@ pub fn generate_pdf(papercraft: &Papercraft, with_textures: bool) -> Result<Vec<
     let page_size_mm = Vector2::new(options.page_size.0, options.page_size.1);
     let page_count = options.pages;
 
-    let mut doc = Document::with_version("1.4");
+    // Optional content needs 1.5
+    let mut doc = Document::with_version("1.5");
     doc.reference_table.cross_reference_type = XrefType::CrossReferenceTable;
 
     let id_pages = doc.new_object_id();
 
-    let id_font = doc.add_object(dictionary! {
-        "Type" => "Font",
-        "Subtype" => "Type1",
-        "BaseFont" => "Helvetica",
-        "Encoding" => "WinAnsiEncoding",
-    });
+    // Written after the pages, when the glyphs used are known
+    let id_font = doc.new_object_id();
+    let mut font = match &options.pdf_font {
+        Some(name) => PdfTextFont::TrueType(TrueTypeFont::find(name)?),
+        None => PdfTextFont::Helvetica,
+    };
 
-    // Embed textures as XObjects if needed
-    let texture_xobjects = if with_textures {
-        embed_pdf_textures(papercraft, &mut doc)?
+    // The layers, shared by all the pages
+    let id_layers: Vec<lopdf::ObjectId> = PDF_LAYERS
+        .iter()
+        .map(|(_, title)| {
+            doc.add_object(dictionary! {
+                "Type" => "OCG",
+                "Name" => Object::string_literal(*title),
+            })
+        })
+        .collect();
+    let layer_properties: lopdf::Dictionary = PDF_LAYERS
+        .iter()
+        .zip(&id_layers)
+        .map(|((name, _), id)| (name.to_string(), (*id).into()))
+        .collect();
+
+    // Embed textures as XObjects if needed, compressing them takes about as long as the pages
+    let (texture_xobjects, pages_start) = if with_textures {
+        let xobjects = embed_pdf_textures(papercraft, &mut doc, &mut |p| progress(p / 2.0))?;
+        (xobjects, 0.5)
     } else {
-        Vec::new()
+        (Vec::new(), 0.0)
     };
 
     let mut pages = vec![];
 
     for page in 0..page_count {
-        let ops =
-            generate_pdf_page_ops(papercraft, &options, page, with_textures, &texture_xobjects)?;
+        let ops = generate_pdf_page_ops(
+            papercraft,
+            &options,
+            page,
+            with_textures,
+            &texture_xobjects,
+            &mut font,
+        )?;
 
-        let content = Content { operations: ops };
+        let content = Content {
+            operations: ops.into_operations(),
+        };
         let id_content = doc.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
 
         // Build resources dictionary with textures
@@ -971,6 +1329,7 @@ pub fn generate_pdf(papercraft: &Papercraft, with_textures: bool) -> Result<Vec<
             "Font" => dictionary! {
                 "F1" => id_font,
             },
+            "Properties" => layer_properties.clone(),
         };
 
         if !texture_xobjects.is_empty() {
@@ -993,6 +1352,7 @@ pub fn generate_pdf(papercraft: &Papercraft, with_textures: bool) -> Result<Vec<
             "Resources" => id_resources,
         });
         pages.push(id_page.into());
+        progress(pages_start + (1.0 - pages_start) * (page + 1) as f32 / page_count as f32)?;
     }
 
     let pdf_pages = dictionary! {
@@ -1006,9 +1366,29 @@ pub fn generate_pdf(papercraft: &Papercraft, with_textures: bool) -> Result<Vec<
     };
     doc.set_object(id_pages, pdf_pages);
 
+    let pdf_font = match &font {
+        PdfTextFont::Helvetica => dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+            "Encoding" => "WinAnsiEncoding",
+        },
+        PdfTextFont::TrueType(font) => font.pdf_font(&mut doc)?,
+    };
+    doc.set_object(id_font, pdf_font);
+
+    let layer_refs: Vec<Object> = id_layers.iter().map(|&id| id.into()).collect();
     let id_catalog = doc.add_object(dictionary! {
         "Type" => "Catalog",
         "Pages" => id_pages,
+        "OCProperties" => dictionary! {
+            "OCGs" => layer_refs.clone(),
+            // All visible, listed in drawing order
+            "D" => dictionary! {
+                "Name" => Object::string_literal("Layers"),
+                "Order" => layer_refs,
+            },
+        },
     });
     doc.trailer.set("Root", id_catalog);
 
@@ -1039,6 +1419,93 @@ pub fn generate_pdf(papercraft: &Papercraft, with_textures: bool) -> Result<Vec<
     Ok(buffer)
 }
 
+/// The font of the texts of the PDF pages.
+enum PdfTextFont {
+    /// The standard font, not embedded, it can only write the characters of WinAnsiEncoding
+    Helvetica,
+    /// An embedded font, with the glyphs used only
+    TrueType(TrueTypeFont),
+}
+
+impl PdfTextFont {
+    /// The PDF string that writes `text` with this font, and its width in thousandths of the
+    /// font size.
+    fn encode(&mut self, text: &str) -> (Object, f32) {
+        match self {
+            PdfTextFont::Helvetica => {
+                // The characters that are not in the encoding are skipped, and not measured
+                let (bytes, text): (Vec<u8>, String) = text
+                    .chars()
+                    .filter_map(|c| Some((pdf_metrics::win_ansi(c)?, c)))
+                    .unzip();
+                let (width, _) = pdf_metrics::measure_helvetica(&text);
+                (Object::String(bytes, StringFormat::Literal), width as f32)
+            }
+            PdfTextFont::TrueType(font) => {
+                let (bytes, width) = font.encode(text);
+                (Object::String(bytes, StringFormat::Hexadecimal), width)
+            }
+        }
+    }
+}
+
+// Optional content groups of every page, in drawing order: resource name and title
+const PDF_LAYERS: [(&str, &str); 7] = [
+    ("Textures", "Textures"),
+    ("Flaps", "Flaps"),
+    ("Mountain", "Mountain folds"),
+    ("Valley", "Valley folds"),
+    ("Cut", "Cut lines"),
+    ("EdgeIds", "Edge IDs"),
+    ("Furniture", "Page furniture"),
+];
+
+/// The PDF operations of a page, one list for each layer of `PDF_LAYERS`.
+#[derive(Default)]
+struct PdfPageLayers {
+    textures: Vec<Operation>,
+    flaps: Vec<Operation>,
+    mountain: Vec<Operation>,
+    valley: Vec<Operation>,
+    cut: Vec<Operation>,
+    edge_ids: Vec<Operation>,
+    furniture: Vec<Operation>,
+}
+
+impl PdfPageLayers {
+    /// The content of the page, each layer marked as optional content and with its own
+    /// graphics state.
+    fn into_operations(self) -> Vec<Operation> {
+        let layers = [
+            self.textures,
+            self.flaps,
+            self.mountain,
+            self.valley,
+            self.cut,
+            self.edge_ids,
+            self.furniture,
+        ];
+        let mut ops = Vec::new();
+        for ((name, _), layer) in PDF_LAYERS.iter().zip(layers) {
+            if layer.is_empty() {
+                continue;
+            }
+            ops.push(Operation::new(
+                "BDC",
+                vec![
+                    Object::Name(b"OC".to_vec()),
+                    Object::Name(name.as_bytes().to_vec()),
+                ],
+            ));
+            ops.push(Operation::new("q", vec![]));
+            ops.extend(layer);
+            ops.push(Operation::new("Q", vec![]));
+            ops.push(Operation::new("EMC", vec![]));
+        }
+        ops
+    }
+}
+
 /// Generate PDF operations for a single page.
 fn generate_pdf_page_ops(
     papercraft: &Papercraft,
@@ -1046,12 +1513,13 @@ fn generate_pdf_page_ops(
     page: u32,
     with_textures: bool,
     texture_xobjects: &[(lopdf::ObjectId, lopdf::ObjectId, u32, u32)],
-) -> Result<Vec<Operation>> {
+    font: &mut PdfTextFont,
+) -> Result<PdfPageLayers> {
     let page_size_mm = Vector2::new(options.page_size.0, options.page_size.1);
     let scale = options.scale;
     let page_offset = options.page_position(page);
 
-    let mut ops: Vec<Operation> = Vec::new();
+    let mut layers = PdfPageLayers::default();
 
     // Helper to convert mm to points
     let mm_to_pt = |mm: f32| mm * 72.0 / 25.4;
@@ -1088,6 +1556,9 @@ fn generate_pdf_page_ops(
                 .collect();
 
             if vertices.len() >= 3 {
+                let bleed = face_bleed(papercraft, face);
+                let outline = offset_polygon(&vertices, &bleed);
+
                 // Get material index for this face (if any)
                 // Material index directly maps to texture index (0-based)
                 let material_idx = usize::from(face.material());
@@ -1098,7 +1569,7 @@ fn generate_pdf_page_ops(
                 let has_texture = with_textures && texture_info.is_some();
 
                 // First, always draw the paper color fill as base
-                ops.push(Operation::new(
+                layers.textures.push(Operation::new(
                     "rg",
                     vec![
                         paper_color.0.r.into(),
@@ -1108,22 +1579,22 @@ fn generate_pdf_page_ops(
                 ));
 
                 // Move to first vertex
-                let p0 = vertices[0];
-                ops.push(Operation::new(
+                let p0 = outline[0];
+                layers.textures.push(Operation::new(
                     "m",
                     vec![mm_to_pt(p0.x).into(), pdf_y(p0.y).into()],
                 ));
 
                 // Line to other vertices
-                for p in &vertices[1..] {
-                    ops.push(Operation::new(
+                for p in &outline[1..] {
+                    layers.textures.push(Operation::new(
                         "l",
                         vec![mm_to_pt(p.x).into(), pdf_y(p.y).into()],
                     ));
                 }
 
                 // Close and fill
-                ops.push(Operation::new("f", vec![]));
+                layers.textures.push(Operation::new("f", vec![]));
 
                 // Draw texture if enabled and available
                 if has_texture {
@@ -1155,11 +1626,11 @@ fn generate_pdf_page_ops(
                                 ];
 
                                 // Calculate texture transform matrix for this triangle
-                                if let Some(tex_matrix) =
-                                    calc_pdf_texture_matrix_triangle(tri_uvs, tri_pts)
-                                {
+                                let tex_matrix = calc_pdf_texture_matrix_triangle(tri_uvs, tri_pts);
+                                let to_uv = tex_matrix.and_then(|m| m.invert());
+                                if let (Some(tex_matrix), Some(to_uv)) = (tex_matrix, to_uv) {
                                     // Save graphics state
-                                    ops.push(Operation::new("q", vec![]));
+                                    layers.textures.push(Operation::new("q", vec![]));
 
                                     // The texture matrix maps UV coordinates to paper coords (mm)
                                     // We want to draw using UV coordinates directly.
@@ -1182,7 +1653,7 @@ fn generate_pdf_page_ops(
                                     let f_pt = (page_size_mm.y - f) * mm_to_pt_scale;
 
                                     // Apply UV-to-paper transformation matrix
-                                    ops.push(Operation::new(
+                                    layers.textures.push(Operation::new(
                                         "cm",
                                         vec![
                                             a_pt.into(),
@@ -1195,12 +1666,12 @@ fn generate_pdf_page_ops(
                                     ));
 
                                     // Set Pattern Color Space
-                                    ops.push(Operation::new(
+                                    layers.textures.push(Operation::new(
                                         "cs",
                                         vec![Object::Name(b"Pattern".to_vec())],
                                     ));
                                     // Set Pattern Color (Non-Stroking)
-                                    ops.push(Operation::new(
+                                    layers.textures.push(Operation::new(
                                         "scn",
                                         vec![Object::Name(
                                             format!("Pat{}", material_idx).into_bytes(),
@@ -1209,26 +1680,24 @@ fn generate_pdf_page_ops(
 
                                     // Draw the triangle using UV coordinates
                                     // Note: cm establishes the coordinate system where (u,v) maps to Page(x,y).
-                                    // So we simply draw the triangle using UVs.
-
-                                    ops.push(Operation::new(
-                                        "m",
-                                        vec![tri_uvs[0].x.into(), tri_uvs[0].y.into()],
-                                    ));
-                                    ops.push(Operation::new(
-                                        "l",
-                                        vec![tri_uvs[1].x.into(), tri_uvs[1].y.into()],
-                                    ));
-                                    ops.push(Operation::new(
-                                        "l",
-                                        vec![tri_uvs[2].x.into(), tri_uvs[2].y.into()],
-                                    ));
+                                    // So we simply draw the triangle, extended by the bleed, in UVs.
+                                    let tri_outline = offset_polygon(
+                                        &tri_pts.map(|p| p.to_vec()),
+                                        &triangle_bleed(&bleed, *tri_indices),
+                                    );
+                                    for (i, p) in tri_outline.iter().enumerate() {
+                                        let uv = to_uv.transform_point(Point2::from_vec(*p));
+                                        layers.textures.push(Operation::new(
+                                            if i == 0 { "m" } else { "l" },
+                                            vec![uv.x.into(), uv.y.into()],
+                                        ));
+                                    }
 
                                     // Close and fill
-                                    ops.push(Operation::new("f", vec![]));
+                                    layers.textures.push(Operation::new("f", vec![]));
 
                                     // Restore graphics state
-                                    ops.push(Operation::new("Q", vec![]));
+                                    layers.textures.push(Operation::new("Q", vec![]));
                                 }
                             }
                         }
@@ -1240,12 +1709,24 @@ fn generate_pdf_page_ops(
         });
     }
 
-    // Draw lines (black)
-    ops.push(Operation::new(
-        "RG",
-        vec![0.0.into(), 0.0.into(), 0.0.into()],
+    // Draw lines (black), every layer has its own graphics state
+    for (lines, width) in [
+        (&mut layers.flaps, 0.2),
+        (&mut layers.mountain, 0.5),
+        (&mut layers.valley, 0.5),
+        (&mut layers.cut, 0.5),
+    ] {
+        lines.push(Operation::new(
+            "RG",
+            vec![0.0.into(), 0.0.into(), 0.0.into()],
+        ));
+        lines.push(Operation::new("w", vec![width.into()])); // Line width
+    }
+    // Valley: Dashed
+    layers.valley.push(Operation::new(
+        "d",
+        vec![vec![2.into(), 2.into()].into(), 0.into()],
     ));
-    ops.push(Operation::new("w", vec![0.5.into()])); // Line width
 
     for (i_island, island) in papercraft.islands() {
         // Bounding box filter
@@ -1258,14 +1739,6 @@ fn generate_pdf_page_ops(
             continue;
         }
 
-        // Build Face -> Island matrix map for flaps and perimeter
-        let mut face_matrices: std::collections::HashMap<crate::paper::FaceIndex, Matrix3> =
-            std::collections::HashMap::new();
-        let _ = papercraft.traverse_faces(island, |i_face, _, mx| {
-            face_matrices.insert(i_face, *mx);
-            ControlFlow::Continue(())
-        });
-
         // 1. Draw Folds
         if options.fold_style != FoldStyle::None {
             let _ = papercraft.traverse_faces(island, |i_face, face, mx| {
@@ -1304,16 +1777,11 @@ fn generate_pdf_page_ops(
                     let p1 = p1_global - page_offset;
 
                     let angle = edge.angle().0;
-                    if angle.is_sign_negative() {
-                        // Valley: Dashed
-                        ops.push(Operation::new(
-                            "d",
-                            vec![vec![2.into(), 2.into()].into(), 0.into()],
-                        ));
+                    let ops = if angle.is_sign_negative() {
+                        &mut layers.valley
                     } else {
-                        // Mountain: Solid
-                        ops.push(Operation::new("d", vec![vec![].into(), 0.into()]));
-                    }
+                        &mut layers.mountain
+                    };
                     ops.push(Operation::new(
                         "m",
                         vec![mm_to_pt(p0.x).into(), pdf_y(p0.y).into()],
@@ -1326,192 +1794,125 @@ fn generate_pdf_page_ops(
                 }
                 ControlFlow::Continue(())
             });
-            // Reset dash
-            ops.push(Operation::new("d", vec![vec![].into(), 0.into()]));
         }
 
         // 2. Draw Flaps
-        if options.flap_style != FlapStyle::None {
-            for peri in papercraft.island_perimeter(i_island).iter() {
-                let edge_status = papercraft.edge_status(peri.i_edge());
-                if let EdgeStatus::Cut(flap_side) = edge_status {
-                    if !flap_side.flap_visible(peri.face_sign()) {
-                        continue;
-                    }
-
-                    let edge = &papercraft.model()[peri.i_edge()];
-                    let i_face = edge.face_by_sign(peri.face_sign()).unwrap();
-                    let face = &papercraft.model()[i_face];
-                    let plane = papercraft.model().face_plane(face);
-
-                    let mx = face_matrices
-                        .get(&i_face)
-                        .cloned()
-                        .unwrap_or(Matrix3::identity());
-
-                    let Some((i_v0, i_v1)) = face.vertices_of_edge(peri.i_edge()) else {
-                        continue;
-                    };
-                    let v0 = &papercraft.model()[i_v0];
-                    let v1 = &papercraft.model()[i_v1];
-
-                    let p0_global = mx
-                        .transform_point(Point2::from_vec(plane.project(&v0.pos(), scale)))
-                        .to_vec();
-                    let p1_global = mx
-                        .transform_point(Point2::from_vec(plane.project(&v1.pos(), scale)))
-                        .to_vec();
-
-                    let p0 = p0_global - page_offset;
-                    let p1 = p1_global - page_offset;
-
-                    let edge_vec = p1 - p0;
-                    let edge_len = edge_vec.magnitude();
-                    let normal = Vector2::new(-edge_vec.y, edge_vec.x).normalize();
-                    let flap_width = options.flap_width.min(edge_len * 0.4);
-                    let taper = 0.15;
-
-                    let f0 = p0 + normal * flap_width + edge_vec.normalize() * (edge_len * taper);
-                    let f1 = p1 + normal * flap_width - edge_vec.normalize() * (edge_len * taper);
-
-                    // Fill Flap
-                    ops.push(Operation::new(
-                        "rg",
-                        vec![0.88.into(), 0.88.into(), 0.88.into()],
-                    ));
+        let flaps = papercraft.island_flaps(island);
+        for (_, _, vertices) in &flaps {
+            let vertices: Vec<Vector2> = vertices.iter().map(|p| p - page_offset).collect();
+            let path = |ops: &mut Vec<Operation>| {
+                for (i, p) in vertices.iter().enumerate() {
+                    let op = if i == 0 { "m" } else { "l" };
                     ops.push(Operation::new(
-                        "m",
-                        vec![mm_to_pt(p0.x).into(), pdf_y(p0.y).into()],
-                    ));
-                    ops.push(Operation::new(
-                        "l",
-                        vec![mm_to_pt(p1.x).into(), pdf_y(p1.y).into()],
-                    ));
-                    ops.push(Operation::new(
-                        "l",
-                        vec![mm_to_pt(f1.x).into(), pdf_y(f1.y).into()],
-                    ));
-                    ops.push(Operation::new(
-                        "l",
-                        vec![mm_to_pt(f0.x).into(), pdf_y(f0.y).into()],
-                    ));
-                    ops.push(Operation::new("f", vec![]));
-
-                    // Stroke Flap
-                    ops.push(Operation::new("w", vec![0.2.into()]));
-                    ops.push(Operation::new(
-                        "m",
-                        vec![mm_to_pt(p0.x).into(), pdf_y(p0.y).into()],
-                    ));
-                    ops.push(Operation::new(
-                        "l",
-                        vec![mm_to_pt(f0.x).into(), pdf_y(f0.y).into()],
-                    ));
-                    ops.push(Operation::new(
-                        "l",
-                        vec![mm_to_pt(f1.x).into(), pdf_y(f1.y).into()],
-                    ));
-                    ops.push(Operation::new(
-                        "l",
-                        vec![mm_to_pt(p1.x).into(), pdf_y(p1.y).into()],
+                        op,
+                        vec![mm_to_pt(p.x).into(), pdf_y(p.y).into()],
                     ));
-                    ops.push(Operation::new("S", vec![]));
                 }
-            }
-        }
-
-        // 3. Draw Perimeter Cut Lines
-        let perimeter = papercraft.island_perimeter(i_island);
-        if !perimeter.is_empty() {
-            let mut contour_points: Vec<Vector2> = Vec::new();
-
-            for peri in perimeter.iter() {
-                let edge = &papercraft.model()[peri.i_edge()];
-                let i_face = edge.face_by_sign(peri.face_sign()).unwrap();
-                let face = &papercraft.model()[i_face];
-                let plane = papercraft.model().face_plane(face);
-
-                let mx = face_matrices
-                    .get(&i_face)
-                    .cloned()
-                    .unwrap_or(Matrix3::from_scale(1.0));
+            };
 
-                let (i_v0, _) = face.vertices_of_edge(peri.i_edge()).unwrap();
-                let v0 = &papercraft.model()[i_v0];
+            // Fill Flap
+            layers.flaps.push(Operation::new(
+                "rg",
+                vec![0.88.into(), 0.88.into(), 0.88.into()],
+            ));
+            path(&mut layers.flaps);
+            layers.flaps.push(Operation::new("f", vec![]));
 
-                let p0_2d = plane.project(&v0.pos(), scale);
-                let p0_global = mx.transform_point(Point2::from_vec(p0_2d)).to_vec();
+            // Stroke Flap, closed to draw the base, the cut goes around it
+            path(&mut layers.flaps);
+            layers.flaps.push(Operation::new("s", vec![]));
+        }
 
-                contour_points.push(p0_global - page_offset);
-            }
+        // 3. Draw Perimeter Cut Lines
+        let contour_points: Vec<Vector2> = island_cut_contour(papercraft, i_island, &flaps)
+            .into_iter()
+            .map(|p| p - page_offset)
+            .collect();
+        if !contour_points.is_empty() {
+            let p0 = contour_points[0];
+            layers.cut.push(Operation::new(
+                "m",
+                vec![mm_to_pt(p0.x).into(), pdf_y(p0.y).into()],
+            ));
 
-            if !contour_points.is_empty() {
-                let p0 = contour_points[0];
-                ops.push(Operation::new(
-                    "m",
-                    vec![mm_to_pt(p0.x).into(), pdf_y(p0.y).into()],
+            for p in &contour_points[1..] {
+                layers.cut.push(Operation::new(
+                    "l",
+                    vec![mm_to_pt(p.x).into(), pdf_y(p.y).into()],
                 ));
+            }
 
-                for p in &contour_points[1..] {
-                    ops.push(Operation::new(
-                        "l",
-                        vec![mm_to_pt(p.x).into(), pdf_y(p.y).into()],
-                    ));
-                }
+            layers.cut.push(Operation::new("s", vec![])); // Close and stroke
+        }
+    }
 
-                ops.push(Operation::new("s", vec![])); // Close and stroke
-            }
+    // Draw the registration marks, filled in black
+    let marks = options.registration_mark_rects();
+    if !marks.is_empty() {
+        layers.furniture.push(Operation::new(
+            "rg",
+            vec![0.0.into(), 0.0.into(), 0.0.into()],
+        ));
+        for (a, b) in marks {
+            layers.furniture.push(Operation::new(
+                "re",
+                vec![
+                    mm_to_pt(a.x).into(),
+                    pdf_y(b.y).into(),
+                    mm_to_pt(b.x - a.x).into(),
+                    mm_to_pt(b.y - a.y).into(),
+                ],
+            ));
+            layers.furniture.push(Operation::new("f", vec![]));
         }
     }
 
-    // Draw text
-    let texts = collect_texts(papercraft, options, page);
-    if !texts.is_empty() {
+    // Draw text, the labels of the islands apart from the rest
+    let mut text_ops = |texts: Vec<PrintableText>, ops: &mut Vec<Operation>| {
+        if texts.is_empty() {
+            return;
+        }
         ops.push(Operation::new("BT", Vec::new()));
 
         for text in texts {
             let size = text.size * 72.0 / 25.4 / 1.1;
             ops.push(Operation::new("Tf", vec!["F1".into(), size.into()]));
 
-            // Heuristic alignment shift
-            let mut x = mm_to_pt(text.pos.x);
-            match text.align {
-                TextAlign::Center => {
-                    let approx_width = (text.text.len() as f32) * size * 0.5;
-                    x -= approx_width / 2.0;
-                }
-                TextAlign::Far => {
-                    let approx_width = (text.text.len() as f32) * size * 0.5;
-                    x -= approx_width;
-                }
-                TextAlign::Near => {}
-            }
+            let (string, width) = font.encode(&text.text);
+            let width = width * size / 1000.0;
+            let shift = match text.align {
+                TextAlign::Center => width / 2.0,
+                TextAlign::Far => width,
+                TextAlign::Near => 0.0,
+            };
 
-            let y = pdf_y(text.pos.y);
+            // The angle is clockwise on paper, that is counter-clockwise in PDF coordinates
+            let (sin, cos) = text.angle.0.sin_cos();
+            let x = mm_to_pt(text.pos.x) - shift * cos;
+            let y = pdf_y(text.pos.y) + shift * sin;
 
             ops.push(Operation::new(
                 "Tm",
                 vec![
-                    1.0.into(),
-                    0.0.into(),
-                    0.0.into(),
-                    1.0.into(),
+                    cos.into(),
+                    (-sin).into(),
+                    sin.into(),
+                    cos.into(),
                     x.into(),
                     y.into(),
                 ],
             ));
 
-            ops.push(Operation::new(
-                "Tj",
-                vec![Object::String(
-                    text.text.into_bytes(),
-                    StringFormat::Literal,
-                )],
-            ));
+            ops.push(Operation::new("Tj", vec![string]));
         }
 
         ops.push(Operation::new("ET", Vec::new()));
-    }
+    };
+    text_ops(collect_page_texts(options, page), &mut layers.furniture);
+    text_ops(
+        collect_label_texts(papercraft, options, page),
+        &mut layers.edge_ids,
+    );
 
-    Ok(ops)
+    Ok(layers)
 }
@@ -3,7 +3,7 @@ import { Button, FileTrigger, ToggleButton } from 'react-aria-components';
 import {
   Upload, Scissors, Link2, Move, RotateCw, Settings,
   ZoomIn, ZoomOut, Maximize2, Box, Origami,
-  MousePointer2, Hand, Undo2, Redo2, HelpCircle, LayoutGrid, Save, FolderOpen, Download, Image as ImageIcon
+  MousePointer2, Hand, Undo2, Redo2, HelpCircle, LayoutGrid, Lock, Wand2, Save, FolderOpen, Download, Image as ImageIcon
 } from 'lucide-react';
 import * as api from './api/client';
 import Preview3D from './Preview3D';
@@ -294,6 +294,13 @@ function Toolbar({
         >
           <span style={{ fontSize: '10px', fontWeight: 'bold' }}>SVG</span>
         </button>
+        <button
+          className="toolbar-btn"
+          onClick={() => onExport('dxf')}
+          title="Export DXF"
+        >
+          <span style={{ fontSize: '10px', fontWeight: 'bold' }}>DXF</span>
+        </button>
       </div>
       <div className="toolbar-group">
         <button
@@ -311,6 +318,22 @@ function Toolbar({
         >
           <LayoutGrid size={18} />
         </button>
+        <button
+          className="toolbar-btn"
+          onClick={() => onAction('lock', {})}
+          title="Lock/Unlock Selected Islands (locked islands are not moved when packing)"
+          data-testid="lock-islands-btn"
+        >
+          <Lock size={18} />
+        </button>
+        <button
+          className="toolbar-btn"
+          onClick={() => onAction('unfold', {})}
+          title="Auto-Unfold (replaces all cuts)"
+          data-testid="auto-unfold-btn"
+        >
+          <Wand2 size={18} />
+        </button>
       </div>
     </div>
   );
@@ -371,7 +394,7 @@ function Canvas2D({ project, mode, viewOptions, selectedIslands, onSelectIsland,
         }
         const img = new Image();
         img.crossOrigin = "anonymous";
-        img.src = `http://localhost:3000/api/texture/${i}`;
+        img.src = api.textureUrl(i);
         img.onload = () => setRedrawKey(k => k + 1);
         img.onerror = () => console.error(`Failed to load texture ${i}`);
         return img;
@@ -1102,6 +1125,25 @@ function Canvas2D({ project, mode, viewOptions, selectedIslands, onSelectIsland,
         });
       }
 
+      // Draw edge ids and the island name, as printed
+      const fontSize = (options?.edge_id_font_size ?? 8) * 25.4 / 72; // pt to mm
+      ctx.font = `${fontSize}px sans-serif`;
+      ctx.textAlign = 'center';
+      ctx.fillStyle = '#000000';
+      island.edges?.forEach(edge => {
+        if (!edge.label) return;
+        const p = getPoint(edge.label.pos);
+        ctx.save();
+        ctx.translate(p.x, p.y);
+        ctx.rotate(edge.label.angle);
+        ctx.fillText(edge.label.text, 0, 0);
+        ctx.restore();
+      });
+      if (island.name && island.name_pos) {
+        const p = getPoint(island.name_pos);
+        ctx.fillText(island.name, p.x, p.y);
+      }
+
       // Draw Island Marker (Center)
       const markerSize = (isSelected ? 6 : 4) / scale;
       ctx.beginPath();
@@ -1219,6 +1261,9 @@ export default function App() {
   const [status, setStatus] = useState({ connected: false, hasModel: false });
   // @ts-ignore - useHistory is generic but inferred usage is complex, suppressing for speed
   const [project, setProject, undo, redo, canUndo, canRedo, resetProject] = useHistory<Project | null>(null);
+  // The latest project, for the callbacks that apply a delta to it
+  const projectRef = useRef(project);
+  projectRef.current = project;
   const [mode, setMode] = useState('select');
   const [selectedIslands, setSelectedIslands] = useState<number[]>([]);
   const [viewOptions, setViewOptions] = useState({
@@ -1249,11 +1294,18 @@ export default function App() {
       setStatus({ connected: true, hasModel: true });
       setSelectedIslands([]);
       setMode('select');
+      const missing = [...(projectData.missing_files ?? []), ...(projectData.missing_textures ?? [])];
+      if (missing.length) {
+        setError('Files not found, upload them along with the model: ' + missing.join(', '));
+      }
     } catch (err: any) {
       console.error("Upload failed details:", err);
       // Detailed error if possible
       let msg = err.message;
-      if (err.responseText) {
+      // An ApiError message already has the causes sent by the server
+      if (err instanceof api.ApiError) {
+        msg = err.message;
+      } else if (err.responseText) {
         msg += " - " + err.responseText;
       } else if (err.response) {
         try {
@@ -1409,6 +1461,17 @@ export default function App() {
       let action;
       if (type === 'pack') {
         action = api.actions.packIslands();
+      } else if (type === 'unfold') {
+        action = api.actions.autoUnfold();
+      } else if (type === 'lock') {
+        const selected = (project?.islands ?? []).filter(island => selectedIslands.includes(island.id.idx));
+        if (selected.length === 0) return;
+        // Lock them all, unless they are all already locked
+        const locked = !selected.every(island => island.locked);
+        const updatedProject = await api.performActions(
+          selected.map(island => api.actions.lockIsland(island.id, locked)));
+        setProject(updatedProject);
+        return;
       } else {
         return;
       }
@@ -1419,6 +1482,24 @@ export default function App() {
     }
   };
 
+  // Other browsers may be editing the same project, follow their changes
+  useEffect(() => {
+    if (!status.hasModel) return;
+    return api.openLive({
+      onState: (revision, projectData) => {
+        if (projectData) setProject({ ...projectData, revision }, true);
+      },
+      onEdit: (revision, delta) => {
+        const current = projectRef.current;
+        if (!current || (current.revision ?? 0) >= revision) return;
+        setProject({ ...api.applyDelta(current, delta), revision }, true);
+      },
+      onReload: async () => {
+        resetProject(await api.getProject());
+      },
+    });
+  }, [status.hasModel]);
+
   // Check backend status on mount
   useEffect(() => {
     const checkStatus = async () => {
@@ -1460,12 +1541,12 @@ export default function App() {
   const handleMoveIsland = useCallback(async (islandId: IslandId, delta: [number, number]) => {
     try {
       // islandId is the full key object {idx, version}
-      const result = await api.performAction({
+      const result = await api.performActionDelta({
         type: 'moveIsland',
         island: islandId,
         delta
       });
-      setProject(result);
+      if (projectRef.current) setProject(api.applyDelta(projectRef.current, result));
     } catch (err: any) {
       setError('Failed to move island: ' + err.message);
       // Revert optimization? Complex without dedicated revert
@@ -1475,13 +1556,13 @@ export default function App() {
   // Handle island rotate
   const handleRotateIsland = useCallback(async (islandId: IslandId, angle: number, center: [number, number]) => {
     try {
-      const result = await api.performAction({
+      const result = await api.performActionDelta({
         type: 'rotateIsland',
         island: islandId,
         angle,
         center
       });
-      setProject(result);
+      if (projectRef.current) setProject(api.applyDelta(projectRef.current, result));
     } catch (err: any) {
       setError('Failed to rotate island: ' + err.message);
     }
@@ -1492,6 +1573,29 @@ export default function App() {
     setViewOptions(prev => ({ ...prev, [option]: value }));
   }, []);
 
+  // Undo and redo are done by the backend, the local history only tracks what is available
+  const handleUndo = useCallback(async () => {
+    try {
+      const restored = await api.undo();
+      if (!restored) return;
+      undo();
+      setProject(restored, true);
+    } catch (err: any) {
+      setError('Failed to undo: ' + err.message);
+    }
+  }, [undo, setProject]);
+
+  const handleRedo = useCallback(async () => {
+    try {
+      const restored = await api.redo();
+      if (!restored) return;
+      redo();
+      setProject(restored, true);
+    } catch (err: any) {
+      setError('Failed to redo: ' + err.message);
+    }
+  }, [redo, setProject]);
+
   // Keyboard shortcuts for mode switching
   useEffect(() => {
     const handleKeyDown = (e: KeyboardEvent) => {
@@ -1500,9 +1604,9 @@ export default function App() {
       if ((e.ctrlKey || e.metaKey) && e.key.toLowerCase() === 'z') {
         e.preventDefault();
         if (e.shiftKey) {
-          redo();
+          handleRedo();
         } else {
-          undo();
+          handleUndo();
         }
         return;
       }
@@ -1526,7 +1630,7 @@ export default function App() {
 
     window.addEventListener('keydown', handleKeyDown);
     return () => window.removeEventListener('keydown', handleKeyDown);
-  }, []);
+  }, [handleUndo, handleRedo]);
 
   // Handle options save
   const handleOptionsSave = async (newOptions: SettingsOptions) => {
@@ -1541,8 +1645,7 @@ export default function App() {
 
   // Handle export
   const handleExport = (format: string) => {
-    const textureParam = viewOptions.showTextures ? '&textures=true' : '&textures=false';
-    window.open(`http://localhost:3000/api/export?format=${format}${textureParam}`, '_blank');
+    window.open(api.exportUrl(format, viewOptions.showTextures), '_blank');
   };
 
   return (
@@ -1594,8 +1697,8 @@ export default function App() {
             onViewOptionChange={handleViewOptionChange}
             onOpenSettings={() => setIsSettingsOpen(true)}
             onExport={handleExport}
-            onUndo={undo}
-            onRedo={redo}
+            onUndo={handleUndo}
+            onRedo={handleRedo}
             onAction={performAction}
             onSave={handleSaveFile}
             onSaveAs={handleSaveAs}
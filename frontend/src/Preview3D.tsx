@@ -3,6 +3,7 @@ import { Canvas, useLoader } from '@react-three/fiber';
 import { OrbitControls, Center, Environment } from '@react-three/drei';
 import * as THREE from 'three';
 import { Project, Face } from './types';
+import * as api from './api/client';
 
 interface Preview3DProps {
     project: Project | null;
@@ -162,7 +163,7 @@ const Model: React.FC<{ project: Project; showTextures: boolean }> = ({ project,
         textures.forEach((tex, i) => {
             if (tex.has_data) {
                 indexMap.set(i, urls.length);
-                urls.push(`http://localhost:3000/api/texture/${i}`);
+                urls.push(api.textureUrl(i));
             }
         });
 
@@ -164,6 +164,67 @@ export default function SettingsDialog({ options, onSave, isOpen, onOpenChange }
                             </div>
                         </div>
 
+                        <div className="form-section">
+                            <h3>Packing</h3>
+                            <div className="form-row">
+                                <div className="form-group">
+                                    <Label>Gap (mm)</Label>
+                                    <Input
+                                        type="number"
+                                        value={(formData.pack_gap ?? 2).toString()}
+                                        onChange={e => handleChange('pack_gap', parseFloat(e.target.value))}
+                                    />
+                                </div>
+                                <div className="form-group">
+                                    <Label>Rotations</Label>
+                                    <Input
+                                        type="number"
+                                        value={(formData.pack_rotations ?? 4).toString()}
+                                        onChange={e => handleChange('pack_rotations', parseInt(e.target.value))}
+                                    />
+                                </div>
+                            </div>
+                            <div className="form-row">
+                                <div className="form-group">
+                                    <Label>Cut offset (mm)</Label>
+                                    <Input
+                                        type="number"
+                                        value={(formData.cut_offset ?? 0).toString()}
+                                        onChange={e => handleChange('cut_offset', parseFloat(e.target.value))}
+                                        title="Moves the cut lines outwards, or inwards if negative, to make up for the kerf"
+                                    />
+                                </div>
+                                <div className="form-group">
+                                    <Label>Bleed (mm)</Label>
+                                    <Input
+                                        type="number"
+                                        value={(formData.bleed ?? 0).toString()}
+                                        onChange={e => handleChange('bleed', parseFloat(e.target.value))}
+                                        title="How far the printed faces go past the cut lines"
+                                    />
+                                </div>
+                            </div>
+                            <div className="form-group">
+                                <Label>
+                                    <input
+                                        type="checkbox"
+                                        checked={formData.registration_marks ?? false}
+                                        onChange={e => handleChange('registration_marks', e.target.checked)}
+                                    /> Registration marks (print & cut)
+                                </Label>
+                            </div>
+                            <div className="form-group">
+                                <Label>PDF font</Label>
+                                <Input
+                                    type="text"
+                                    value={formData.pdf_font ?? ''}
+                                    onChange={e => handleChange('pdf_font', e.target.value.trim() || null)}
+                                    placeholder="Helvetica"
+                                    title="File name of a TrueType font installed in the server, for texts that Helvetica cannot write"
+                                />
+                            </div>
+                        </div>
+
                         <div className="modal-footer">
                             <Button type="button" onPress={() => onOpenChange(false)} className="btn btn-secondary">Cancel</Button>
                             <Button type="submit" className="btn btn-primary">Apply Changes</Button>
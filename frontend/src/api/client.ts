@@ -1,27 +1,137 @@
-import { Project, SettingsOptions, IslandId } from '../types';
+import { Project, ProjectDelta, SettingsOptions, IslandId, ValidationReport } from '../types';
 
 const API_BASE = '/api';
 
-export async function getStatus(): Promise<{ has_model: boolean }> {
+export interface ProjectInfo {
+    id: number;
+    name: string;
+    has_model: boolean;
+    can_undo: boolean;
+    can_redo: boolean;
+}
+
+// The body of every error response of the API
+export interface ApiErrorBody {
+    // Machine-readable, such as 'unsupported_format', 'corrupt_file' or 'no_model'
+    code: string;
+    message: string;
+    // The causes of the error, from the outermost to the root one
+    causes: string[];
+}
+
+export class ApiError extends Error {
+    constructor(public status: number, public body: ApiErrorBody | null, fallback: string) {
+        super(body ? [body.message, ...body.causes].join(': ') : `${fallback}: ${status}`);
+    }
+
+    get code(): string | null {
+        return this.body?.code ?? null;
+    }
+}
+
+function parseErrorBody(text: string): ApiErrorBody | null {
+    try {
+        const body = JSON.parse(text);
+        return typeof body?.code === 'string' ? body : null;
+    } catch {
+        return null;
+    }
+}
+
+async function apiError(response: Response, fallback: string): Promise<ApiError> {
+    return new ApiError(response.status, parseErrorBody(await response.text()), fallback);
+}
+
+// The project this client works on, every project route is relative to it
+let currentProjectId: number | null = null;
+
+function projectUrl(path = ''): string {
+    if (currentProjectId === null) throw new Error('No project opened');
+    return `${API_BASE}/projects/${currentProjectId}${path}`;
+}
+
+export async function getStatus(): Promise<{ has_model: boolean; projects: number }> {
     const response = await fetch(`${API_BASE}/status`);
-    if (!response.ok) throw new Error('Failed to get status');
+    if (!response.ok) throw await apiError(response, 'Failed to get status');
+    return response.json();
+}
+
+export async function listProjects(): Promise<ProjectInfo[]> {
+    const response = await fetch(`${API_BASE}/projects`);
+    if (!response.ok) throw await apiError(response, 'Failed to list projects');
     return response.json();
 }
 
-export async function uploadModel(file: File): Promise<Project> {
+export async function createProject(name?: string): Promise<ProjectInfo> {
+    const response = await fetch(`${API_BASE}/projects`, {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify({ name }),
+    });
+    if (!response.ok) throw await apiError(response, 'Failed to create project');
+    return response.json();
+}
+
+export async function deleteProject(id: number): Promise<void> {
+    const response = await fetch(`${API_BASE}/projects/${id}`, { method: 'DELETE' });
+    if (!response.ok) throw await apiError(response, 'Failed to delete project');
+    if (id === currentProjectId) currentProjectId = null;
+}
+
+export async function openProject(id: number): Promise<Project> {
+    currentProjectId = id;
+    return getProject();
+}
+
+// Picks a project to work on if there is none yet: the first one with a model, or a new one
+async function ensureProject(): Promise<number> {
+    if (currentProjectId === null) {
+        const projects = await listProjects();
+        const existing = projects.find(p => p.has_model) ?? projects[0];
+        currentProjectId = existing ? existing.id : (await createProject()).id;
+    }
+    return currentProjectId;
+}
+
+export function textureUrl(index: number): string {
+    return projectUrl(`/texture/${index}`);
+}
+
+export function exportUrl(format: string, textures: boolean): string {
+    return projectUrl(`/export?format=${format}&textures=${textures}`);
+}
+
+// The print-and-cut file of a page, only the outer contours of the islands
+export function cutFileUrl(format: 'svg' | 'dxf', page: number): string {
+    return projectUrl(`/export?format=${format}&page=${page}&cut_only=true`);
+}
+
+export function craftUrl(): string {
+    return projectUrl('/craft');
+}
+
+// The project of a new upload, with the textures that the model uses but were not uploaded
+export type UploadedProject = Project & { missing_textures: string[]; missing_files: string[] };
+
+// Several files are uploaded together, such as an OBJ with its MTL and textures, or a .zip
+export async function uploadModel(file: File | File[]): Promise<UploadedProject> {
     return uploadModelWithProgress(file, () => { });
 }
 
 export async function uploadModelWithProgress(
-    file: File,
+    file: File | File[],
     onProgress: (percent: number) => void
-): Promise<Project> {
+): Promise<UploadedProject> {
+    await ensureProject();
     return new Promise((resolve, reject) => {
         const formData = new FormData();
-        formData.append('file', file);
+        for (const f of Array.isArray(file) ? file : [file]) {
+            // Files picked from a folder keep their path, for models with textures in subfolders
+            formData.append('file', f, f.webkitRelativePath || f.name);
+        }
 
         const xhr = new XMLHttpRequest();
-        xhr.open('POST', `${API_BASE}/upload`, true);
+        xhr.open('POST', projectUrl('/upload'), true);
 
         xhr.upload.onprogress = (e) => {
             if (e.lengthComputable) {
@@ -40,10 +150,7 @@ export async function uploadModelWithProgress(
                     reject(error);
                 }
             } else {
-                const error = new Error(`Upload failed: ${xhr.status} ${xhr.statusText}`) as any;
-                error.status = xhr.status;
-                error.responseText = xhr.responseText;
-                reject(error);
+                reject(new ApiError(xhr.status, parseErrorBody(xhr.responseText), 'Upload failed'));
             }
         };
 
@@ -55,9 +162,40 @@ export async function uploadModelWithProgress(
     });
 }
 
+// What could not be carried over from the old model, see updateModel
+export interface UpdateReport {
+    unmatched_edges: number[];
+    unmatched_islands: IslandId[];
+}
+
+export type UpdatedProject = UploadedProject & { report: UpdateReport };
+
+// Replaces the model of the project with a new version, keeping its cuts and layout
+export async function updateModel(file: File | File[]): Promise<UpdatedProject> {
+    await ensureProject();
+    const formData = new FormData();
+    for (const f of Array.isArray(file) ? file : [file]) {
+        formData.append('file', f, f.webkitRelativePath || f.name);
+    }
+    const response = await fetch(projectUrl('/update'), {
+        method: 'POST',
+        body: formData,
+    });
+    if (!response.ok) throw await apiError(response, 'Failed to update model');
+    return response.json();
+}
+
 export async function getProject(): Promise<Project> {
-    const response = await fetch(`${API_BASE}/project`);
-    if (!response.ok) throw new Error('Failed to get project');
+    await ensureProject();
+    const response = await fetch(projectUrl());
+    if (!response.ok) throw await apiError(response, 'Failed to get project');
+    return response.json();
+}
+
+export async function validate(): Promise<ValidationReport> {
+    await ensureProject();
+    const response = await fetch(projectUrl('/validate'));
+    if (!response.ok) throw await apiError(response, 'Failed to validate project');
     return response.json();
 }
 
@@ -67,20 +205,164 @@ interface Action {
 }
 
 export async function performAction(action: Action): Promise<Project> {
-    const response = await fetch(`${API_BASE}/action`, {
+    const response = await fetch(projectUrl('/action'), {
         method: 'POST',
         headers: { 'Content-Type': 'application/json' },
         body: JSON.stringify(action),
     });
 
-    if (!response.ok) {
-        const error = new Error('Failed to perform action') as any;
-        error.response = response;
-        throw error;
-    }
+    if (!response.ok) throw await apiError(response, 'Failed to perform action');
+    return response.json();
+}
+
+// Like performAction, but the answer has only what changed, see applyDelta
+export async function performActionDelta(action: Action): Promise<ProjectDelta> {
+    const response = await fetch(projectUrl('/action?delta=true'), {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify(action),
+    });
+
+    if (!response.ok) throw await apiError(response, 'Failed to perform action');
+    return response.json();
+}
+
+function sameIsland(a: IslandId, b: IslandId): boolean {
+    return a.idx === b.idx && a.version === b.version;
+}
+
+// The project after an action, from the project before it and the delta of the action.
+// Applying the same delta twice is harmless, it may come both as an answer and as a live update.
+export function applyDelta(project: Project, delta: ProjectDelta): Project {
+    const changed = new Map(delta.changed.map(island => [`${island.id.idx}v${island.id.version}`, island]));
+    const islands = (project.islands ?? [])
+        .filter(island => !delta.removed.some(id => sameIsland(id, island.id)))
+        .filter(island => !delta.added.some(added => sameIsland(added.id, island.id)))
+        .map(island => changed.get(`${island.id.idx}v${island.id.version}`) ?? island);
+    return {
+        ...project,
+        model_version: delta.model_version,
+        revision: delta.revision ?? project.revision,
+        islands: [...islands, ...delta.added],
+        options: delta.options ?? project.options,
+    };
+}
+
+// Applies all the actions or none of them, undone as a single step
+export async function performActions(actions: Action[]): Promise<Project> {
+    const response = await fetch(projectUrl('/actions'), {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify(actions),
+    });
+
+    if (!response.ok) throw await apiError(response, 'Failed to perform actions');
+    return response.json();
+}
+
+// Returns null if there is nothing to undo
+export async function undo(): Promise<Project | null> {
+    const response = await fetch(projectUrl('/undo'), { method: 'POST' });
+    if (response.status === 409) return null;
+    if (!response.ok) throw await apiError(response, 'Failed to undo');
+    return response.json();
+}
+
+// Returns null if there is nothing to redo
+export async function redo(): Promise<Project | null> {
+    const response = await fetch(projectUrl('/redo'), { method: 'POST' });
+    if (response.status === 409) return null;
+    if (!response.ok) throw await apiError(response, 'Failed to redo');
+    return response.json();
+}
+
+export interface JobStatus {
+    id: number;
+    project: number;
+    kind: 'import' | 'export';
+    state: 'running' | 'done' | 'failed' | 'cancelled';
+    error?: ApiErrorBody;
+    stage: string;
+    progress: number;
+}
+
+export async function startImportJob(file: File): Promise<JobStatus> {
+    await ensureProject();
+    const formData = new FormData();
+    formData.append('file', file);
+    const response = await fetch(projectUrl('/jobs/import'), { method: 'POST', body: formData });
+    if (!response.ok) throw await apiError(response, 'Failed to start import');
+    return response.json();
+}
+
+export async function startExportJob(format: string, textures: boolean): Promise<JobStatus> {
+    const response = await fetch(projectUrl(`/jobs/export?format=${format}&textures=${textures}`), {
+        method: 'POST',
+    });
+    if (!response.ok) throw await apiError(response, 'Failed to start export');
+    return response.json();
+}
+
+export async function getJob(id: number): Promise<JobStatus> {
+    const response = await fetch(`${API_BASE}/jobs/${id}`);
+    if (!response.ok) throw await apiError(response, 'Failed to get job');
     return response.json();
 }
 
+export async function cancelJob(id: number): Promise<void> {
+    const response = await fetch(`${API_BASE}/jobs/${id}`, { method: 'DELETE' });
+    if (!response.ok) throw await apiError(response, 'Failed to cancel job');
+}
+
+// Calls `onStatus` every time the job changes, resolves with the final status
+export function watchJob(id: number, onStatus: (status: JobStatus) => void): Promise<JobStatus> {
+    return new Promise((resolve, reject) => {
+        const events = new EventSource(`${API_BASE}/jobs/${id}/events`);
+        events.addEventListener('status', (e) => {
+            const status: JobStatus = JSON.parse((e as MessageEvent).data);
+            onStatus(status);
+            if (status.state !== 'running') {
+                events.close();
+                resolve(status);
+            }
+        });
+        events.onerror = () => {
+            events.close();
+            reject(new Error('Lost the events of the job'));
+        };
+    });
+}
+
+export function jobResultUrl(id: number): string {
+    return `${API_BASE}/jobs/${id}/result`;
+}
+
+export interface LiveHandlers {
+    // The whole project, when connecting or after missing some updates
+    onState: (revision: number, project: Project | null) => void;
+    // A change made by any client, this one included
+    onEdit: (revision: number, delta: ProjectDelta) => void;
+    // The model was replaced, it has to be loaded again
+    onReload: (revision: number) => void;
+}
+
+// Follows the changes of the current project, made by this or other browsers.
+// Returns the function that stops following them.
+export function openLive(handlers: LiveHandlers): () => void {
+    const protocol = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
+    const socket = new WebSocket(`${protocol}//${window.location.host}${projectUrl('/live')}`);
+    socket.onmessage = (e) => {
+        const msg = JSON.parse(e.data);
+        switch (msg.type) {
+            case 'state': handlers.onState(msg.revision, msg.project); break;
+            case 'edit': handlers.onEdit(msg.revision, msg.delta); break;
+            case 'reload': handlers.onReload(msg.revision); break;
+            case 'rejected': console.warn('Live edit rejected:', msg.error); break;
+        }
+    };
+    return () => socket.close();
+}
+
 // Action helpers
 export const actions = {
     toggleFlap: (edge: any, action = 'Toggle'): Action => ({
@@ -101,6 +383,11 @@ export const actions = {
         priority_face: priorityFace,
     }),
 
+    joinStrip: (edge: any): Action => ({
+        type: 'joinStrip',
+        edge,
+    }),
+
     moveIsland: (island: IslandId, delta: [number, number]): Action => ({
         type: 'moveIsland',
         island,
@@ -114,12 +401,38 @@ export const actions = {
         center,
     }),
 
+    autoRotateIsland: (island: IslandId): Action => ({
+        type: 'autoRotateIsland',
+        island,
+    }),
+
+    resetIslandRotation: (island: IslandId): Action => ({
+        type: 'resetIslandRotation',
+        island,
+    }),
+
+    setIslandRoot: (island: IslandId, face: number): Action => ({
+        type: 'setIslandRoot',
+        island,
+        face,
+    }),
+
+    lockIsland: (island: IslandId, locked: boolean): Action => ({
+        type: 'lockIsland',
+        island,
+        locked,
+    }),
+
     setOptions: (options: SettingsOptions, relocatePieces = false): Action => ({
         type: 'setOptions',
         options,
         relocate_pieces: relocatePieces,
     }),
     packIslands: (): Action => ({
-        type: 'pack',
+        type: 'packIslands',
+    }),
+
+    autoUnfold: (): Action => ({
+        type: 'autoUnfold',
     }),
 };
@@ -18,10 +18,22 @@ export interface Edge {
     start: PointOrArray;
     end: PointOrArray;
     kind: string;
+    label?: EdgeLabel | null; // Only for cut edges with an edge id
+}
+
+export interface EdgeLabel {
+    id: number;
+    text: string; // Partner island name and edge id, "B:12"
+    partner_island: IslandId;
+    partner_face: number;
+    pos: PointOrArray; // Center of the text baseline
+    angle: number;
 }
 
 export interface Flap {
-    vertices: PointOrArray[];
+    id?: number; // Face the flap is attached to
+    edge?: number;
+    vertices: PointOrArray[]; // 3 for triangular flaps, 4 otherwise
 }
 
 export interface IslandId {
@@ -29,9 +41,18 @@ export interface IslandId {
     version?: number;
 }
 
+// An island removed by a join, and the island it went into
+export interface IslandRename {
+    from: IslandId;
+    to: IslandId;
+}
+
 export interface Island {
     id: IslandId;
     pos: PointOrArray;
+    locked?: boolean;
+    name?: string;
+    name_pos?: PointOrArray | null; // null if island names are not printed
     edges?: Edge[];
     faces?: Face[];
     flaps?: Flap[];
@@ -57,15 +78,60 @@ export interface SettingsOptions {
     tab_width?: number;
     tab_angle?: number;
     tab_style?: 'Textured' | 'HalfTextured' | 'White' | 'None';
+    pack_gap?: number;
+    pack_rotations?: number;
+    registration_marks?: boolean; // Print-and-cut marks on every page
+    cut_offset?: number; // mm, positive outwards
+    bleed?: number; // mm
+    pdf_font?: string | null; // TrueType font file name, Helvetica if null
+    edge_id_font_size?: number;
     [key: string]: any;
 }
 
 export interface Project {
     model: ModelData | null;
+    model_version?: string; // Changes only if the model does
+    revision?: number; // Incremented by the server with every change
+    renames?: IslandRename[]; // Only in the answers to the edits that joined islands
     islands?: Island[];
     options?: SettingsOptions;
 }
 
+// What an action changed, the rest of the project is as it was
+export interface ProjectDelta {
+    revision?: number; // In the answers to the actions, the live updates have their own
+    renames?: IslandRename[];
+    model_version: string;
+    added: Island[];
+    changed: Island[];
+    removed: IslandId[];
+    edges: { id: number; status: number }[]; // 0 hidden, 1 joined, 2-4 cut
+    options: SettingsOptions | null;
+}
+
+export interface FaceOverlap {
+    island_a: IslandId;
+    face_a: number;
+    island_b: IslandId;
+    face_b: number;
+}
+
+export interface FlapCollision {
+    island: IslandId;
+    edge: number;
+    other_island: IslandId;
+    other_face: number | null;
+    other_edge: number | null;
+}
+
+export interface ValidationReport {
+    face_overlaps: FaceOverlap[];
+    flap_collisions: FlapCollision[];
+    outside_margin: IslandId[];
+    straddling_pages: { island: IslandId; pages: number[] }[];
+    in_registration_zone: IslandId[];
+}
+
 export interface Status {
     connected: boolean;
     has_model: boolean; // Note: API returns snake_case 'has_model'
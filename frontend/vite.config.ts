@@ -9,6 +9,7 @@ export default defineConfig({
       '/api': {
         target: 'http://localhost:3000',
         changeOrigin: true,
+        ws: true, // The live updates of the projects
         timeout: 300000, // 5 minutes
         proxyTimeout: 300000, // 5 minutes
       }
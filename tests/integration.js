@@ -46,20 +46,41 @@ async function runTests() {
         assert(typeof data.has_model === 'boolean', 'has_model should be boolean');
     });
 
-    await test('GET /api/project returns 404 when no model loaded', async () => {
-        const response = await fetch(`${BACKEND_URL}/api/project`);
-        // May be 200 if a model was previously loaded, or 404 if not
-        assert(response.status === 200 || response.status === 404,
-            'Should return 200 or 404');
+    let projectUrl = `${BACKEND_URL}/api/projects/0`;
+
+    await test('POST /api/projects creates an empty project', async () => {
+        const response = await fetch(`${BACKEND_URL}/api/projects`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ name: 'integration' }),
+        });
+        assert(response.ok, 'Response should be OK');
+        const data = await response.json();
+        assert(typeof data.id === 'number', 'Should return the project id');
+        assert(data.has_model === false, 'New project should have no model');
+        projectUrl = `${BACKEND_URL}/api/projects/${data.id}`;
+    });
+
+    await test('GET /api/projects lists the new project', async () => {
+        const response = await fetch(`${BACKEND_URL}/api/projects`);
+        assert(response.ok, 'Response should be OK');
+        const data = await response.json();
+        assert(data.some(p => `${BACKEND_URL}/api/projects/${p.id}` === projectUrl),
+            'Should include the created project');
     });
 
-    await test('POST /api/upload accepts OBJ file', async () => {
+    await test('GET /api/projects/:id returns 404 when no model loaded', async () => {
+        const response = await fetch(projectUrl);
+        assert(response.status === 404, 'Should return 404');
+    });
+
+    await test('POST /api/projects/:id/upload accepts OBJ file', async () => {
         const objContent = 'v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n';
         const blob = new Blob([objContent], { type: 'text/plain' });
         const formData = new FormData();
         formData.append('file', blob, 'test.obj');
 
-        const response = await fetch(`${BACKEND_URL}/api/upload`, {
+        const response = await fetch(`${projectUrl}/upload`, {
             method: 'POST',
             body: formData,
         });
@@ -75,8 +96,8 @@ async function runTests() {
         assert(data.has_model === true, 'has_model should be true after upload');
     });
 
-    await test('GET /api/project returns model data', async () => {
-        const response = await fetch(`${BACKEND_URL}/api/project`);
+    await test('GET /api/projects/:id returns model data', async () => {
+        const response = await fetch(projectUrl);
         assert(response.ok, 'Response should be OK');
         const data = await response.json();
         assert(data.model, 'Should have model property');
@@ -84,9 +105,9 @@ async function runTests() {
         assert(data.islands, 'Should have islands property');
     });
 
-    await test('POST /api/action performs moveIsland', async () => {
+    await test('POST /api/projects/:id/action performs moveIsland', async () => {
         // First get current project to find island key
-        const projectResponse = await fetch(`${BACKEND_URL}/api/project`);
+        const projectResponse = await fetch(projectUrl);
         const project = await projectResponse.json();
 
         // Find a valid island
@@ -97,7 +118,7 @@ async function runTests() {
             return;
         }
 
-        const response = await fetch(`${BACKEND_URL}/api/action`, {
+        const response = await fetch(`${projectUrl}/action`, {
             method: 'POST',
             headers: { 'Content-Type': 'application/json' },
             body: JSON.stringify({
@@ -112,6 +133,16 @@ async function runTests() {
         assert(data.islands, 'Response should include updated islands');
     });
 
+    await test('GET /api/projects/:id/validate returns a report', async () => {
+        const response = await fetch(`${projectUrl}/validate`);
+        assert(response.ok, 'Response should be OK');
+        const data = await response.json();
+        assert(Array.isArray(data.face_overlaps), 'Should list face overlaps');
+        assert(Array.isArray(data.flap_collisions), 'Should list flap collisions');
+        assert(Array.isArray(data.outside_margin), 'Should list islands outside the margin');
+        assert(Array.isArray(data.straddling_pages), 'Should list islands in several pages');
+    });
+
     // Frontend Tests
     console.log('\n🖥️  Frontend Tests\n');
 
@@ -138,15 +169,15 @@ async function runTests() {
     // Export Tests
     console.log('\n📤 Export Tests\n');
 
-    await test('GET /api/export?format=svg returns SVG', async () => {
+    await test('GET /api/projects/:id/export?format=svg returns SVG', async () => {
         // Ensure a model is loaded first
         const objContent = 'v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\nf 1 2 3\nf 2 4 3\n';
         const blob = new Blob([objContent], { type: 'text/plain' });
         const formData = new FormData();
         formData.append('file', blob, 'test.obj');
-        await fetch(`${BACKEND_URL}/api/upload`, { method: 'POST', body: formData });
+        await fetch(`${projectUrl}/upload`, { method: 'POST', body: formData });
 
-        const response = await fetch(`${BACKEND_URL}/api/export?format=svg`);
+        const response = await fetch(`${projectUrl}/export?format=svg`);
         assert(response.ok, 'SVG export should succeed');
         const contentType = response.headers.get('content-type');
         assert(contentType && contentType.includes('svg'), 'Should return SVG content type');
@@ -155,15 +186,15 @@ async function runTests() {
         assert(svg.includes('</svg>'), 'Should contain SVG closing tag');
     });
 
-    await test('GET /api/export?format=svg&page=0 returns single page SVG', async () => {
-        const response = await fetch(`${BACKEND_URL}/api/export?format=svg&page=0`);
+    await test('GET /api/projects/:id/export?format=svg&page=0 returns single page SVG', async () => {
+        const response = await fetch(`${projectUrl}/export?format=svg&page=0`);
         assert(response.ok, 'Single page SVG export should succeed');
         const svg = await response.text();
         assert(svg.includes('<svg'), 'Should contain SVG tag');
     });
 
-    await test('GET /api/export?format=pdf returns PDF', async () => {
-        const response = await fetch(`${BACKEND_URL}/api/export?format=pdf`);
+    await test('GET /api/projects/:id/export?format=pdf returns PDF', async () => {
+        const response = await fetch(`${projectUrl}/export?format=pdf`);
         assert(response.ok, 'PDF export should succeed');
         const contentType = response.headers.get('content-type');
         assert(contentType && contentType.includes('pdf'), 'Should return PDF content type');
@@ -174,16 +205,31 @@ async function runTests() {
             'Should start with PDF magic bytes');
     });
 
-    await test('GET /api/export without format returns 400', async () => {
-        const response = await fetch(`${BACKEND_URL}/api/export`);
+    await test('GET /api/projects/:id/export without format returns 400', async () => {
+        const response = await fetch(`${projectUrl}/export`);
         assert(response.status === 400, 'Should return 400 for missing format');
     });
 
-    await test('GET /api/export with invalid format returns 400', async () => {
-        const response = await fetch(`${BACKEND_URL}/api/export?format=invalid`);
+    await test('GET /api/projects/:id/export with invalid format returns 400', async () => {
+        const response = await fetch(`${projectUrl}/export?format=invalid`);
         assert(response.status === 400, 'Should return 400 for invalid format');
     });
 
+    await test('GET /api/projects/:id/craft returns a .craft file', async () => {
+        const response = await fetch(`${projectUrl}/craft`);
+        assert(response.ok, 'Craft download should succeed');
+        const bytes = new Uint8Array(await response.arrayBuffer());
+        // A .craft file is a zip archive
+        assert(bytes[0] === 0x50 && bytes[1] === 0x4b, 'Should start with zip magic bytes');
+    });
+
+    await test('DELETE /api/projects/:id removes the project', async () => {
+        const response = await fetch(projectUrl, { method: 'DELETE' });
+        assert(response.status === 204, 'Should return 204');
+        const again = await fetch(projectUrl);
+        assert(again.status === 404, 'Deleted project should return 404');
+    });
+
     // Summary
     console.log('\n' + '='.repeat(50));
     console.log(`\n📊 Results: ${passed} passed, ${failed} failed\n`);